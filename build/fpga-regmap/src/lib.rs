@@ -23,6 +23,11 @@ enum Node {
         inst_name: String,
         lsb: usize,
         msb: usize,
+        #[serde(default)]
+        reset: Option<usize>,
+        sw_access: Option<String>,
+        #[serde(default)]
+        encode: Option<Vec<EncodedValue>>,
     },
     Mem {
         inst_name: String,
@@ -30,6 +35,13 @@ enum Node {
     },
 }
 
+/// One value of an enumerated field (a SystemRDL `encode`)
+#[derive(Debug, Deserialize)]
+struct EncodedValue {
+    name: String,
+    value: usize,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 fn recurse_addr_map(
@@ -121,6 +133,7 @@ fn write_reg_fields(children: &[Node], prefix: &str, output: &mut String) {
             inst_name,
             lsb,
             msb,
+            ..
         } = child
         {
             let nbits = *msb - *lsb + 1;
@@ -139,7 +152,251 @@ fn write_reg_fields(children: &[Node], prefix: &str, output: &mut String) {
     }
 }
 
-fn write_node(node: &Node, prefix: &str, output: &mut String) {
+/// Returns the name of the enum type for an encoded field
+fn field_enum(inst_name: &str) -> String {
+    inst_name
+        .split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut c = w.chars();
+            let first = c.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first)
+                .chain(c.map(|c| c.to_ascii_lowercase()))
+                .collect::<String>()
+        })
+        .collect::<String>()
+        + "Encoded"
+}
+
+/// Writes a typed `Value` struct for a register, with an accessor for each
+/// field that software may read, and setters for each field that software
+/// may write.
+fn write_reg_value(
+    reg_name: &str,
+    addr: &str,
+    depth: usize,
+    children: &[Node],
+    prefix: &str,
+    output: &mut String,
+) {
+    let mut reset = 0;
+    let mut methods = String::new();
+    let mut fields = String::new();
+    let mut readable = false;
+    let mut writable = false;
+
+    for child in children {
+        let (inst_name, lsb, msb, field_reset, sw_access, encode) =
+            if let Node::Field {
+                inst_name,
+                lsb,
+                msb,
+                reset,
+                sw_access,
+                encode,
+            } = child
+            {
+                (inst_name, lsb, msb, reset, sw_access, encode)
+            } else {
+                panic!("unexpected non-Field: {child:?}");
+            };
+
+        let (read, write, access) = match sw_access.as_deref() {
+            Some("r") => (true, false, "Read"),
+            Some("w") => (false, true, "Write"),
+            Some("rw") | None => (true, true, "ReadWrite"),
+            Some(a) => panic!("{reg_name}.{inst_name}: bad sw_access {a:?}"),
+        };
+        readable |= read;
+        writable |= write;
+        reset |= (field_reset.unwrap_or(0) << lsb) & 0xff;
+
+        writeln!(
+            fields,
+            "{prefix}                fpga_regmap::Field {{ \
+                name: {inst_name:?}, \
+                mask: {inst_name}, \
+                access: fpga_regmap::Access::{access} }},"
+        )
+        .unwrap();
+
+        let ident = inst_name.to_lowercase();
+        let nbits = *msb - *lsb + 1;
+
+        // Pick the type exposed for this field, along with conversions to
+        // and from its raw (shifted-down) value.
+        let raw = if *lsb == 0 {
+            format!("self.0 & {inst_name}")
+        } else {
+            format!("(self.0 & {inst_name}) >> {lsb}")
+        };
+        let (ty, get, to_raw) = if let Some(values) = encode {
+            let ty = field_enum(inst_name);
+            writeln!(
+                output,
+                "\
+{prefix}        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+{prefix}        #[allow(non_camel_case_types)]
+{prefix}        #[repr(u8)]
+{prefix}        pub enum {ty} {{"
+            )
+            .unwrap();
+            for v in values {
+                writeln!(
+                    output,
+                    "{prefix}            {} = {},",
+                    v.name, v.value
+                )
+                .unwrap();
+            }
+            writeln!(
+                output,
+                "\
+{prefix}        }}
+
+{prefix}        impl {ty} {{
+{prefix}            pub fn from_raw(v: u8) -> Option<Self> {{
+{prefix}                match v {{"
+            )
+            .unwrap();
+            for v in values {
+                writeln!(
+                    output,
+                    "{prefix}                    {} => Some(Self::{}),",
+                    v.value, v.name
+                )
+                .unwrap();
+            }
+            writeln!(
+                output,
+                "\
+{prefix}                    _ => None,
+{prefix}                }}
+{prefix}            }}
+{prefix}        }}
+"
+            )
+            .unwrap();
+            (
+                format!("Option<{ty}>"),
+                format!("{ty}::from_raw({raw})"),
+                "v as u8",
+            )
+        } else if nbits == 1 {
+            (
+                "bool".to_string(),
+                format!("self.0 & {inst_name} != 0"),
+                "v as u8",
+            )
+        } else {
+            ("u8".to_string(), raw, "v")
+        };
+        let set_ty = if encode.is_some() {
+            field_enum(inst_name)
+        } else {
+            ty.clone()
+        };
+
+        if read {
+            writeln!(
+                methods,
+                "
+{prefix}            #[inline]
+{prefix}            pub fn {ident}(&self) -> {ty} {{
+{prefix}                {get}
+{prefix}            }}"
+            )
+            .unwrap();
+        }
+        if write {
+            let set = if nbits == 8 {
+                format!("self.0 = {to_raw};")
+            } else if *lsb == 0 {
+                format!("self.0 = (self.0 & !{inst_name}) | ({to_raw} & {inst_name});")
+            } else {
+                format!(
+                    "self.0 = (self.0 & !{inst_name}) | \
+                        (({to_raw}) << {lsb} & {inst_name});"
+                )
+            };
+            writeln!(
+                methods,
+                "
+{prefix}            #[inline]
+{prefix}            pub fn set_{ident}(&mut self, v: {set_ty}) {{
+{prefix}                {set}
+{prefix}            }}
+
+{prefix}            #[inline]
+{prefix}            pub fn with_{ident}(mut self, v: {set_ty}) -> Self {{
+{prefix}                self.set_{ident}(v);
+{prefix}                self
+{prefix}            }}"
+            )
+            .unwrap();
+        }
+    }
+
+    let supers = "super::".repeat(depth);
+    writeln!(
+        output,
+        "\
+{prefix}        /// Typed value of the `{reg_name}` register
+{prefix}        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+{prefix}        pub struct Value(pub u8);
+
+{prefix}        #[allow(dead_code)]
+{prefix}        impl Value {{{methods}{prefix}        }}
+
+{prefix}        impl Default for Value {{
+{prefix}            fn default() -> Self {{
+{prefix}                <Self as fpga_regmap::Register>::RESET
+{prefix}            }}
+{prefix}        }}
+
+{prefix}        impl fpga_regmap::Register for Value {{
+{prefix}            const NAME: &'static str = {reg_name:?};
+{prefix}            const ADDR: u16 = {supers}Addr::{addr} as u16;
+{prefix}            const RESET: Self = Self({reset:#04x});
+{prefix}            const FIELDS: &'static [fpga_regmap::Field] = &[
+{fields}{prefix}            ];
+
+{prefix}            fn from_raw(raw: u8) -> Self {{
+{prefix}                Self(raw)
+{prefix}            }}
+
+{prefix}            fn raw(self) -> u8 {{
+{prefix}                self.0
+{prefix}            }}
+{prefix}        }}"
+    )
+    .unwrap();
+    if readable {
+        writeln!(
+            output,
+            "{prefix}        impl fpga_regmap::Readable for Value {{}}"
+        )
+        .unwrap();
+    }
+    if writable {
+        writeln!(
+            output,
+            "{prefix}        impl fpga_regmap::Writable for Value {{}}"
+        )
+        .unwrap();
+    }
+}
+
+/// Writes the module for a single node.  `addr_prefix` mirrors the naming
+/// of the corresponding `Addr` variants, and `depth` is the number of
+/// modules between this node and the top level.
+fn write_node(
+    node: &Node,
+    prefix: &str,
+    addr_prefix: &str,
+    depth: usize,
+    output: &mut String,
+) {
     match node {
         Node::Reg {
             inst_name,
@@ -159,6 +416,14 @@ fn write_node(node: &Node, prefix: &str, output: &mut String) {
             )
             .unwrap();
             write_reg_fields(children, prefix, output);
+            write_reg_value(
+                inst_name,
+                &format!("{addr_prefix}{inst_name}"),
+                depth + 1,
+                children,
+                prefix,
+                output,
+            );
 
             writeln!(output, "{prefix}    }}").unwrap();
         }
@@ -188,7 +453,13 @@ fn write_node(node: &Node, prefix: &str, output: &mut String) {
 {prefix}    pub mod {inst_name} {{",
             )
             .unwrap();
-            recurse_reg_map(children, &format!("    {prefix}"), output);
+            recurse_reg_map(
+                children,
+                &format!("    {prefix}"),
+                &format!("{inst_name}_{addr_prefix}"),
+                depth + 1,
+                output,
+            );
             writeln!(output, "{prefix}    }}").unwrap();
         }
 
@@ -198,9 +469,15 @@ fn write_node(node: &Node, prefix: &str, output: &mut String) {
     }
 }
 
-fn recurse_reg_map(children: &[Node], prefix: &str, output: &mut String) {
+fn recurse_reg_map(
+    children: &[Node],
+    prefix: &str,
+    addr_prefix: &str,
+    depth: usize,
+    output: &mut String,
+) {
     for child in children.iter() {
-        write_node(child, prefix, output);
+        write_node(child, prefix, addr_prefix, depth, output);
     }
 }
 
@@ -219,13 +496,19 @@ pub mod Reg {{"
     )
     .unwrap();
 
-    recurse_reg_map(children, "", output);
+    recurse_reg_map(children, "", "", 1, output);
 
     writeln!(output, "}}").unwrap();
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Generates Rust code for a register map exported from SystemRDL as JSON.
+///
+/// The output contains an `Addr` enum of register addresses and a `Reg`
+/// module with a module per register, each containing field masks and a
+/// typed `Value` implementing the traits from the `fpga-regmap` crate (which
+/// the including crate must depend on).
 pub fn fpga_regs(regs: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut output = String::new();
 
@@ -237,3 +520,184 @@ pub fn fpga_regs(regs: &str) -> Result<String, Box<dyn std::error::Error>> {
 
     Ok(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small register map exercising plain, encoded, read-only,
+    /// write-only, and nested registers
+    const FIXTURE: &str = r#"{
+        "type": "addrmap", "inst_name": "fixture", "addr_offset": 0,
+        "children": [
+            {
+                "type": "reg", "inst_name": "CTRL", "addr_offset": 0,
+                "regwidth": 8,
+                "children": [
+                    {
+                        "type": "field", "inst_name": "EN",
+                        "lsb": 0, "msb": 0, "reset": 1, "sw_access": "rw"
+                    },
+                    {
+                        "type": "field", "inst_name": "MODE",
+                        "lsb": 2, "msb": 3, "reset": 1, "sw_access": "rw",
+                        "encode": [
+                            { "name": "IDLE", "value": 0 },
+                            { "name": "RUN", "value": 1 },
+                            { "name": "HALT", "value": 2 }
+                        ]
+                    }
+                ]
+            },
+            {
+                "type": "reg", "inst_name": "STATUS", "addr_offset": 1,
+                "regwidth": 8,
+                "children": [
+                    {
+                        "type": "field", "inst_name": "COUNT",
+                        "lsb": 0, "msb": 3, "sw_access": "r"
+                    },
+                    {
+                        "type": "field", "inst_name": "BUSY",
+                        "lsb": 7, "msb": 7, "sw_access": "r"
+                    }
+                ]
+            },
+            {
+                "type": "addrmap", "inst_name": "sub", "addr_offset": 16,
+                "children": [
+                    {
+                        "type": "reg", "inst_name": "DATA", "addr_offset": 2,
+                        "regwidth": 8,
+                        "children": [
+                            {
+                                "type": "field", "inst_name": "VAL",
+                                "lsb": 0, "msb": 7, "sw_access": "w"
+                            }
+                        ]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    /// Returns the generated code for `module`, i.e. everything from its
+    /// `pub mod` line up to the next register module
+    fn module(name: &str) -> String {
+        let out = fpga_regs(FIXTURE).unwrap();
+        let start = out
+            .find(&format!("pub mod {name} {{"))
+            .unwrap_or_else(|| panic!("no module {name}"));
+        let rest = &out[start..];
+        let end = rest[1..].find("pub mod ").map_or(rest.len(), |i| i + 1);
+        rest[..end].to_string()
+    }
+
+    fn assert_has(code: &str, lines: &[&str]) {
+        for line in lines {
+            assert!(
+                code.lines().any(|l| l.trim() == *line),
+                "missing `{line}` in:\n{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn addresses() {
+        let out = fpga_regs(FIXTURE).unwrap();
+        assert_has(&out, &["CTRL = 0x0,", "STATUS = 0x1,", "sub_DATA = 0x12,"]);
+    }
+
+    #[test]
+    fn masks_and_reset() {
+        let ctrl = module("CTRL");
+        assert_has(
+            &ctrl,
+            &[
+                "pub const EN: u8 = 0b00000001;",
+                "pub const MODE: u8 = 0b00001100;",
+                "const ADDR: u16 = super::super::Addr::CTRL as u16;",
+                "const RESET: Self = Self(0x05);",
+                "impl fpga_regmap::Readable for Value {}",
+                "impl fpga_regmap::Writable for Value {}",
+            ],
+        );
+    }
+
+    #[test]
+    fn encoded_field() {
+        let ctrl = module("CTRL");
+        assert_has(
+            &ctrl,
+            &[
+                "pub enum ModeEncoded {",
+                "IDLE = 0,",
+                "RUN = 1,",
+                "HALT = 2,",
+                "0 => Some(Self::IDLE),",
+                "2 => Some(Self::HALT),",
+                "_ => None,",
+                "pub fn mode(&self) -> Option<ModeEncoded> {",
+                "ModeEncoded::from_raw((self.0 & MODE) >> 2)",
+                "pub fn set_mode(&mut self, v: ModeEncoded) {",
+                "self.0 = (self.0 & !MODE) | ((v as u8) << 2 & MODE);",
+                "pub fn with_mode(mut self, v: ModeEncoded) -> Self {",
+            ],
+        );
+    }
+
+    #[test]
+    fn bool_field() {
+        let ctrl = module("CTRL");
+        assert_has(
+            &ctrl,
+            &[
+                "pub fn en(&self) -> bool {",
+                "self.0 & EN != 0",
+                "pub fn set_en(&mut self, v: bool) {",
+                "self.0 = (self.0 & !EN) | (v as u8 & EN);",
+            ],
+        );
+    }
+
+    #[test]
+    fn read_only_register() {
+        let status = module("STATUS");
+        assert_has(
+            &status,
+            &[
+                "pub fn count(&self) -> u8 {",
+                "self.0 & COUNT",
+                "pub fn busy(&self) -> bool {",
+                "const RESET: Self = Self(0x00);",
+                "impl fpga_regmap::Readable for Value {}",
+            ],
+        );
+        assert!(!status.contains("fn set_"), "{status}");
+        assert!(!status.contains("Writable"), "{status}");
+    }
+
+    #[test]
+    fn nested_write_only_register() {
+        let data = module("DATA");
+        assert_has(
+            &data,
+            &[
+                "const ADDR: u16 = super::super::super::Addr::sub_DATA as u16;",
+                "pub fn set_val(&mut self, v: u8) {",
+                "self.0 = v;",
+                "impl fpga_regmap::Writable for Value {}",
+            ],
+        );
+        assert!(!data.contains("pub fn val(&self)"), "{data}");
+        assert!(!data.contains("Readable"), "{data}");
+    }
+
+    #[test]
+    #[should_panic(expected = "bad sw_access")]
+    fn bad_access() {
+        let regs =
+            FIXTURE.replace(r#""sw_access": "w""#, r#""sw_access": "x""#);
+        let _ = fpga_regs(&regs);
+    }
+}
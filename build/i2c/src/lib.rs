@@ -204,6 +204,50 @@ struct I2cSensors {
     speed: usize,
 
     names: Option<Vec<String>>,
+
+    /// warning/critical thresholds, by sensor kind
+    #[serde(default)]
+    thresholds: BTreeMap<Sensor, SensorThresholds>,
+}
+
+///
+/// Warning and critical thresholds for a sensor, in the sensor's native unit.
+/// These are specified per sensor kind, e.g.:
+///
+/// ```toml
+/// sensors = { temperature = 1, thresholds = { temperature = { warning = 80.0 } } }
+/// ```
+///
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SensorThresholds {
+    pub warning: Option<f32>,
+    pub critical: Option<f32>,
+
+    /// distance below a threshold before its alarm clears
+    #[serde(default)]
+    pub hysteresis: f32,
+}
+
+impl SensorThresholds {
+    pub fn check(&self, what: &str) -> Result<()> {
+        if self.hysteresis < 0.0 {
+            bail!("{}: hysteresis ({}) is negative", what, self.hysteresis);
+        }
+
+        if let (Some(warning), Some(critical)) = (self.warning, self.critical) {
+            if warning > critical {
+                bail!(
+                    "{}: warning threshold ({}) exceeds critical ({})",
+                    what,
+                    warning,
+                    critical
+                );
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -412,7 +456,9 @@ pub enum Disposition {
     Validation,
 }
 
-#[derive(Copy, Clone, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[derive(
+    Copy, Clone, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
 #[serde(rename_all = "kebab-case")]
 pub enum Sensor {
     Temperature,
@@ -442,6 +488,24 @@ impl std::fmt::Display for Sensor {
     }
 }
 
+impl std::str::FromStr for Sensor {
+    type Err = anyhow::Error;
+
+    /// Parses a sensor kind as spelled in app.toml (e.g. `input-current`)
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "temperature" => Sensor::Temperature,
+            "power" => Sensor::Power,
+            "current" => Sensor::Current,
+            "voltage" => Sensor::Voltage,
+            "input-current" | "input_current" => Sensor::InputCurrent,
+            "input-voltage" | "input_voltage" => Sensor::InputVoltage,
+            "speed" => Sensor::Speed,
+            _ => bail!("unknown sensor kind \"{}\"", s),
+        })
+    }
+}
+
 #[derive(PartialEq)]
 enum PowerDevices {
     /// PMBus power devices
@@ -670,6 +734,7 @@ impl ConfigGenerator {
             r##"
     use drv_stm32xx_i2c::I2cPin;
 
+    #[allow(dead_code)]
     pub fn pins() -> [I2cPin; {}] {{"##,
             len
         )?;
@@ -1407,3 +1472,63 @@ pub fn device_descriptions() -> impl Iterator<Item = I2cDeviceDescription> {
         },
     )
 }
+
+pub struct I2cSensorDescription {
+    pub id: usize,
+    pub name: Option<String>,
+    pub kind: Sensor,
+    pub device: String,
+    pub description: String,
+    pub refdes: Option<String>,
+    pub thresholds: SensorThresholds,
+}
+
+///
+/// Returns a description of every I2C sensor, in `SensorId` order, including
+/// the owning device and any thresholds specified in the app.toml.
+///
+pub fn sensor_descriptions() -> Result<Vec<I2cSensorDescription>> {
+    let g = ConfigGenerator::new(Disposition::Sensors);
+    let sensors = g.sensors_description();
+    let mut rval = vec![];
+
+    for (device, sensors) in g.devices.iter().zip(sensors.device_sensors) {
+        if let Some(s) = &device.sensors {
+            for (kind, t) in &s.thresholds {
+                if !sensors.iter().any(|s| s.kind == *kind) {
+                    bail!(
+                        "{} ({}) has {:?} thresholds but no such sensors",
+                        device.device,
+                        device.description,
+                        kind
+                    );
+                }
+
+                t.check(&format!("{} {:?}", device.device, kind))?;
+            }
+        }
+
+        for s in sensors {
+            let thresholds = device
+                .sensors
+                .as_ref()
+                .and_then(|d| d.thresholds.get(&s.kind))
+                .copied()
+                .unwrap_or_default();
+
+            rval.push(I2cSensorDescription {
+                id: s.id,
+                name: s.name,
+                kind: s.kind,
+                device: device.device.clone(),
+                description: device.description.clone(),
+                refdes: device.refdes.clone(),
+                thresholds,
+            });
+        }
+    }
+
+    assert!(rval.iter().enumerate().all(|(i, s)| s.id == i));
+
+    Ok(rval)
+}
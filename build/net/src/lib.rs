@@ -4,6 +4,7 @@
 
 use serde::Deserialize;
 use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
 
 ///////////////////////////////////////////////////////////////////////////////
 // Network config schema definition.
@@ -27,19 +28,163 @@ pub struct NetConfig {
     /// during the `net` build, so it must be present iff the `vlan` feature
     /// is turned on.
     pub vlan: Option<VLanConfig>,
+
+    /// Global (non-link-local) IPv6 addressing, or None to only use each
+    /// interface's link-local address.
+    pub ipv6: Option<Ipv6Config>,
+
+    /// Tasks to notify when an Ethernet link goes up or down, or changes
+    /// speed.
+    #[serde(default)]
+    pub link_notify: Vec<TaskNote>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Ipv6Config {
+    /// Whether to configure addresses from router advertisements (SLAAC).
+    /// This is checked against the `net` task's `slaac` feature.
+    #[serde(default)]
+    pub slaac: bool,
+
+    /// Static addresses, at most one per interface. These can be overridden
+    /// from VPD if the `net` task's `vpd-ipv6` feature is enabled.
+    #[serde(default)]
+    pub addresses: Vec<StaticAddressConfig>,
+}
+
+impl Ipv6Config {
+    /// Returns the static address for each interface (i.e. each VLAN, or the
+    /// single interface without VLANs), in order.
+    pub fn static_addresses(
+        &self,
+        vlan: Option<VLanConfig>,
+    ) -> Vec<Option<&StaticAddressConfig>> {
+        let mut out = vec![None; vlan.map(|v| v.count).unwrap_or(1)];
+        for a in &self.addresses {
+            let i = match (vlan, a.vlan) {
+                (Some(v), Some(vid)) => vid - v.start,
+                _ => 0,
+            };
+            out[i] = Some(a);
+        }
+        out
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StaticAddressConfig {
+    /// VLAN (by VID) to which this address is assigned. This must be present
+    /// iff `vlan` is configured.
+    pub vlan: Option<usize>,
+    pub address: Ipv6Addr,
+    #[serde(default = "default_prefix_len")]
+    pub prefix_len: u8,
+
+    /// Default router for off-link destinations; if this is omitted and SLAAC
+    /// is enabled, the router is learned from advertisements instead.
+    pub gateway: Option<Ipv6Addr>,
+}
+
+fn default_prefix_len() -> u8 {
+    64
 }
 
 /// TODO: this type really wants to be an enum, but the toml crate's enum
-/// handling is really, really fragile, and currently it would be an enum with a
-/// single variant anyway.
+/// handling is really, really fragile.
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct SocketConfig {
+    /// Either `udp` or `tcp`; the latter requires the `net` task's `tcp`
+    /// feature.
     pub kind: String,
     pub owner: TaskNote,
     pub port: u16,
     pub tx: BufSize,
     pub rx: BufSize,
+
+    /// VLANs (by VID) on which this socket is bound, or None to bind it on
+    /// every VLAN. This may only be used if `vlan` is configured.
+    pub vlans: Option<Vec<usize>>,
+
+    /// Linker section for the socket's data buffers (e.g. `eth_bulk`), which
+    /// must be mapped to a memory in the `net` task's `sections`. Packet
+    /// headers always stay in normal RAM, since these sections are not
+    /// initialized.
+    pub section: Option<String>,
+
+    /// Binds the socket to its interface's global IPv6 address (static or
+    /// from SLAAC) instead of its link-local address. This is only allowed
+    /// for UDP sockets, and requires `ipv6` to be configured.
+    #[serde(default)]
+    pub global: bool,
+
+    /// Limits on the rate at which incoming packets are accepted, or None
+    /// for no limit. This is only allowed for UDP sockets. With VLANs, the
+    /// limits apply to each VLAN separately.
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Source prefixes (e.g. `fe80::/10`) from which incoming packets are
+    /// accepted, or None to accept packets from anywhere. This is only
+    /// allowed for UDP sockets.
+    pub allow: Option<Vec<Ipv6Prefix>>,
+}
+
+impl SocketConfig {
+    /// Returns the (0-based) indices of the VLANs on which this socket is
+    /// bound. Without VLANs, every socket is bound on the single interface.
+    pub fn vlan_indices(&self, vlan: Option<VLanConfig>) -> Vec<usize> {
+        match (vlan, &self.vlans) {
+            (None, _) => vec![0],
+            (Some(v), None) => (0..v.count).collect(),
+            (Some(v), Some(vids)) => vids.iter().map(|i| i - v.start).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Limit on packets from all sources together
+    pub socket: Option<TokenBucketConfig>,
+    /// Limit on packets from each source address
+    pub per_source: Option<TokenBucketConfig>,
+}
+
+#[derive(Copy, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TokenBucketConfig {
+    /// Sustained rate, in packets per second
+    pub rate: u32,
+    /// Number of packets which may arrive back-to-back
+    pub burst: u32,
+}
+
+/// An IPv6 prefix, written as `address/length`
+#[derive(Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Ipv6Prefix {
+    pub address: Ipv6Addr,
+    pub len: u8,
+}
+
+impl TryFrom<String> for Ipv6Prefix {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (addr, len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("prefix {:?} has no length", s))?;
+        let address = addr
+            .parse()
+            .map_err(|_| format!("invalid address in prefix {:?}", s))?;
+        let len = len
+            .parse()
+            .ok()
+            .filter(|&n| n <= 128)
+            .ok_or_else(|| format!("invalid length in prefix {:?}", s))?;
+        Ok(Self { address, len })
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize)]
@@ -54,6 +199,9 @@ pub struct VLanConfig {
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct BufSize {
+    /// Number of packets, for UDP sockets; TCP sockets are byte streams, so
+    /// this must be omitted for them.
+    #[serde(default)]
     pub packets: usize,
     pub bytes: usize,
 }
@@ -78,9 +226,265 @@ pub fn load_net_config() -> Result<NetConfig, Box<dyn std::error::Error>> {
         _ => (),
     }
 
+    validate(&cfg)?;
     Ok(cfg)
 }
 
+/// Checks socket declarations for unknown kinds, bad notification bits, VLANs
+/// outside the configured range, bad rate limits, and sockets which would bind
+/// the same port on the same VLAN, along with any static IPv6 addresses and
+/// the notification bits for link state changes.
+fn validate(cfg: &NetConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(ipv6) = &cfg.ipv6 {
+        validate_ipv6(cfg.vlan, ipv6)?;
+    }
+
+    // Maps (task, notification bit) to the socket using it
+    let mut bits: BTreeMap<(&String, u32), &str> = BTreeMap::new();
+    for (name, s) in &cfg.sockets {
+        match s.kind.as_str() {
+            "udp" => {
+                if s.tx.packets == 0 || s.rx.packets == 0 {
+                    return Err(format!(
+                        "socket {}: UDP sockets need a packet count",
+                        name
+                    )
+                    .into());
+                }
+            }
+            "tcp" => {
+                if s.tx.packets != 0 || s.rx.packets != 0 {
+                    return Err(format!(
+                        "socket {}: TCP sockets don't use a packet count",
+                        name
+                    )
+                    .into());
+                }
+            }
+            k => {
+                return Err(format!(
+                    "socket {}: unsupported socket kind {:?}",
+                    name, k
+                )
+                .into());
+            }
+        }
+
+        let note = s.owner.notification;
+        if note.count_ones() != 1 {
+            return Err(format!(
+                "socket {}: notification mask ({:#b}) has {} bits set \
+                 (expected exactly one)",
+                name,
+                note,
+                note.count_ones()
+            )
+            .into());
+        }
+        if let Some(prev) = bits.insert((&s.owner.name, note), name) {
+            return Err(format!(
+                "sockets {} and {} both notify task {} with {:#b}",
+                prev, name, s.owner.name, note
+            )
+            .into());
+        }
+
+        match (cfg.vlan, &s.vlans) {
+            (None, Some(_)) => {
+                return Err(format!(
+                    "socket {} lists vlans, but no VLANs are configured",
+                    name
+                )
+                .into());
+            }
+            (Some(v), Some(vids)) => {
+                if vids.is_empty() {
+                    return Err(format!("socket {}: empty vlans", name).into());
+                }
+                let range = v.start..v.start + v.count;
+                for (i, vid) in vids.iter().enumerate() {
+                    if !range.contains(vid) {
+                        return Err(format!(
+                            "socket {}: VLAN {:#x} is not in {:#x?}",
+                            name, vid, range
+                        )
+                        .into());
+                    }
+                    if vids[..i].contains(vid) {
+                        return Err(format!(
+                            "socket {}: VLAN {:#x} is listed twice",
+                            name, vid
+                        )
+                        .into());
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        if s.global && (s.kind != "udp" || cfg.ipv6.is_none()) {
+            return Err(format!(
+                "socket {}: only UDP sockets can be global, and only if \
+                 ipv6 is configured",
+                name
+            )
+            .into());
+        }
+
+        if (s.rate_limit.is_some() || s.allow.is_some()) && s.kind != "udp" {
+            return Err(format!(
+                "socket {}: rate-limit and allow are only supported for UDP \
+                 sockets",
+                name
+            )
+            .into());
+        }
+        if let Some(r) = &s.rate_limit {
+            for b in [r.socket, r.per_source].iter().flatten() {
+                // Buckets count thousandths of a packet in a u32
+                if b.rate == 0 || b.burst == 0 || b.burst > u32::MAX / 1000 {
+                    return Err(format!(
+                        "socket {}: invalid rate limit (rate {}, burst {})",
+                        name, b.rate, b.burst
+                    )
+                    .into());
+                }
+            }
+        }
+        if s.allow.as_ref().map_or(false, |a| a.is_empty()) {
+            return Err(format!(
+                "socket {}: empty allow list (omit it to allow any source)",
+                name
+            )
+            .into());
+        }
+
+        if let Some(section) = &s.section {
+            let valid = section
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if section.is_empty() || !valid {
+                return Err(format!(
+                    "socket {}: invalid section name {:?}",
+                    name, section
+                )
+                .into());
+            }
+        }
+    }
+
+    for t in &cfg.link_notify {
+        let note = t.notification;
+        if note.count_ones() != 1 {
+            return Err(format!(
+                "link-notify: notification mask ({:#b}) for task {} has {} \
+                 bits set (expected exactly one)",
+                note,
+                t.name,
+                note.count_ones()
+            )
+            .into());
+        }
+        if let Some(prev) = bits.insert((&t.name, note), "link-notify") {
+            return Err(format!(
+                "socket {} and link-notify both notify task {} with {:#b}",
+                prev, t.name, note
+            )
+            .into());
+        }
+    }
+
+    let sockets = cfg.sockets.iter().collect::<Vec<_>>();
+    for (i, (a, sa)) in sockets.iter().enumerate() {
+        let va = sa.vlan_indices(cfg.vlan);
+        for (b, sb) in &sockets[i + 1..] {
+            if sa.kind != sb.kind || sa.port != sb.port {
+                continue;
+            }
+            let vb = sb.vlan_indices(cfg.vlan);
+            if va.iter().any(|v| vb.contains(v)) {
+                return Err(format!(
+                    "sockets {} and {} both bind {} port {}",
+                    a, b, sa.kind, sa.port
+                )
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_ipv6(
+    vlan: Option<VLanConfig>,
+    cfg: &Ipv6Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut seen = BTreeMap::new();
+    for a in &cfg.addresses {
+        let addr = a.address;
+        match (vlan, a.vlan) {
+            (Some(_), None) => {
+                return Err(format!(
+                    "IPv6 address {} needs a vlan, since VLANs are configured",
+                    addr
+                )
+                .into());
+            }
+            (None, Some(_)) => {
+                return Err(format!(
+                    "IPv6 address {} has a vlan, but no VLANs are configured",
+                    addr
+                )
+                .into());
+            }
+            (Some(v), Some(vid))
+                if !(v.start..v.start + v.count).contains(&vid) =>
+            {
+                return Err(format!(
+                    "IPv6 address {}: VLAN {:#x} is not configured",
+                    addr, vid
+                )
+                .into());
+            }
+            _ => (),
+        }
+        if let Some(prev) = seen.insert(a.vlan, addr) {
+            return Err(format!(
+                "IPv6 addresses {} and {} are on the same interface",
+                prev, addr
+            )
+            .into());
+        }
+
+        // Link-local addresses are always derived from the MAC address.
+        let link_local = addr.segments()[0] & 0xffc0 == 0xfe80;
+        if addr.is_unspecified() || addr.is_multicast() || link_local {
+            return Err(format!(
+                "IPv6 address {} is not a global unicast address",
+                addr
+            )
+            .into());
+        }
+        if !(1..=128).contains(&a.prefix_len) {
+            return Err(format!(
+                "IPv6 address {}: invalid prefix length {}",
+                addr, a.prefix_len
+            )
+            .into());
+        }
+        if let Some(gw) = a.gateway {
+            if gw.is_unspecified() || gw.is_multicast() {
+                return Err(format!(
+                    "IPv6 address {}: invalid gateway {}",
+                    addr, gw
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn generate_vlan_consts(
     config: &NetConfig,
     mut out: impl std::io::Write,
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{collections::HashSet, hash::Hash};
+use std::{collections::HashSet, fmt, hash::Hash};
 
 use anyhow::{bail, Result};
 use rand::prelude::*;
@@ -10,6 +10,61 @@ use rand_chacha::ChaCha20Rng;
 
 use phash::PerfectHash;
 
+/// Hash families to search, expressed as the right shift applied to
+/// `PerfectHash::phash` before it's reduced to a table index.  Our hashes are
+/// multiplicative, which mixes the key into the high bits of the product, so
+/// those are worth trying when the low bits can't separate the keys.
+pub const SHIFTS: [u32; 3] = [0, 16, 8];
+
+fn check_unique<K: Hash + Eq, V>(values: &[(K, V)]) -> Result<()> {
+    if values.iter().map(|v| &v.0).collect::<HashSet<_>>().len() != values.len()
+    {
+        bail!("Cannot build a lookup table with duplicate keys");
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Size and lookup cost of a generated table, for reporting at build time
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+    pub kind: &'static str,
+    pub entries: usize,
+    /// Number of value slots in the table, including empty ones
+    pub slots: usize,
+    /// Hash computations per lookup
+    pub hashes: usize,
+    /// Worst-case key comparisons per lookup
+    pub comparisons: usize,
+}
+
+impl Stats {
+    pub fn load_factor(&self) -> f64 {
+        if self.slots == 0 {
+            1.0
+        } else {
+            self.entries as f64 / self.slots as f64
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} entries in {} slots ({:.0}% load), \
+             {} hash(es) and {} comparison(s) per lookup",
+            self.kind,
+            self.entries,
+            self.slots,
+            self.load_factor() * 100.0,
+            self.hashes,
+            self.comparisons,
+        )
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 /// A owned perfect hash from keys to values. This `struct` is intended for
@@ -17,6 +72,7 @@ use phash::PerfectHash;
 /// from the table; `phash::PerfectHash` is intended for use at runtime.
 pub struct OwnedPerfectHashMap<K, V> {
     pub m: u32,
+    pub shift: u32,
     pub values: Vec<Option<(K, V)>>,
 }
 
@@ -24,13 +80,17 @@ impl<K, V> OwnedPerfectHashMap<K, V>
 where
     K: PerfectHash + Hash + Eq,
 {
+    fn index(key: &K, slots: usize, m: u32, shift: u32) -> usize {
+        (key.phash(m) >> shift) % slots
+    }
+
     /// Checks if `m` creates a valid perfect hash with some number of slots
-    fn check(values: &[(K, V)], slots: usize, m: u32) -> bool {
+    fn check(values: &[(K, V)], slots: usize, m: u32, shift: u32) -> bool {
         assert!(slots >= values.len());
 
         let mut vs = values
             .iter()
-            .map(|v| v.0.phash(m) % slots)
+            .map(|v| Self::index(&v.0, slots, m, shift))
             .collect::<Vec<usize>>();
         vs.sort_unstable();
         vs.dedup();
@@ -39,37 +99,51 @@ where
 
     /// Attempt to generate a perfect hash for the given input data
     pub fn build(values: Vec<(K, V)>) -> Result<Self> {
-        if values.iter().map(|v| &v.0).collect::<HashSet<_>>().len()
-            != values.len()
-        {
-            bail!("Cannot build a perfect hash with duplicate keys");
-        }
+        check_unique(&values)?;
 
         const TRY_COUNT: usize = 1_000;
         let mut rng = ChaCha20Rng::seed_from_u64(0x1de);
         for slots in values.len()..(2 * values.len() + 1) {
-            for _ in 0..TRY_COUNT {
-                let m = rng.gen();
-                if Self::check(&values, slots, m) {
-                    let mut out = (0..slots).map(|_| None).collect::<Vec<_>>();
-                    for v in values.into_iter() {
-                        let index = v.0.phash(m) % slots;
-                        assert!(out[index].is_none());
-                        out[index] = Some(v);
+            for shift in SHIFTS {
+                for _ in 0..TRY_COUNT {
+                    let m = rng.gen();
+                    if Self::check(&values, slots, m, shift) {
+                        let mut out =
+                            (0..slots).map(|_| None).collect::<Vec<_>>();
+                        for v in values.into_iter() {
+                            let index = Self::index(&v.0, slots, m, shift);
+                            assert!(out[index].is_none());
+                            out[index] = Some(v);
+                        }
+                        return Ok(Self {
+                            m,
+                            shift,
+                            values: out,
+                        });
                     }
-                    return Ok(Self { m, values: out });
                 }
             }
         }
 
         bail!("Could not generate perfect hash");
     }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            kind: "PerfectHashMap",
+            entries: self.values.iter().filter(|v| v.is_some()).count(),
+            slots: self.values.len(),
+            hashes: 1,
+            comparisons: 1,
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
 pub struct OwnedNestedPerfectHashMap<K, V> {
     pub m: u32,
+    pub shift: u32,
     pub g: Vec<u32>,
     pub values: Vec<Vec<Option<(K, V)>>>,
 }
@@ -78,9 +152,9 @@ impl<K, V> OwnedNestedPerfectHashMap<K, V>
 where
     K: PerfectHash + Hash + Eq,
 {
-    fn id(key: &K, m: u32, g: &[u32]) -> (usize, usize) {
-        let i = key.phash(m) % g.len();
-        let j = key.phash(g[i]);
+    fn id(key: &K, m: u32, shift: u32, g: &[u32]) -> (usize, usize) {
+        let i = (key.phash(m) >> shift) % g.len();
+        let j = key.phash(g[i]) >> shift;
         (i, j)
     }
 
@@ -88,10 +162,15 @@ where
     ///
     /// If they work, returns a `Vec` of sub-table sizes (for each value
     /// in `g`).  Otherwise, returns `None`
-    fn check(values: &[(K, V)], m: u32, g: &[u32]) -> Option<Vec<usize>> {
+    fn check(
+        values: &[(K, V)],
+        m: u32,
+        shift: u32,
+        g: &[u32],
+    ) -> Option<Vec<usize>> {
         // Accumulate un-modded values
         let mut seen: Vec<HashSet<usize>> = vec![HashSet::default(); g.len()];
-        for (i, j) in values.iter().map(|(k, _v)| Self::id(k, m, g)) {
+        for (i, j) in values.iter().map(|(k, _v)| Self::id(k, m, shift, g)) {
             if !seen[i].insert(j) {
                 return None;
             }
@@ -119,39 +198,57 @@ where
 
     /// Attempt to generate a perfect hash for the given input data
     pub fn build(values: Vec<(K, V)>) -> Result<Self> {
-        if values.iter().map(|v| &v.0).collect::<HashSet<_>>().len()
-            != values.len()
-        {
-            bail!("Cannot build a perfect hash with duplicate keys");
-        }
+        check_unique(&values)?;
 
         const TRY_COUNT: usize = 1_000;
         let mut rng = ChaCha20Rng::seed_from_u64(0x1de);
         for slots in 2..16 {
-            for _ in 0..TRY_COUNT {
-                let m: u32 = rng.gen();
-                let mut g = vec![0u32; slots];
-                for g in g.iter_mut() {
-                    *g = rng.gen();
-                }
-                if let Some(sizes) = Self::check(&values, m, &g) {
-                    let mut out = vec![];
-                    for s in &sizes {
-                        out.push((0..*s).map(|_| None).collect::<Vec<_>>());
+            for shift in SHIFTS {
+                for _ in 0..TRY_COUNT {
+                    let m: u32 = rng.gen();
+                    let mut g = vec![0u32; slots];
+                    for g in g.iter_mut() {
+                        *g = rng.gen();
                     }
-                    for (k, v) in values.into_iter() {
-                        let (i, j) = Self::id(&k, m, &g);
-                        let j = j % sizes[i];
-                        assert!(out[i][j].is_none());
-                        out[i][j] = Some((k, v));
+                    if let Some(sizes) = Self::check(&values, m, shift, &g) {
+                        let mut out = vec![];
+                        for s in &sizes {
+                            out.push((0..*s).map(|_| None).collect::<Vec<_>>());
+                        }
+                        for (k, v) in values.into_iter() {
+                            let (i, j) = Self::id(&k, m, shift, &g);
+                            let j = j % sizes[i];
+                            assert!(out[i][j].is_none());
+                            out[i][j] = Some((k, v));
+                        }
+                        return Ok(Self {
+                            g,
+                            m,
+                            shift,
+                            values: out,
+                        });
                     }
-                    return Ok(Self { g, m, values: out });
                 }
             }
         }
 
         bail!("Could not generate perfect hash");
     }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            kind: "NestedPerfectHashMap",
+            entries: self
+                .values
+                .iter()
+                .flatten()
+                .filter(|v| v.is_some())
+                .count(),
+            slots: self.values.iter().map(|v| v.len()).sum(),
+            hashes: 2,
+            comparisons: 1,
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -169,6 +266,59 @@ where
         values.sort_by(|x, y| x.0.cmp(&y.0));
         Ok(Self { values })
     }
+
+    pub fn stats(&self) -> Stats {
+        let n = self.values.len();
+        Stats {
+            kind: "SortedList",
+            entries: n,
+            slots: n,
+            hashes: 0,
+            // Binary search takes ceil(log2(n + 1)) comparisons
+            comparisons: (usize::BITS - n.leading_zeros()) as usize,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A lookup table built with the cheapest strategy that works for a given set
+/// of keys: a single-level perfect hash if possible, then a nested perfect
+/// hash, and finally a sorted list (which always works).
+pub enum OwnedLookup<K, V> {
+    PerfectHash(OwnedPerfectHashMap<K, V>),
+    NestedPerfectHash(OwnedNestedPerfectHashMap<K, V>),
+    SortedList(OwnedSortedList<K, V>),
+}
+
+impl<K, V> OwnedLookup<K, V>
+where
+    K: PerfectHash + Hash + Eq + Ord + Clone,
+    V: Clone,
+{
+    /// Builds a lookup table for the given data.  If `hash` is false (e.g.
+    /// on targets without hardware division), this goes straight to a sorted
+    /// list.
+    pub fn build(values: Vec<(K, V)>, hash: bool) -> Result<Self> {
+        check_unique(&values)?;
+        if hash {
+            if let Ok(map) = OwnedPerfectHashMap::build(values.clone()) {
+                return Ok(Self::PerfectHash(map));
+            }
+            if let Ok(map) = OwnedNestedPerfectHashMap::build(values.clone()) {
+                return Ok(Self::NestedPerfectHash(map));
+            }
+        }
+        Ok(Self::SortedList(OwnedSortedList::build(values)?))
+    }
+
+    pub fn stats(&self) -> Stats {
+        match self {
+            Self::PerfectHash(m) => m.stats(),
+            Self::NestedPerfectHash(m) => m.stats(),
+            Self::SortedList(m) => m.stats(),
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -177,7 +327,7 @@ where
 mod tests {
     use super::*;
 
-    #[derive(Copy, Clone, Hash, Eq, PartialEq)]
+    #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
     struct U(u32);
     impl PerfectHash for U {
         fn phash(&self, b: u32) -> usize {
@@ -194,6 +344,16 @@ mod tests {
         }
     }
 
+    /// A key which hashes to the same value regardless of the seed, so no
+    /// perfect hash can be found
+    #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+    struct Bad(u32);
+    impl PerfectHash for Bad {
+        fn phash(&self, _b: u32) -> usize {
+            0
+        }
+    }
+
     fn hash_slots<K: PerfectHash + Hash + Eq>(values: Vec<K>) -> usize {
         let values = values.into_iter().map(|v| (v, ())).collect();
         OwnedPerfectHashMap::build(values).unwrap().values.len()
@@ -291,4 +451,42 @@ mod tests {
         let values = vec![U(5), U(7)];
         assert!(values.len() + 1 >= hash_slots(values));
     }
+
+    #[test]
+    fn lookup_prefers_perfect_hash() {
+        let values = vec![36, 51, 13, 14].into_iter().map(|i| (U(i), ()));
+        let lookup = OwnedLookup::build(values.collect(), true).unwrap();
+        assert!(matches!(lookup, OwnedLookup::PerfectHash(..)));
+        assert_eq!(lookup.stats().load_factor(), 1.0);
+    }
+
+    #[test]
+    fn lookup_falls_back_to_sorted_list() {
+        let values = (0..8).map(|i| (Bad(i), ())).collect();
+        let lookup = OwnedLookup::build(values, true).unwrap();
+        assert!(matches!(lookup, OwnedLookup::SortedList(..)));
+    }
+
+    #[test]
+    fn lookup_without_hashing() {
+        let values = vec![(U(5), ()), (U(7), ())];
+        let lookup = OwnedLookup::build(values, false).unwrap();
+        assert!(matches!(lookup, OwnedLookup::SortedList(..)));
+    }
+
+    #[test]
+    fn lookup_duplicate_keys() {
+        let values = vec![(Bad(1), ()), (Bad(1), ())];
+        assert!(OwnedLookup::build(values, false).is_err());
+    }
+
+    #[test]
+    fn sorted_list_stats() {
+        for (n, comparisons) in [(0, 0), (1, 1), (3, 2), (4, 3), (7, 3)] {
+            let values = (0..n).map(|i| (U(i), ())).collect();
+            let stats = OwnedSortedList::build(values).unwrap().stats();
+            assert_eq!(stats.entries, n as usize);
+            assert_eq!(stats.comparisons, comparisons);
+        }
+    }
 }
@@ -0,0 +1,328 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Support for `udprpc`'s allowlist of remotely callable ops.
+//!
+//! This is shared between the `udprpc` build script, which turns the
+//! allowlist into a table of op codes and leases, and `xtask`, which writes a
+//! description of the allowed ops into the build archive for host tools.
+
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Configuration for `udprpc`, from `[tasks.udprpc.config]`
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RpcConfig {
+    /// Ops which may be called over the network; if this is absent, no op
+    /// may be called (unless `allow_any` is set).
+    pub allow: Option<Vec<AllowConfig>>,
+    /// Allows any op (without leases) on any task to be called, for bench
+    /// setups; this may not be combined with `allow`.
+    #[serde(default)]
+    pub allow_any: bool,
+}
+
+impl RpcConfig {
+    /// Returns the allowlist, or `None` if any op may be called.
+    pub fn allowlist(&self) -> Result<Option<&[AllowConfig]>> {
+        match (&self.allow, self.allow_any) {
+            (Some(_), true) => {
+                bail!("udprpc: `allow` and `allow-any` are mutually exclusive")
+            }
+            (None, true) => Ok(None),
+            (Some(allow), false) => Ok(Some(allow)),
+            (None, false) => Ok(Some(&[])),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AllowConfig {
+    /// Name of the task serving the interface
+    pub task: String,
+    /// Name of the interface's file in `idl/`, without the `.idol` extension
+    pub interface: String,
+    /// Names of the ops which may be called
+    pub ops: Vec<String>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Idol schema
+//
+
+/// This represents our _subset_ of the Idol schema and _must not_ be marked
+/// with `deny_unknown_fields`!
+#[derive(Deserialize)]
+struct Interface {
+    name: String,
+    ops: IndexMap<String, Operation>,
+}
+
+#[derive(Deserialize)]
+struct Operation {
+    #[serde(default)]
+    leases: IndexMap<String, Lease>,
+    #[serde(default)]
+    idempotent: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Lease {
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub read: bool,
+    #[serde(default)]
+    pub write: bool,
+    pub max_len: Option<u32>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Resolved allowlist
+//
+
+/// An interface with some allowed ops
+pub struct AllowedInterface {
+    pub task: String,
+    /// Name of the interface, as declared in its Idol file
+    pub name: String,
+    /// Text of the interface's Idol file
+    pub idol: String,
+    pub ops: Vec<AllowedOp>,
+}
+
+#[derive(Serialize)]
+pub struct AllowedOp {
+    pub name: String,
+    /// Operation code, as passed to `sys_send`
+    pub code: u16,
+    pub idempotent: bool,
+    /// Leases, in the order in which they're passed to the server
+    pub leases: IndexMap<String, Lease>,
+}
+
+/// Loads the Idol files named in `allow` from `idl_dir`, and resolves the
+/// allowed ops to their op codes and leases.
+pub fn resolve(
+    allow: &[AllowConfig],
+    idl_dir: &Path,
+) -> Result<Vec<AllowedInterface>> {
+    allow
+        .iter()
+        .map(|a| {
+            let path = idl_dir.join(format!("{}.idol", a.interface));
+            let idol = std::fs::read_to_string(&path).with_context(|| {
+                format!("could not read {}", path.display())
+            })?;
+            resolve_interface(a, idol)
+                .with_context(|| format!("in {}", path.display()))
+        })
+        .collect()
+}
+
+/// Resolves the ops allowed by `a`, given the text of its Idol file.
+fn resolve_interface(
+    a: &AllowConfig,
+    idol: String,
+) -> Result<AllowedInterface> {
+    let iface: Interface = ron::from_str(&idol).context("could not parse")?;
+
+    let ops = a
+        .ops
+        .iter()
+        .map(|name| {
+            let Some((i, _, op)) = iface.ops.get_full(name) else {
+                bail!("interface {} has no op {name}", iface.name);
+            };
+            Ok(AllowedOp {
+                name: name.clone(),
+                // Idol numbers ops from 1, in order of declaration
+                code: u16::try_from(i + 1)?,
+                idempotent: op.idempotent,
+                leases: op.leases.clone(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(AllowedInterface {
+        task: a.task.clone(),
+        name: iface.name,
+        idol,
+        ops,
+    })
+}
+
+/// Checks that every task in `allow` serves the interface that it's listed
+/// with. `served` returns the names of the interfaces (files in `idl/`,
+/// without their extension) which a task serves, given the name of the task;
+/// tasks declare these as `serves` in their crate's `[package.metadata.idol]`.
+pub fn check_servers(
+    allow: &[AllowConfig],
+    served: impl Fn(&str) -> Result<Vec<String>>,
+) -> Result<()> {
+    for a in allow {
+        let served = served(&a.task)?;
+        if !served.contains(&a.interface) {
+            bail!(
+                "udprpc allowlist: task {} does not serve {} (it serves: {})",
+                a.task,
+                a.interface,
+                if served.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    served.join(", ")
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Builds a JSON description of the allowed ops, which lets host tools call
+/// them by name: for each interface, it gives the ID of the task serving it,
+/// the source of its Idol file (for argument and reply types), and the code
+/// and leases of each allowed op.
+///
+/// `task_id` maps task names to their indices.
+pub fn client_description(
+    interfaces: &[AllowedInterface],
+    task_id: impl Fn(&str) -> Option<usize>,
+) -> Result<String> {
+    #[derive(Serialize)]
+    struct Description<'a> {
+        task: &'a str,
+        task_id: usize,
+        interface: &'a str,
+        idol: &'a str,
+        ops: &'a [AllowedOp],
+    }
+
+    let out = interfaces
+        .iter()
+        .map(|i| {
+            let Some(task_id) = task_id(&i.task) else {
+                bail!("unknown task {} in udprpc allowlist", i.task);
+            };
+            Ok(Description {
+                task: &i.task,
+                task_id,
+                interface: &i.name,
+                idol: &i.idol,
+                ops: &i.ops,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(serde_json::to_string_pretty(&out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDOL: &str = r#"Interface(
+        name: "Demo",
+        ops: {
+            "first": (
+                args: {},
+                reply: Simple("()"),
+            ),
+            "second": (
+                leases: {
+                    "out": (type: "[u8]", write: true),
+                    "in": (type: "[u8]", read: true, max_len: Some(16)),
+                },
+                reply: Simple("u32"),
+                idempotent: true,
+            ),
+            "third": (
+                reply: Simple("()"),
+            ),
+        },
+    )"#;
+
+    fn allow(ops: &[&str]) -> AllowConfig {
+        AllowConfig {
+            task: "demo".to_string(),
+            interface: "demo".to_string(),
+            ops: ops.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn op_codes_follow_declaration_order() {
+        let iface =
+            resolve_interface(&allow(&["third", "first"]), IDOL.to_string())
+                .unwrap();
+        assert_eq!(iface.name, "Demo");
+        let codes: Vec<_> = iface
+            .ops
+            .iter()
+            .map(|op| (op.name.as_str(), op.code))
+            .collect();
+        assert_eq!(codes, [("third", 3), ("first", 1)]);
+    }
+
+    #[test]
+    fn leases_keep_declaration_order() {
+        let iface =
+            resolve_interface(&allow(&["second"]), IDOL.to_string()).unwrap();
+        let op = &iface.ops[0];
+        assert_eq!(op.code, 2);
+        assert!(op.idempotent);
+
+        let leases: Vec<_> = op.leases.keys().map(String::as_str).collect();
+        assert_eq!(leases, ["out", "in"]);
+        let out = &op.leases["out"];
+        assert!(out.write && !out.read && out.max_len.is_none());
+        let inp = &op.leases["in"];
+        assert!(inp.read && !inp.write);
+        assert_eq!(inp.max_len, Some(16));
+    }
+
+    #[test]
+    fn unknown_op_is_an_error() {
+        assert!(
+            resolve_interface(&allow(&["fourth"]), IDOL.to_string()).is_err()
+        );
+    }
+
+    #[test]
+    fn servers_must_declare_interface() {
+        let served = |task: &str| match task {
+            "demo" => Ok(vec!["jefe".to_string(), "demo".to_string()]),
+            _ => Ok(vec![]),
+        };
+        assert!(check_servers(&[allow(&["first"])], served).is_ok());
+
+        let mut other = allow(&["first"]);
+        other.interface = "other".to_string();
+        assert!(check_servers(&[other], served).is_err());
+
+        let mut elsewhere = allow(&["first"]);
+        elsewhere.task = "elsewhere".to_string();
+        assert!(check_servers(&[elsewhere], served).is_err());
+    }
+
+    #[test]
+    fn missing_allowlist_denies_everything() {
+        let config = RpcConfig::default();
+        assert_eq!(config.allowlist().unwrap().map(<[_]>::len), Some(0));
+
+        let config = RpcConfig {
+            allow: None,
+            allow_any: true,
+        };
+        assert!(config.allowlist().unwrap().is_none());
+
+        let config = RpcConfig {
+            allow: Some(vec![allow(&["first"])]),
+            allow_any: true,
+        };
+        assert!(config.allowlist().is_err());
+    }
+}
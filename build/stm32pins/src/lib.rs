@@ -0,0 +1,411 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+mod stm32h7;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Chip {
+    Stm32H743,
+    Stm32H753,
+}
+
+impl Chip {
+    /// Picks the chip from the calling crate's features, which must include
+    /// exactly one of `h743` or `h753` (optionally prefixed with `stm32`).
+    pub fn from_features() -> Result<Self> {
+        let has = |chip: &str| {
+            build_util::has_feature(chip)
+                || build_util::has_feature(&format!("stm32{}", chip))
+        };
+        match (has("h743"), has("h753")) {
+            (true, false) => Ok(Chip::Stm32H743),
+            (false, true) => Ok(Chip::Stm32H753),
+            (false, false) => bail!("no chip feature (h743 or h753) enabled"),
+            (true, true) => bail!("both h743 and h753 features are enabled"),
+        }
+    }
+
+    fn af_table(self) -> &'static [(char, u8, u8, &'static str)] {
+        match self {
+            Chip::Stm32H743 | Chip::Stm32H753 => stm32h7::AF_TABLE,
+        }
+    }
+
+    fn ports(self) -> &'static str {
+        match self {
+            Chip::Stm32H743 | Chip::Stm32H753 => stm32h7::PORTS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    Input,
+    Output,
+    Alternate,
+    Analog,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputType {
+    #[default]
+    PushPull,
+    OpenDrain,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Speed {
+    #[default]
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Pull {
+    #[default]
+    None,
+    Up,
+    Down,
+}
+
+///
+/// A set of pins within a single GPIO port that share a configuration, e.g.:
+///
+/// ```toml
+/// [[tasks.hf.config.pins]]
+/// port = "F"
+/// pins = [6, 7, 10]
+/// af = 9
+/// speed = "very-high"
+/// signals = ["QUADSPI_BK1_IO3", "QUADSPI_BK1_IO2", "QUADSPI_CLK"]
+/// ```
+///
+/// `mode` defaults to `alternate` if `af` is given.  If `signals` is given,
+/// each pin's alternate function is checked against the chip's AF table.
+///
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PinConfig {
+    port: char,
+    pins: Vec<u8>,
+    mode: Option<Mode>,
+    af: Option<u8>,
+    #[serde(default)]
+    output_type: OutputType,
+    #[serde(default)]
+    speed: Speed,
+    #[serde(default)]
+    pull: Pull,
+    signals: Option<Vec<String>>,
+    name: Option<String>,
+}
+
+impl PinConfig {
+    fn mode(&self) -> Result<Mode> {
+        match (self.mode, self.af) {
+            (None, Some(_)) | (Some(Mode::Alternate), Some(_)) => {
+                Ok(Mode::Alternate)
+            }
+            (Some(Mode::Alternate), None) => {
+                bail!("port {}: alternate mode requires `af`", self.port)
+            }
+            (Some(_), Some(_)) => {
+                bail!("port {}: `af` requires alternate mode", self.port)
+            }
+            (Some(mode), None) => Ok(mode),
+            (None, None) => bail!("port {}: missing `mode` or `af`", self.port),
+        }
+    }
+
+    fn mask(&self) -> u16 {
+        self.pins.iter().fold(0, |mask, p| mask | 1 << p)
+    }
+}
+
+/// Checks pin declarations for invalid pins, pins configured more than once,
+/// and alternate functions that disagree with the chip's AF table.
+fn validate(chip: Chip, pins: &[PinConfig]) -> Result<()> {
+    let mut used = BTreeMap::new();
+    let mut signals = BTreeMap::new();
+
+    for (i, p) in pins.iter().enumerate() {
+        if !chip.ports().contains(p.port) {
+            bail!("{:?} has no GPIO port {}", chip, p.port);
+        }
+
+        if p.pins.is_empty() {
+            bail!("port {}: no pins specified", p.port);
+        }
+
+        let mode = p.mode()?;
+        if let Some(af) = p.af {
+            if af > 15 {
+                bail!("port {}: invalid alternate function {}", p.port, af);
+            }
+        }
+
+        for &pin in &p.pins {
+            if pin > 15 {
+                bail!("invalid pin P{}{}", p.port, pin);
+            }
+
+            if let Some(prev) = used.insert((p.port, pin), i) {
+                bail!(
+                    "P{}{} is configured twice (pins entries {} and {})",
+                    p.port,
+                    pin,
+                    prev,
+                    i
+                );
+            }
+        }
+
+        if let Some(names) = &p.signals {
+            if mode != Mode::Alternate {
+                bail!("port {}: `signals` requires alternate mode", p.port);
+            }
+
+            if names.len() != p.pins.len() {
+                bail!(
+                    "port {}: {} signals given for {} pins",
+                    p.port,
+                    names.len(),
+                    p.pins.len()
+                );
+            }
+
+            let af = p.af.unwrap();
+            for (&pin, signal) in p.pins.iter().zip(names) {
+                let found = chip.af_table().iter().find(|(port, n, a, _)| {
+                    *port == p.port && *n == pin && *a == af
+                });
+
+                match found {
+                    Some((.., s)) if s == signal => (),
+                    Some((.., s)) => bail!(
+                        "P{}{} AF{} is {}, not {}",
+                        p.port,
+                        pin,
+                        af,
+                        s,
+                        signal
+                    ),
+                    None => bail!(
+                        "P{}{} AF{} is not in the {:?} AF table",
+                        p.port,
+                        pin,
+                        af,
+                        chip
+                    ),
+                }
+
+                if let Some((port, n)) = signals.insert(signal, (p.port, pin)) {
+                    bail!(
+                        "{} is routed to both P{}{} and P{}{}",
+                        signal,
+                        port,
+                        n,
+                        p.port,
+                        pin
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// Generates `pin_config.rs`, containing a `setup_pins` function that
+/// configures the given pins through the `sys` task, along with a `PinSet`
+/// constant for each named pin set.
+///
+pub fn codegen(chip: Chip, pins: Vec<PinConfig>) -> Result<()> {
+    validate(chip, &pins)?;
+
+    let out_dir = build_util::out_dir();
+    let dest_path = out_dir.join("pin_config.rs");
+    let mut file = std::fs::File::create(dest_path)?;
+
+    writeln!(
+        &mut file,
+        "#[allow(dead_code)]
+fn setup_pins(
+    sys: &drv_stm32xx_sys_api::Sys,
+) -> Result<(), drv_stm32xx_sys_api::GpioError> {{"
+    )?;
+
+    if !pins.is_empty() {
+        writeln!(&mut file, "    use drv_stm32xx_sys_api::*;")?;
+    }
+
+    for p in &pins {
+        writeln!(
+            &mut file,
+            "    sys.gpio_configure(
+        Port::{},
+        {:#018b},
+        Mode::{:?},
+        OutputType::{:?},
+        Speed::{:?},
+        Pull::{:?},
+        Alternate::AF{},
+    )?;",
+            p.port,
+            p.mask(),
+            p.mode()?,
+            p.output_type,
+            p.speed,
+            p.pull,
+            p.af.unwrap_or(0),
+        )?;
+    }
+
+    writeln!(&mut file, "    Ok(())\n}}")?;
+
+    for p in &pins {
+        if let Some(name) = &p.name {
+            writeln!(
+                &mut file,
+                "\n#[allow(dead_code)]
+const {}: drv_stm32xx_sys_api::PinSet = drv_stm32xx_sys_api::PinSet {{
+    port: drv_stm32xx_sys_api::Port::{},
+    pin_mask: {:#018b},
+}};",
+                name,
+                p.port,
+                p.mask()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alt(port: char, pins: &[u8], af: u8, signals: &[&str]) -> PinConfig {
+        PinConfig {
+            port,
+            pins: pins.to_vec(),
+            mode: None,
+            af: Some(af),
+            output_type: OutputType::default(),
+            speed: Speed::default(),
+            pull: Pull::default(),
+            signals: if signals.is_empty() {
+                None
+            } else {
+                Some(signals.iter().map(|s| s.to_string()).collect())
+            },
+            name: None,
+        }
+    }
+
+    fn output(port: char, pins: &[u8]) -> PinConfig {
+        PinConfig {
+            mode: Some(Mode::Output),
+            af: None,
+            ..alt(port, pins, 0, &[])
+        }
+    }
+
+    fn error(pins: &[PinConfig]) -> String {
+        validate(Chip::Stm32H753, pins).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_valid_pins() {
+        let pins = [
+            alt(
+                'F',
+                &[6, 7, 10],
+                9,
+                &["QUADSPI_BK1_IO3", "QUADSPI_BK1_IO2", "QUADSPI_CLK"],
+            ),
+            alt('F', &[8, 9], 10, &[]),
+            output('F', &[11]),
+            output('K', &[15]),
+        ];
+        validate(Chip::Stm32H753, &pins).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_pins() {
+        assert_eq!(error(&[output('L', &[0])]), "Stm32H753 has no GPIO port L");
+        assert_eq!(error(&[output('A', &[16])]), "invalid pin PA16");
+        assert_eq!(error(&[output('A', &[])]), "port A: no pins specified");
+        assert_eq!(
+            error(&[alt('A', &[1], 16, &[])]),
+            "port A: invalid alternate function 16"
+        );
+        assert_eq!(
+            error(&[PinConfig {
+                af: None,
+                ..alt('A', &[1], 0, &[])
+            }]),
+            "port A: missing `mode` or `af`"
+        );
+    }
+
+    #[test]
+    fn rejects_pins_configured_twice() {
+        assert_eq!(
+            error(&[output('B', &[1, 2]), alt('B', &[2], 11, &[])]),
+            "PB2 is configured twice (pins entries 0 and 1)"
+        );
+        assert_eq!(
+            error(&[output('B', &[3, 3])]),
+            "PB3 is configured twice (pins entries 0 and 0)"
+        );
+
+        // The same pin number on different ports is fine
+        validate(Chip::Stm32H753, &[output('B', &[2]), output('C', &[2])])
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_signal_conflicts() {
+        assert_eq!(
+            error(&[alt('F', &[10], 9, &["QUADSPI_BK1_IO3"])]),
+            "PF10 AF9 is QUADSPI_CLK, not QUADSPI_BK1_IO3"
+        );
+        assert_eq!(
+            error(&[alt('F', &[10], 8, &["QUADSPI_CLK"])]),
+            "PF10 AF8 is not in the Stm32H753 AF table"
+        );
+        assert_eq!(
+            error(&[
+                alt('B', &[11], 11, &["ETH_TX_EN"]),
+                alt('G', &[11], 11, &["ETH_TX_EN"]),
+            ]),
+            "ETH_TX_EN is routed to both PB11 and PG11"
+        );
+        assert_eq!(
+            error(&[alt('E', &[7, 8], 7, &["UART7_RX"])]),
+            "port E: 1 signals given for 2 pins"
+        );
+        assert_eq!(
+            error(&[PinConfig {
+                signals: Some(vec!["ETH_MDC".to_string()]),
+                ..output('C', &[1])
+            }]),
+            "port C: `signals` requires alternate mode"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Alternate function table for the STM32H743/753, from the "Port X
+//! alternate functions" tables of the datasheet (DS12110/DS12117).
+//!
+//! This only covers the pins configured through `build-stm32pins` so far;
+//! add entries as more pins move into app.toml.
+
+/// (port, pin, alternate function, signal)
+pub const AF_TABLE: &[(char, u8, u8, &str)] = &[
+    // Ethernet (RMII and MDIO)
+    ('A', 1, 11, "ETH_REF_CLK"),
+    ('A', 2, 11, "ETH_MDIO"),
+    ('A', 7, 11, "ETH_CRS_DV"),
+    ('B', 11, 11, "ETH_TX_EN"),
+    ('B', 12, 11, "ETH_TXD0"),
+    ('B', 13, 11, "ETH_TXD1"),
+    ('C', 1, 11, "ETH_MDC"),
+    ('C', 4, 11, "ETH_RXD0"),
+    ('C', 5, 11, "ETH_RXD1"),
+    ('G', 11, 11, "ETH_TX_EN"),
+    ('G', 12, 11, "ETH_TXD1"),
+    ('G', 13, 11, "ETH_TXD0"),
+    // I2C1
+    ('B', 6, 4, "I2C1_SCL"),
+    ('B', 7, 4, "I2C1_SDA"),
+    // UART7
+    ('E', 7, 7, "UART7_RX"),
+    ('E', 8, 7, "UART7_TX"),
+    ('E', 9, 7, "UART7_RTS"),
+    ('E', 10, 7, "UART7_CTS"),
+    // QUADSPI bank 1
+    ('F', 6, 9, "QUADSPI_BK1_IO3"),
+    ('F', 7, 9, "QUADSPI_BK1_IO2"),
+    ('F', 8, 10, "QUADSPI_BK1_IO0"),
+    ('F', 9, 10, "QUADSPI_BK1_IO1"),
+    ('F', 10, 9, "QUADSPI_CLK"),
+    ('G', 6, 10, "QUADSPI_BK1_NCS"),
+];
+
+pub const PORTS: &str = "ABCDEFGHIJK";
@@ -2,13 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::{hash_map::DefaultHasher, BTreeMap};
-use std::hash::Hasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Context, Result};
 use indexmap::IndexMap;
+use ordered_toml::value::{Table, Value};
 use serde::{Deserialize, Serialize};
 
 use crate::auxflash::{build_auxflash, AuxFlash, AuxFlashData};
@@ -17,33 +18,6 @@ use lpc55_areas::{
     ROTKeyStatus, SecureBootCfg,
 };
 
-/// A `PatchedConfig` allows a minimal form of inheritance between TOML files
-/// Specifically, it allows you to **add features** to specific tasks; nothing
-/// else.
-///
-/// Here's an example:
-/// ```toml
-/// name = "sidecar-a-lab"
-///
-/// [patches]
-/// inherit = "rev-a.toml"
-/// features.sequencer = ["stay-in-a2"]
-/// ```
-#[derive(Clone, Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
-pub struct PatchedConfig {
-    inherit: String,
-    patches: ConfigPatches,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
-pub struct ConfigPatches {
-    name: String,
-    #[serde(default)]
-    features: IndexMap<String, Vec<String>>,
-}
-
 /// A `RawConfig` represents an `app.toml` file that has been deserialized,
 /// but may not be ready for use.  In particular, we use the `chip` field
 /// to load a second file containing peripheral register addresses.
@@ -76,6 +50,8 @@ struct RawConfig {
     #[serde(default)]
     secure_task: Option<String>,
     auxflash: Option<AuxFlash>,
+    #[serde(default)]
+    shared: IndexMap<String, Vec<String>>,
 }
 
 #[derive(Clone, Debug)]
@@ -97,66 +73,38 @@ pub struct Config {
     pub peripherals: IndexMap<String, Peripheral>,
     pub extratext: IndexMap<String, Peripheral>,
     pub config: Option<ordered_toml::Value>,
-    pub buildhash: u64,
     pub app_toml_path: PathBuf,
-    pub patches: Option<ConfigPatches>,
+    /// Where each task and its config keys were set, for error messages
+    pub task_locations: TaskLocations,
+    /// The fully resolved TOML, after applying any inheritance
+    pub resolved: Value,
     pub secure_task: Option<String>,
     pub auxflash: Option<AuxFlashData>,
     pub dice_mfg: Option<Output>,
+    /// Resources which may be claimed by more than one task, mapped to the
+    /// tasks which are allowed to claim them; see `ownership.rs`
+    pub shared: IndexMap<String, Vec<String>>,
 }
 
 impl Config {
     pub fn from_file(cfg: &Path) -> Result<Self> {
-        Self::from_file_with_hasher(cfg, DefaultHasher::new())
-    }
-
-    fn from_file_with_hasher(
-        cfg: &Path,
-        mut hasher: DefaultHasher,
-    ) -> Result<Self> {
-        let cfg_contents = std::fs::read(&cfg)
-            .with_context(|| format!("could not read {}", cfg.display()))?;
-
-        // Accumulate the contents into the buildhash here, so that we hash both
-        // the inheritance file and the target if this is an `PatchedConfig`
-        hasher.write(&cfg_contents);
-
-        // Minimal TOML file inheritance, to enable features on a per-task basis
-        if let Ok(inherited) = toml::from_slice::<PatchedConfig>(&cfg_contents)
-        {
-            let file = cfg.parent().unwrap().join(&inherited.inherit);
-            let mut original = Config::from_file_with_hasher(&file, hasher)
-                .context(format!("Could not load template from {file:?}"))?;
-            original.name = inherited.patches.name.to_owned();
-            for (task, features) in &inherited.patches.features {
-                let t = original
-                    .tasks
-                    .get_mut(task)
-                    .ok_or_else(|| anyhow!("No such task {task}"))?;
-                for f in features {
-                    if t.features.contains(f) {
-                        bail!("Task {task} already contains feature {f}");
-                    }
-                    t.features.push(f.to_owned());
-                }
-            }
-            original.patches = Some(inherited.patches);
-            return Ok(original);
-        }
-
-        let toml: RawConfig = toml::from_slice(&cfg_contents)?;
+        let mut task_locations = TaskLocations::default();
+        let resolved =
+            Value::Table(load_layered(cfg, &mut vec![], &mut task_locations)?);
+        let toml: RawConfig = resolved
+            .clone()
+            .try_into()
+            .with_context(|| format!("could not parse {}", cfg.display()))?;
         if toml.tasks.contains_key("kernel") {
             bail!("'kernel' is reserved and cannot be used as a task name");
         }
 
         // The app.toml must include a `chip` key, which defines the peripheral
-        // register map in a separate file.  We load it then accumulate that
-        // file in the buildhash.
+        // register map in a separate file.
         let peripherals = {
             let chip_file =
                 cfg.parent().unwrap().join(&toml.chip).join("chip.toml");
             let chip_contents = std::fs::read(chip_file)?;
-            hasher.write(&chip_contents);
             toml::from_slice(&chip_contents)?
         };
 
@@ -171,12 +119,9 @@ impl Config {
                 std::fs::read(&chip_file).with_context(|| {
                     format!("reading chip file {}", chip_file.display())
                 })?;
-            hasher.write(&chip_contents);
             toml::from_slice::<IndexMap<String, Vec<Output>>>(&chip_contents)?
         };
 
-        let buildhash = hasher.finish();
-
         let img_names = if toml.image_names.is_empty() {
             vec!["default".to_string()]
         } else {
@@ -214,14 +159,81 @@ impl Config {
             extratext: toml.extratext,
             config: toml.config,
             auxflash,
-            buildhash,
             app_toml_path: cfg.to_owned(),
-            patches: None,
+            task_locations,
+            resolved,
             secure_task: toml.secure_task,
             dice_mfg,
+            shared: toml.shared,
         })
     }
 
+    /// Returns a fingerprint of everything in the configuration which affects
+    /// how the given task (or `"kernel"`) is compiled.
+    ///
+    /// `cargo` doesn't track the environment variables that we use to pass
+    /// configuration into build scripts, so we compare these fingerprints
+    /// against the previous build to decide which crates must be cleaned.
+    /// Settings which only matter at link time (task slots, stack size,
+    /// allocations) are covered by the link fingerprint in `dist.rs`.
+    pub fn build_fingerprint(&self, name: &str) -> u64 {
+        let mut hasher = fnv::FnvHasher::default();
+
+        // Settings which are shared by every build, either because they're
+        // passed in through `common_build_config` or because they change the
+        // generated register map / memory layout.
+        self.target.hash(&mut hasher);
+        self.board.hash(&mut hasher);
+        self.chip.hash(&mut hasher);
+        self.epoch.hash(&mut hasher);
+        self.version.hash(&mut hasher);
+        self.secure_separation.hash(&mut hasher);
+        self.image_names.hash(&mut hasher);
+        self.app_toml_path.hash(&mut hasher);
+        for task_name in self.tasks.keys() {
+            task_name.hash(&mut hasher);
+        }
+        for (periph_name, p) in &self.peripherals {
+            periph_name.hash(&mut hasher);
+            p.hash(&mut hasher);
+        }
+        for (mem, out) in &self.outputs {
+            mem.hash(&mut hasher);
+            out.hash(&mut hasher);
+        }
+        if let Some(config) = &self.config {
+            toml::to_string(config).unwrap().hash(&mut hasher);
+        }
+        if let Some(aux) = &self.auxflash {
+            aux.chck.hash(&mut hasher);
+            aux.checksums.hash(&mut hasher);
+        }
+        self.dice_mfg.hash(&mut hasher);
+
+        if name == "kernel" {
+            self.kernel.name.hash(&mut hasher);
+            self.kernel.features.hash(&mut hasher);
+            for (mem, size) in &self.kernel.requires {
+                mem.hash(&mut hasher);
+                size.hash(&mut hasher);
+            }
+        } else {
+            let task = &self.tasks[name];
+            name.hash(&mut hasher);
+            task.name.hash(&mut hasher);
+            task.features.hash(&mut hasher);
+            for (section, memory) in &task.sections {
+                section.hash(&mut hasher);
+                memory.hash(&mut hasher);
+            }
+            if let Some(config) = &task.config {
+                toml::to_string(config).unwrap().hash(&mut hasher);
+            }
+            self.need_tz_linker(name).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
     pub fn task_name_suggestion(&self, name: &str) -> String {
         // Suggest only for very small differences
         // High number can result in inaccurate suggestions for short queries e.g. `rls`
@@ -273,8 +285,8 @@ impl Config {
         // We include the path to the configuration TOML file so that proc macros
         // that use it can easily force a rebuild (using include_bytes!)
         //
-        // This doesn't matter now, because we rebuild _everything_ on app.toml
-        // changes, but once #240 is closed, this will be important.
+        // Changes to the app.toml are also caught by `build_fingerprint`, which
+        // cleans only the affected crates before building.
         let app_toml_path = self
             .app_toml_path
             .canonicalize()
@@ -516,12 +528,312 @@ impl Config {
         self.image_names.contains(name)
     }
 
+    /// Returns the image with the given name, checking that it's declared in
+    /// the TOML, or the first image if no name is given
+    pub fn image_name<'a>(
+        &'a self,
+        name: Option<&'a String>,
+    ) -> Result<&'a String> {
+        match name {
+            Some(name) if !self.check_image_name(name) => {
+                bail!("Image name {} not declared in TOML", name)
+            }
+            Some(name) => Ok(name),
+            None => Ok(&self.image_names[0]),
+        }
+    }
+
     pub fn need_tz_linker(&self, name: &str) -> bool {
         self.tasks[name].uses_secure_entry
             || self.secure_task.as_ref().map_or(false, |n| n == name)
     }
 }
 
+/// Loads a TOML file as a table, applying any `inherit` directive.
+///
+/// A file which uses `inherit` is an overlay on top of the named base file
+/// (which may itself inherit from another file).  For example:
+/// ```toml
+/// inherit = "rev-b.toml"
+/// name = "gimlet-b-lab"
+/// delete = ["tasks.udpecho", "tasks.udpbroadcast"]
+///
+/// [tasks.hiffy]
+/// features = ["h753", "stm32h7", "itm", "i2c", "gpio", "qspi"]
+///
+/// [append.tasks.gimlet_seq]
+/// features = ["stay-in-a2"]
+/// ```
+///
+/// The overlay is applied to the base in three steps:
+/// - Each dotted key path in `delete` is removed from the base; a path which
+///   doesn't exist is an error, so typos don't go unnoticed.
+/// - The rest of the overlay is deep-merged into the base: tables are merged
+///   key-by-key, and any other value (including arrays) replaces the value in
+///   the base.
+/// - The `append` table is merged in the same way, except that arrays are
+///   extended rather than replaced.
+///
+/// An overlay must set its own `name`, since that decides where build
+/// artifacts go.  Relative paths (e.g. `chip`) are resolved relative to the
+/// top-level file, so overlays should live next to the file they inherit.
+///
+/// The lines where each task and its config keys are set are recorded in
+/// `locations`, with overlays taking precedence over their base files.
+fn load_layered(
+    cfg: &Path,
+    seen: &mut Vec<PathBuf>,
+    locations: &mut TaskLocations,
+) -> Result<Table> {
+    let canonical = cfg
+        .canonicalize()
+        .with_context(|| format!("could not find {}", cfg.display()))?;
+    if seen.contains(&canonical) {
+        bail!("{} inherits from itself", cfg.display());
+    }
+    seen.push(canonical);
+
+    let contents = std::fs::read(&cfg)
+        .with_context(|| format!("could not read {}", cfg.display()))?;
+    let mut overlay: Table = ordered_toml::from_slice(&contents)
+        .with_context(|| format!("could not parse {}", cfg.display()))?;
+
+    let inherit = match take_key(&mut overlay, "inherit") {
+        None => {
+            for key in ["delete", "append"] {
+                if overlay.contains_key(key) {
+                    bail!(
+                        "{}: `{}` is only allowed along with `inherit`",
+                        cfg.display(),
+                        key
+                    );
+                }
+            }
+            locations.scan(cfg, &String::from_utf8_lossy(&contents));
+            return Ok(overlay);
+        }
+        Some(Value::String(s)) => s,
+        Some(_) => bail!("{}: `inherit` must be a file name", cfg.display()),
+    };
+    if !overlay.contains_key("name") {
+        bail!(
+            "{}: a file which uses `inherit` must set its own `name`",
+            cfg.display()
+        );
+    }
+
+    let file = cfg.parent().unwrap().join(&inherit);
+    let mut base = load_layered(&file, seen, locations)
+        .with_context(|| format!("could not load template from {file:?}"))?;
+
+    if let Some(delete) = take_key(&mut overlay, "delete") {
+        let paths = match delete {
+            Value::Array(a) => a,
+            _ => bail!("{}: `delete` must be an array", cfg.display()),
+        };
+        for p in paths {
+            let p = p.as_str().ok_or_else(|| {
+                anyhow!("{}: `delete` entries must be strings", cfg.display())
+            })?;
+            delete_path(&mut base, &split_key_path(p)?).with_context(|| {
+                format!("{}: could not delete `{}`", cfg.display(), p)
+            })?;
+        }
+    }
+
+    let append = take_key(&mut overlay, "append");
+    merge_tables(&mut base, overlay, false);
+    match append {
+        Some(Value::Table(t)) => merge_tables(&mut base, t, true),
+        Some(_) => bail!("{}: `append` must be a table", cfg.display()),
+        None => (),
+    }
+    locations.scan(cfg, &String::from_utf8_lossy(&contents));
+
+    Ok(base)
+}
+
+/// Removes a key from a table, preserving the order of the remaining keys
+fn take_key(table: &mut Table, key: &str) -> Option<Value> {
+    let mut out = None;
+    for (k, v) in std::mem::replace(table, Table::new()) {
+        if k == key {
+            out = Some(v);
+        } else {
+            table.insert(k, v);
+        }
+    }
+    out
+}
+
+/// Recursively merges `overlay` into `base`.  If `append` is true, arrays
+/// in the overlay are appended to arrays in the base; otherwise, they replace
+/// them.
+fn merge_tables(base: &mut Table, overlay: Table, append: bool) {
+    for (k, v) in overlay {
+        match (base.get_mut(&k), v) {
+            (Some(Value::Table(b)), Value::Table(o)) => {
+                merge_tables(b, o, append)
+            }
+            (Some(Value::Array(b)), Value::Array(o)) if append => b.extend(o),
+            (_, v) => {
+                base.insert(k, v);
+            }
+        }
+    }
+}
+
+/// Deletes the value at the given key path
+fn delete_path(table: &mut Table, path: &[String]) -> Result<()> {
+    let (last, parents) = path.split_last().unwrap();
+    let mut t = table;
+    for p in parents {
+        t = match t.get_mut(p) {
+            Some(Value::Table(t)) => t,
+            _ => bail!("no table named `{}`", p),
+        };
+    }
+    if take_key(t, last).is_none() {
+        bail!("no key named `{}`", last);
+    }
+    Ok(())
+}
+
+/// Splits a dotted key path (e.g. `tasks.i2c_driver.interrupts."i2c2.event"`)
+/// into its components.
+fn split_key_path(s: &str) -> Result<Vec<String>> {
+    let mut out = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => out.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quote in `{}`", s);
+    }
+    out.push(current);
+    if out.iter().any(|p| p.is_empty()) {
+        bail!("invalid key path `{}`", s);
+    }
+    Ok(out)
+}
+
+/// A line in one of the files making up an app's config
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// Where each task's `name`, and each key in its `config` table, was last set
+/// across the files making up an app's config.
+///
+/// `toml` can't tell us where tables defined by headers (including arrays of
+/// tables) start, so these are found by scanning each file's headers and
+/// keys instead.  Values spanning several lines are skipped over, and keys
+/// set within an inline `config = { .. }` table aren't found.
+#[derive(Clone, Debug, Default)]
+pub struct TaskLocations(BTreeMap<(String, Option<String>), Location>);
+
+impl TaskLocations {
+    /// Returns where the given task's `name` was last set
+    pub fn task(&self, task: &str) -> Option<&Location> {
+        self.0.get(&(task.to_owned(), None))
+    }
+
+    /// Returns where the given key of a task's config was last set
+    pub fn config_key(&self, task: &str, key: &str) -> Option<&Location> {
+        self.0.get(&(task.to_owned(), Some(key.to_owned())))
+    }
+
+    /// Records the locations set by one file, replacing any from the files
+    /// which it inherits from
+    fn scan(&mut self, file: &Path, contents: &str) {
+        let mut found = BTreeMap::new();
+        let mut section = vec![];
+        let mut depth = 0;
+        for (i, line) in contents.lines().enumerate() {
+            let (line, brackets) = scan_line(line);
+            let line = line.trim();
+            let path = if depth != 0 {
+                None
+            } else if let Some(header) = line.strip_prefix('[') {
+                // Either `[a.b]` or `[[a.b]]`
+                let header = header.strip_prefix('[').unwrap_or(header);
+                section = parse_key_path(header.trim_end_matches(']'))
+                    .unwrap_or_default();
+                Some(section.clone())
+            } else if let Some((key, _)) = line.split_once('=') {
+                parse_key_path(key).map(|k| [&section[..], &k[..]].concat())
+            } else {
+                None
+            };
+            depth += brackets;
+
+            let path = match path.as_deref() {
+                Some([append, rest @ ..]) if append == "append" => rest,
+                Some(p) => p,
+                None => continue,
+            };
+            let (task, rest) = match path {
+                [tasks, task, rest @ ..] if tasks == "tasks" => (task, rest),
+                _ => continue,
+            };
+            let key = match rest {
+                [name] if name == "name" => None,
+                [config, key, ..] if config == "config" => Some(key.clone()),
+                _ => continue,
+            };
+            found.entry((task.clone(), key)).or_insert(Location {
+                file: file.to_owned(),
+                line: i + 1,
+            });
+        }
+        self.0.extend(found);
+    }
+}
+
+/// Strips any comment from a line of TOML, also returning how many more
+/// brackets it opens than it closes (so that multi-line arrays can be
+/// skipped).  Escaped quotes in strings aren't handled.
+fn scan_line(line: &str) -> (&str, i32) {
+    let mut quote = None;
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, '#') => return (&line[..i], depth),
+            _ => (),
+        }
+    }
+    (line, depth)
+}
+
+/// Parses a dotted key path from a table header or the left-hand side of a
+/// key/value pair, returning `None` if `s` isn't one
+fn parse_key_path(s: &str) -> Option<Vec<String>> {
+    let s = s.trim();
+    let valid = |c: char| c.is_ascii_alphanumeric() || "_-.\" ".contains(c);
+    if !s.chars().all(valid) {
+        return None;
+    }
+    let path = split_key_path(s).ok()?;
+    Some(path.into_iter().map(|p| p.trim().to_owned()).collect())
+}
+
 /// Represents an MPU's desired alignment strategy
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum MpuAlignment {
@@ -660,7 +972,7 @@ fn default_name() -> String {
     "default".to_string()
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Output {
     #[serde(default = "default_name")]
@@ -703,7 +1015,7 @@ pub struct Task {
     pub uses_secure_entry: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Hash, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Peripheral {
     pub address: u32,
@@ -825,3 +1137,237 @@ impl BuildConfig<'_> {
         cmd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        name = "base"
+
+        [tasks.a]
+        priority = 1
+        features = ["x"]
+
+        [tasks.a.config]
+        keep = 1
+        over = 2
+
+        [tasks.b]
+        priority = 2
+        features = ["x"]
+    "#;
+
+    /// Writes `files` into a fresh directory, then loads the first one
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<Table> {
+        let dir = std::env::temp_dir().join(format!(
+            "xtask-config-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            std::fs::write(dir.join(file), contents).unwrap();
+        }
+        let out = load_layered(
+            &dir.join(files[0].0),
+            &mut vec![],
+            &mut TaskLocations::default(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        out
+    }
+
+    /// Loads an overlay on top of `BASE`
+    fn overlay(name: &str, overlay: &str) -> Result<Table> {
+        load(name, &[("overlay.toml", overlay), ("base.toml", BASE)])
+    }
+
+    fn table(s: &str) -> Table {
+        ordered_toml::from_slice(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn merges_nested_tables() {
+        let out = overlay(
+            "merge",
+            r#"
+            inherit = "base.toml"
+            name = "overlay"
+
+            [tasks.a]
+            priority = 3
+
+            [tasks.a.config]
+            over = 4
+            new = 5
+            "#,
+        )
+        .unwrap();
+        let expected = table(
+            r#"
+            name = "overlay"
+
+            [tasks.a]
+            priority = 3
+            features = ["x"]
+
+            [tasks.a.config]
+            keep = 1
+            over = 4
+            new = 5
+
+            [tasks.b]
+            priority = 2
+            features = ["x"]
+            "#,
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn replaces_or_appends_arrays() {
+        let out = overlay(
+            "append",
+            r#"
+            inherit = "base.toml"
+            name = "overlay"
+
+            [tasks.a]
+            features = ["y"]
+
+            [append.tasks.b]
+            features = ["z"]
+            uses = ["u"]
+            "#,
+        )
+        .unwrap();
+        let tasks = out["tasks"].as_table().unwrap();
+        let features = |t: &str| tasks[t]["features"].clone();
+        assert_eq!(features("a"), table("v = [\"y\"]")["v"]);
+        assert_eq!(features("b"), table("v = [\"x\", \"z\"]")["v"]);
+        assert_eq!(tasks["b"]["uses"], table("v = [\"u\"]")["v"]);
+    }
+
+    #[test]
+    fn deletes_paths() {
+        let out = overlay(
+            "delete",
+            r#"
+            inherit = "base.toml"
+            name = "overlay"
+            delete = ["tasks.b", "tasks.a.config.over"]
+            "#,
+        )
+        .unwrap();
+        let tasks = out["tasks"].as_table().unwrap();
+        assert!(!tasks.contains_key("b"));
+        let config = tasks["a"]["config"].as_table().unwrap();
+        assert!(config.contains_key("keep"));
+        assert!(!config.contains_key("over"));
+    }
+
+    #[test]
+    fn rejects_deleting_missing_paths() {
+        for (path, msg) in [
+            ("tasks.c", "no key named `c`"),
+            ("tasks.c.config", "no table named `c`"),
+            ("tasks.a.priority.x", "no table named `priority`"),
+        ] {
+            let err = overlay(
+                "delete-missing",
+                &format!(
+                    "inherit = \"base.toml\"\nname = \"o\"\ndelete = [{:?}]",
+                    path
+                ),
+            )
+            .unwrap_err();
+            let err = format!("{:#}", err);
+            assert!(
+                err.contains(&format!("could not delete `{}`", path)),
+                "{}",
+                err
+            );
+            assert!(err.contains(msg), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_inheritance_cycles() {
+        let err = load(
+            "self-cycle",
+            &[("a.toml", "inherit = \"a.toml\"\nname = \"a\"")],
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("a.toml inherits from itself"));
+
+        let err = load(
+            "cycle",
+            &[
+                ("a.toml", "inherit = \"b.toml\"\nname = \"a\""),
+                ("b.toml", "inherit = \"a.toml\"\nname = \"b\""),
+            ],
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("a.toml inherits from itself"));
+
+        // A longer chain of overlays is fine, as long as it ends
+        load(
+            "chain",
+            &[
+                ("a.toml", "inherit = \"b.toml\"\nname = \"a\""),
+                ("b.toml", "inherit = \"c.toml\"\nname = \"b\""),
+                ("c.toml", "name = \"c\""),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn task_locations() {
+        let base = r#"
+            name = "base"
+
+            [tasks.a]
+            name = "task-a"
+            pins = [
+                [1, 2], # not a header
+            ]
+            config = { inline = true }
+
+            [tasks.a.config]
+            x = "[tasks.b]" # nor is this
+            y = [
+                "z = 1",
+            ]
+
+            [[tasks.a.config.table-array]]
+            w = 1
+            [[tasks.a.config.table-array]]
+            w = 2
+        "#;
+        let overlay = r#"
+            [append.tasks.a.config]
+            y = ["more"]
+        "#;
+
+        let mut locations = TaskLocations::default();
+        locations.scan(Path::new("base.toml"), base);
+        locations.scan(Path::new("overlay.toml"), overlay);
+        let at = |file: &str, line| {
+            Some(Location {
+                file: file.into(),
+                line,
+            })
+        };
+        assert_eq!(locations.task("a").cloned(), at("base.toml", 5));
+        let key = |k| locations.config_key("a", k).cloned();
+        assert_eq!(key("inline"), None);
+        assert_eq!(key("x"), at("base.toml", 12));
+        assert_eq!(key("y"), at("overlay.toml", 3));
+        assert_eq!(key("z"), None);
+        assert_eq!(key("table-array"), at("base.toml", 17));
+        assert_eq!(key("w"), None);
+        assert_eq!(locations.task("b"), None);
+    }
+}
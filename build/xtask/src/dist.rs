@@ -18,8 +18,11 @@ use path_slash::PathBufExt;
 use zerocopy::AsBytes;
 
 use crate::{
-    config::{BuildConfig, Config, ConfigPatches},
+    config::{BuildConfig, Config},
     elf,
+    graph::TaskGraph,
+    ownership::Ownership,
+    schema,
     sizes::load_task_size,
     task_slot,
 };
@@ -36,15 +39,6 @@ pub const DEFAULT_KERNEL_STACK: u32 = 1024;
 /// It should be trivial to calculate and kept constant during the build;
 /// mutable build information should be accumulated elsewhere.
 pub struct PackageConfig {
-    /// Path to the `app.toml` file being built
-    ///
-    /// If this app is built using inheritance, `app_toml_file` refers to the
-    /// **root** TOML file (and patches are in `self.patches`)
-    app_toml_file: PathBuf,
-
-    /// Patches from TOML inheritance mechanism
-    patches: Option<ConfigPatches>,
-
     /// Directory containing the `app.toml` file being built
     app_src_dir: PathBuf,
 
@@ -118,8 +112,6 @@ impl PackageConfig {
         }
 
         Ok(Self {
-            app_toml_file: toml.app_toml_path.to_path_buf(),
-            patches: toml.patches.clone(),
             app_src_dir: app_src_dir.to_path_buf(),
             toml,
             verbose,
@@ -171,15 +163,20 @@ impl PackageConfig {
             .join("github.com-1ecc6299db9ec823");
         remap_paths.insert(cargo_registry, "/crates.io");
 
-        let mut hubris_dir =
-            dunce::canonicalize(std::env::var("CARGO_MANIFEST_DIR")?)?;
-        hubris_dir.pop();
-        hubris_dir.pop();
-        remap_paths.insert(hubris_dir.to_path_buf(), "/hubris");
+        remap_paths.insert(hubris_dir()?, "/hubris");
         Ok(remap_paths)
     }
 }
 
+/// Returns the root of the Hubris repository, i.e. two levels above `xtask`.
+fn hubris_dir() -> Result<PathBuf> {
+    let mut hubris_dir =
+        dunce::canonicalize(std::env::var("CARGO_MANIFEST_DIR")?)?;
+    hubris_dir.pop();
+    hubris_dir.pop();
+    Ok(hubris_dir)
+}
+
 pub fn list_tasks(app_toml: &Path) -> Result<()> {
     let toml = Config::from_file(app_toml)?;
     let pad = toml
@@ -215,6 +212,11 @@ pub fn package(
     dirty_ok: bool,
 ) -> Result<BTreeMap<String, AllocationMap>> {
     let cfg = PackageConfig::new(app_toml, verbose, edges)?;
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .no_deps()
+        .exec()
+        .context("could not run `cargo metadata`")?;
+    schema::check_task_configs(&cfg.toml, &metadata)?;
 
     // If we're using filters, we change behavior at the end. Record this in a
     // convenient flag, running other checks as well.
@@ -225,6 +227,8 @@ pub fn package(
         } else {
             assert!(!cfg.toml.tasks.contains_key("kernel"));
             check_task_priorities(&cfg.toml)?;
+            check_ownership(&cfg.toml)?;
+            check_udprpc_allowlist(&cfg.toml, &metadata)?;
             (
                 false,
                 cfg.toml
@@ -293,9 +297,8 @@ pub fn package(
             .keys()
             .map(|name| {
                 let ep = if tasks_to_build.contains(name.as_str()) {
-                    // Link tasks regardless of whether they have changed, because
-                    // we don't want to track changes in the other linker input
-                    // (task-link.x, memory.x, table.ld, etc)
+                    // `link_task` skips tasks whose linker inputs are
+                    // unchanged since the last time they were linked.
                     link_task(&cfg, name, image_name, allocs)?;
                     task_entry_point(
                         &cfg,
@@ -584,6 +587,38 @@ fn write_gdb_script(cfg: &PackageConfig, image_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Returns the allowlist of the app's `udprpc` task, or `None` if there's no
+/// `udprpc` task or it allows any op.
+fn udprpc_allowlist(
+    toml: &Config,
+) -> Result<Option<Vec<build_rpc::AllowConfig>>> {
+    let Some(task) = toml.tasks.values().find(|t| t.name == "task-udprpc")
+    else {
+        return Ok(None);
+    };
+    let config: build_rpc::RpcConfig = match &task.config {
+        Some(config) => toml::from_str(&toml::to_string(config)?)
+            .context("Could not parse udprpc config")?,
+        None => Default::default(),
+    };
+    Ok(config.allowlist()?.map(<[_]>::to_vec))
+}
+
+/// Describes the ops which `udprpc` allows to be called over the network, so
+/// that host tools can call them by name. Returns `None` if there's no
+/// `udprpc` task with an allowlist.
+fn udprpc_description(cfg: &PackageConfig) -> Result<Option<String>> {
+    let Some(allow) = udprpc_allowlist(&cfg.toml)? else {
+        return Ok(None);
+    };
+
+    let interfaces = build_rpc::resolve(&allow, &hubris_dir()?.join("idl"))?;
+    let description = build_rpc::client_description(&interfaces, |name| {
+        cfg.toml.tasks.get_index_of(name)
+    })?;
+    Ok(Some(description))
+}
+
 fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
     // Bundle everything up into an archive.
     let mut archive = Archive::new(
@@ -595,6 +630,8 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         "\
         This is a build archive containing firmware build artifacts.\n\n\
         - app.toml is the config file used to build the firmware.\n\
+        - udprpc.json describes the ops which may be called over the\n\
+          network, if udprpc is configured with an allowlist.\n\
         - git-rev is the commit it was built from, with optional dirty flag.\n\
         - info/ contains human-readable data like logs.\n\
         - elf/ contains ELF images for all firmware components.\n\
@@ -609,15 +646,15 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         "git-rev",
         format!("{}{}", git_rev, if git_dirty { "-dirty" } else { "" }),
     )?;
-    archive.copy(&cfg.app_toml_file, "app.toml")?;
-    if let Some(patches) = cfg.patches.as_ref() {
-        archive
-            .text(
-                "patches.toml",
-                toml::to_string(patches)
-                    .context("Could not serialize patches")?,
-            )
-            .context("Could not write patches.toml")?;
+    // Store the fully resolved config, so that tools reading the archive
+    // don't need to know about TOML inheritance.
+    archive.text(
+        "app.toml",
+        toml::to_string(&cfg.toml.resolved)
+            .context("Could not serialize app.toml")?,
+    )?;
+    if let Some(description) = udprpc_description(cfg)? {
+        archive.text("udprpc.json", description)?;
     }
     let chip_dir = cfg.app_src_dir.join(cfg.toml.chip.clone());
     let chip_file = chip_dir.join("chip.toml");
@@ -720,50 +757,64 @@ fn check_task_names(toml: &Config, task_names: &[String]) -> Result<()> {
     Ok(())
 }
 
-/// Checks the buildstamp file and runs `cargo clean` if invalid
+/// Compares each task's build fingerprint against the buildstamp file, and
+/// runs `cargo clean` on the crates whose configuration has changed.
 fn check_rebuild(toml: &Config) -> Result<()> {
     let buildstamp_file = Path::new("target").join("buildstamp");
-    let rebuild = match std::fs::read(&buildstamp_file) {
-        Ok(contents) => {
-            if let Ok(contents) = std::str::from_utf8(&contents) {
-                if let Ok(cmp) = u64::from_str_radix(contents, 16) {
-                    toml.buildhash != cmp
-                } else {
+    let mut stamps: BTreeMap<String, u64> =
+        match std::fs::read(&buildstamp_file) {
+            Ok(contents) => match serde_json::from_slice(&contents) {
+                Ok(stamps) => stamps,
+                Err(_) => {
                     println!("buildstamp file contents unknown; re-building.");
-                    true
+                    BTreeMap::new()
                 }
-            } else {
-                println!("buildstamp file contents corrupt; re-building.");
-                true
+            },
+            Err(_) => {
+                println!("no buildstamp file found; re-building.");
+                BTreeMap::new()
             }
+        };
+
+    let mut changed = vec![];
+    let mut names = BTreeSet::new();
+    for name in
+        std::iter::once("kernel").chain(toml.tasks.keys().map(String::as_str))
+    {
+        let fingerprint = toml.build_fingerprint(name);
+        if stamps.get(name) == Some(&fingerprint) {
+            continue;
         }
-        Err(_) => {
-            println!("no buildstamp file found; re-building.");
-            true
-        }
-    };
-    // if we need to rebuild, we should clean everything before we start building
-    if rebuild {
-        println!("app.toml has changed; rebuilding all tasks");
-        let mut names = vec![toml.kernel.name.as_str()];
-        for name in toml.tasks.keys() {
-            // This may feel redundant: don't we already have the name?
-            // Well, consider our supervisor:
-            //
-            // [tasks.jefe]
-            // name = "task-jefe"
-            //
-            // The "name" in the key is `jefe`, but the package (crate)
-            // name is in `tasks.jefe.name`, and that's what we need to
-            // give to `cargo`.
-            names.push(toml.tasks[name].name.as_str());
-        }
+        // This may feel redundant: don't we already have the name?
+        // Well, consider our supervisor:
+        //
+        // [tasks.jefe]
+        // name = "task-jefe"
+        //
+        // The "name" in the key is `jefe`, but the package (crate)
+        // name is in `tasks.jefe.name`, and that's what we need to
+        // give to `cargo`.
+        let crate_name = if name == "kernel" {
+            toml.kernel.name.as_str()
+        } else {
+            toml.tasks[name].name.as_str()
+        };
+        names.insert(crate_name);
+        changed.push(name);
+        stamps.insert(name.to_owned(), fingerprint);
+    }
+
+    // if we need to rebuild, we should clean those crates before we start
+    // building
+    if !changed.is_empty() {
+        println!("app.toml has changed; rebuilding {}", changed.join(", "));
+        let names = names.into_iter().collect::<Vec<_>>();
         cargo_clean(&names, &toml.target)?;
     }
 
     // now that we're clean, update our buildstamp file; any failure to build
     // from here on need not trigger a clean
-    std::fs::write(&buildstamp_file, format!("{:x}", toml.buildhash))?;
+    std::fs::write(&buildstamp_file, serde_json::to_vec_pretty(&stamps)?)?;
 
     Ok(())
 }
@@ -802,13 +853,25 @@ fn build_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         .context(format!("failed to build {}", name))
 }
 
-/// Link a specific task
+/// Link a specific task, unless it has already been linked with identical
+/// inputs.
 fn link_task(
     cfg: &PackageConfig,
     name: &str,
     image_name: &str,
     allocs: &Allocations,
 ) -> Result<()> {
+    let fingerprint = link_fingerprint(cfg, name, image_name, allocs)?;
+    let stamp_file = cfg.img_file(format!("{}.linkstamp", name), image_name);
+    let fresh = cfg.img_file(name, image_name).exists()
+        && std::fs::read_to_string(&stamp_file)
+            .map(|s| s == format!("{:x}", fingerprint))
+            .unwrap_or(false);
+    if fresh {
+        println!("task '{}' is unchanged; not relinking", name);
+        return Ok(());
+    }
+
     println!("linking task '{}'", name);
     let task_toml = &cfg.toml.tasks[name];
     generate_task_linker_script(
@@ -834,7 +897,49 @@ fn link_task(
         cfg,
         &format!("{}.elf", name),
         &format!("{}/{}", image_name, name),
-    )
+    )?;
+
+    std::fs::write(&stamp_file, format!("{:x}", fingerprint))?;
+    Ok(())
+}
+
+/// Returns a fingerprint of everything that feeds into the final link of a
+/// task: its compiled (relocatable) object, its build configuration, task
+/// slots, stack size, memory allocations, and the linker scripts.
+fn link_fingerprint(
+    cfg: &PackageConfig,
+    name: &str,
+    image_name: &str,
+    allocs: &Allocations,
+) -> Result<u64> {
+    let task_toml = &cfg.toml.tasks[name];
+    let mut hasher = fnv::FnvHasher::default();
+
+    cfg.toml.build_fingerprint(name).hash(&mut hasher);
+    for (slot, target) in &task_toml.task_slots {
+        slot.hash(&mut hasher);
+        target.hash(&mut hasher);
+    }
+    task_toml.stacksize.or(cfg.toml.stacksize).hash(&mut hasher);
+    allocs.tasks[name].hash(&mut hasher);
+    image_name.hash(&mut hasher);
+    for (image, range) in cfg.toml.all_regions("flash".to_string())? {
+        image.hash(&mut hasher);
+        range.hash(&mut hasher);
+    }
+
+    // `link_script_hash` covers the main linker scripts, but not the
+    // TrustZone script that's only used by some tasks.
+    cfg.link_script_hash.hash(&mut hasher);
+    if cfg.toml.need_tz_linker(name) {
+        std::fs::read("build/trustzone.x")?.hash(&mut hasher);
+    }
+
+    std::fs::read(cfg.dist_file(format!("{}.elf", name)))
+        .context(format!("could not read object for {}", name))?
+        .hash(&mut hasher);
+
+    Ok(hasher.finish())
 }
 
 /// Link a specific task using a dummy linker script that
@@ -943,17 +1048,20 @@ fn build_kernel(
 
     fs::copy("build/kernel-link.x", "target/link.x")?;
 
-    let image_id = image_id.finish();
+    let image_id = format!("{}", image_id.finish());
 
-    // Build the kernel.
-    let build_config = cfg.toml.kernel_build_config(
-        cfg.verbose,
-        &[
-            ("HUBRIS_KCONFIG", &kconfig),
-            ("HUBRIS_IMAGE_ID", &format!("{}", image_id)),
-        ],
-        Some(&cfg.sysroot),
-    );
+    // Build the kernel, showing its interrupt lookup table stats if we're
+    // being verbose.
+    let mut env = vec![
+        ("HUBRIS_KCONFIG", kconfig.as_str()),
+        ("HUBRIS_IMAGE_ID", image_id.as_str()),
+    ];
+    if cfg.verbose {
+        env.push(("HUBRIS_IRQ_STATS", "1"));
+    }
+    let build_config =
+        cfg.toml
+            .kernel_build_config(cfg.verbose, &env, Some(&cfg.sysroot));
     build(cfg, "kernel", build_config, false)?;
     if update_image_header(
         cfg,
@@ -1148,26 +1256,40 @@ fn update_image_header(
     Ok(false)
 }
 
-/// Prints warning messages about priority inversions
+/// Checks task priorities, returning an error if any task can SEND to a task
+/// of equal or lower priority, or if tasks can SEND to each other in a cycle.
 fn check_task_priorities(toml: &Config) -> Result<()> {
+    let graph = TaskGraph::new(toml)?;
+    let mut errors = 0;
+    for e in graph.inversions() {
+        eprint!("{}", "Priority inversion: ".red());
+        eprintln!(
+            "task {} (priority {}) calls into {} (priority {})",
+            e.from,
+            toml.tasks[e.from].priority,
+            e.to,
+            toml.tasks[e.to].priority
+        );
+        errors += 1;
+    }
+    for cycle in &graph.cycles {
+        eprint!("{}", "Send cycle: ".red());
+        eprintln!(
+            "tasks {} can send to each other and may deadlock",
+            cycle.join(", ")
+        );
+        errors += 1;
+    }
+    if errors > 0 {
+        bail!(
+            "found {} task priority error(s); use `cargo xtask graph` \
+             to inspect the task graph",
+            errors
+        );
+    }
+
     let idle_priority = toml.tasks["idle"].priority;
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
-        for callee in task.task_slots.values() {
-            let p = toml
-                .tasks
-                .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
-                .priority;
-            if p >= task.priority && name != callee {
-                // TODO: once all priority inversions are fixed, return an
-                // error so no more can be introduced
-                eprint!("{}", "Priority inversion: ".red());
-                eprintln!(
-                    "task {} (priority {}) calls into {} (priority {})",
-                    name, task.priority, callee, p
-                );
-            }
-        }
         if task.priority >= idle_priority && name != "idle" {
             bail!("task {} has priority that's >= idle priority", name);
         } else if i == 0 && task.priority != 0 {
@@ -1180,6 +1302,58 @@ fn check_task_priorities(toml: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Checks that no two tasks claim the same peripheral, pin, interrupt, or
+/// socket, unless the app declares that resource as `[shared]`.
+fn check_ownership(toml: &Config) -> Result<()> {
+    let conflicts = Ownership::new(toml)?.conflicts()?;
+    for c in &conflicts {
+        eprint!("{}", "Ownership conflict: ".red());
+        let claims: Vec<String> = c
+            .claims
+            .iter()
+            .map(|c| format!("{} ({})", c.task, c.via))
+            .collect();
+        eprintln!("{} is claimed by {}", c.resource, claims.join(", "));
+    }
+    if !conflicts.is_empty() {
+        bail!(
+            "found {} ownership conflict(s); list resources which are \
+             deliberately shared in the app's `[shared]` table",
+            conflicts.len()
+        );
+    }
+    Ok(())
+}
+
+/// Checks that each task in `udprpc`'s allowlist serves the interface that
+/// it's listed with, according to the `serves` list in its crate's
+/// `[package.metadata.idol]`.
+fn check_udprpc_allowlist(
+    toml: &Config,
+    metadata: &cargo_metadata::Metadata,
+) -> Result<()> {
+    let Some(allow) = udprpc_allowlist(toml)? else {
+        return Ok(());
+    };
+
+    build_rpc::check_servers(&allow, |task| {
+        let Some(t) = toml.tasks.get(task) else {
+            bail!("unknown task {} in udprpc allowlist", task);
+        };
+        let Some(p) = metadata.packages.iter().find(|p| p.name == t.name)
+        else {
+            bail!("could not find crate {} for task {}", t.name, task);
+        };
+        let Some(serves) = p.metadata.get("idol").and_then(|m| m.get("serves"))
+        else {
+            return Ok(vec![]);
+        };
+        serde_json::from_value(serves.clone()).with_context(|| {
+            format!("invalid `package.metadata.idol.serves` in {}", t.name)
+        })
+    })
+}
+
 fn generate_task_linker_script(
     name: &str,
     map: &BTreeMap<String, Range<u32>>,
@@ -1787,7 +1961,7 @@ pub fn make_kconfig(
     secure: &Option<SecureData>,
 ) -> Result<build_kconfig::KernelConfig> {
     let mut tasks = vec![];
-    let mut irqs = BTreeMap::new();
+    let irqs = resolve_irqs(toml)?;
 
     let p2_required = toml.mpu_power_of_two_required();
 
@@ -1858,7 +2032,7 @@ pub fn make_kconfig(
 
     let mut used_shared_regions = BTreeSet::new();
 
-    for (i, (name, task)) in toml.tasks.iter().enumerate() {
+    for (name, task) in &toml.tasks {
         let stacksize = task.stacksize.or(toml.stacksize).unwrap();
 
         let flash = &task_allocations[name]["flash"];
@@ -1928,8 +2102,25 @@ pub fn make_kconfig(
             priority: task.priority,
             start_at_boot: task.start,
         });
+    }
+
+    // Pare down the list of shared regions.
+    flat_shared.retain(|name, _v| used_shared_regions.contains(name));
+
+    Ok(build_kconfig::KernelConfig {
+        irqs,
+        tasks,
+        shared_regions: flat_shared,
+    })
+}
 
-        // Interrupts.
+/// Resolves every task's interrupts, given either as IRQ numbers or as
+/// `peripheral.interrupt` references, into a map from IRQ number to owner.
+pub fn resolve_irqs(
+    toml: &Config,
+) -> Result<BTreeMap<u32, build_kconfig::InterruptConfig>> {
+    let mut irqs = BTreeMap::new();
+    for (i, (name, task)) in toml.tasks.iter().enumerate() {
         for (irq_str, &notification) in &task.interrupts {
             // The irq_str can be either a base-ten number, or a reference to a
             // peripheral. Distinguish them based on whether it parses as an
@@ -2015,15 +2206,7 @@ pub fn make_kconfig(
             }
         }
     }
-
-    // Pare down the list of shared regions.
-    flat_shared.retain(|name, _v| used_shared_regions.contains(name));
-
-    Ok(build_kconfig::KernelConfig {
-        irqs,
-        tasks,
-        shared_regions: flat_shared,
-    })
+    Ok(irqs)
 }
 
 /// Loads an SREC file into the same representation we use for ELF. This is
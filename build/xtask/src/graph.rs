@@ -9,114 +9,285 @@ use std::io::Write;
 use std::path::Path;
 
 use anyhow::{anyhow, Result};
+use serde::Serialize;
 
 use crate::config::Config;
 
-/// Generate a directed graph of task priorities and task_slot
-/// dependencies.
-pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
-    // Generate dot syntax for a graph of process priorities.
-    // Collect each task in a priority group
-    // Collect each edge
-    let mut priorities = BTreeMap::new();
-    let mut edges = Vec::new();
-    let mut dot = File::create(path)?;
-    let mut ranks = HashSet::new();
-    let toml = Config::from_file(app_toml)?;
+/// A task in the IPC graph
+#[derive(Debug, Serialize)]
+pub struct Node<'a> {
+    pub name: &'a str,
+    pub priority: u8,
+}
 
-    #[derive(Debug)]
-    struct Edge<'a> {
-        from: &'a String,
-        to: &'a String,
-        inverted: bool,
-    }
+/// A `task_slot` dependency, i.e. a task which may SEND to another task
+#[derive(Debug, Serialize)]
+pub struct Edge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    /// Name of the task slot in the sending task
+    pub slot: &'a str,
+    /// The receiving task has the same or lower priority than the sender,
+    /// which is forbidden by Hubris's IPC rules.
+    pub inverted: bool,
+    /// Both ends of this edge are part of the same send cycle
+    pub in_cycle: bool,
+}
 
-    #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
-    struct Rank {
-        from: u8,
-        to: u8,
-    }
+/// Directed graph of the tasks in an app and the task slots between them,
+/// with analysis of priority inversions and send cycles.
+#[derive(Debug, Serialize)]
+pub struct TaskGraph<'a> {
+    pub app: String,
+    pub tasks: Vec<Node<'a>>,
+    pub edges: Vec<Edge<'a>>,
+    /// Groups of tasks which can (transitively) SEND to each other, and
+    /// could therefore deadlock.  Each group is listed in task order.
+    pub cycles: Vec<Vec<&'a str>>,
+}
 
-    for (_i, (name, task)) in toml.tasks.iter().enumerate() {
-        if !priorities.contains_key(&task.priority) {
-            priorities.insert(task.priority, Vec::new());
-        }
-        if let Some(v) = priorities.get_mut(&task.priority) {
-            v.push(name.to_string());
+impl<'a> TaskGraph<'a> {
+    pub fn new(toml: &'a Config) -> Result<Self> {
+        let tasks: Vec<Node> = toml
+            .tasks
+            .iter()
+            .map(|(name, task)| Node {
+                name: name.as_str(),
+                priority: task.priority,
+            })
+            .collect();
+
+        // Adjacency list by task index, used for cycle detection.  Tasks
+        // may send to themselves (e.g. the test suite), which is harmless, so
+        // self-edges are left out here.
+        let mut adjacent = vec![vec![]; tasks.len()];
+        let mut edges = vec![];
+        for (i, (name, task)) in toml.tasks.iter().enumerate() {
+            for (slot, callee) in &task.task_slots {
+                let j = toml
+                    .tasks
+                    .get_index_of(callee)
+                    .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?;
+                let p = tasks[j].priority;
+                if i != j {
+                    adjacent[i].push(j);
+                }
+                edges.push(Edge {
+                    from: name.as_str(),
+                    to: callee.as_str(),
+                    slot: slot.as_str(),
+                    inverted: p >= task.priority && name != callee,
+                    in_cycle: false,
+                });
+            }
         }
-        for callee in task.task_slots.values() {
-            let p = toml
-                .tasks
-                .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
-                .priority;
-            let inverted = p >= task.priority && name != callee;
-            edges.push(Edge {
-                from: &name,
-                to: &callee,
-                inverted,
-            });
-            let rank = Rank {
-                from: task.priority,
-                to: p,
-            };
-            if !ranks.contains::<Rank>(&rank) {
-                ranks.insert(rank);
+
+        let components = strongly_connected(&adjacent);
+        let mut component_of = vec![0; tasks.len()];
+        for (c, members) in components.iter().enumerate() {
+            for &i in members {
+                component_of[i] = c;
             }
         }
+        for e in edges.iter_mut() {
+            let i = toml.tasks.get_index_of(e.from).unwrap();
+            let j = toml.tasks.get_index_of(e.to).unwrap();
+            e.in_cycle = i != j && component_of[i] == component_of[j];
+        }
+        let cycles = components
+            .into_iter()
+            .filter(|c| c.len() > 1)
+            .map(|c| c.into_iter().map(|i| tasks[i].name).collect())
+            .collect();
+
+        Ok(Self {
+            app: toml.app_toml_path.display().to_string(),
+            tasks,
+            edges,
+            cycles,
+        })
+    }
+
+    /// Returns edges which SEND to a task of equal or lower priority
+    pub fn inversions(&self) -> impl Iterator<Item = &Edge<'a>> {
+        self.edges.iter().filter(|e| e.inverted)
     }
 
-    writeln!(dot, "digraph tasks {{")?;
-    writeln!(
-        dot,
-        "  labelloc=\"t\";\n  label=\"{}\";",
-        toml.app_toml_path.display()
-    )?;
-    for key in priorities.keys() {
-        if let Some(v) = priorities.get(key) {
+    fn priority(&self, name: &str) -> u8 {
+        self.tasks.iter().find(|t| t.name == name).unwrap().priority
+    }
+
+    /// Writes the graph in Graphviz dot syntax
+    pub fn write_dot(&self, dot: &mut impl Write) -> Result<()> {
+        #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
+        struct Rank {
+            from: u8,
+            to: u8,
+        }
+
+        // Collect each task in a priority group
+        let mut priorities: BTreeMap<u8, Vec<&str>> = BTreeMap::new();
+        for t in &self.tasks {
+            priorities.entry(t.priority).or_default().push(t.name);
+        }
+        let ranks: HashSet<Rank> = self
+            .edges
+            .iter()
+            .map(|e| Rank {
+                from: self.priority(e.from),
+                to: self.priority(e.to),
+            })
+            .collect();
+
+        writeln!(dot, "digraph tasks {{")?;
+        writeln!(dot, "  labelloc=\"t\";\n  label=\"{}\";", self.app)?;
+        for (key, v) in &priorities {
             writeln!(dot, "  {{\n    edge [ style=invis ];\n    rank=same;")?;
             for name in v {
+                let style = if self.cycles.iter().any(|c| c.contains(name)) {
+                    ", color=red"
+                } else {
+                    ""
+                };
                 writeln!(
                     dot,
-                    "    {} [ label=\"{}\\n{}\", shape=box ];",
-                    name, name, key
+                    "    {} [ label=\"{}\\n{}\", shape=box{} ];",
+                    name, name, key, style
                 )?;
             }
             writeln!(dot, "  }}")?;
         }
-    }
-    for edge in edges {
-        let attr = if edge.inverted {
-            " [color=red, penwidth=3]"
-        } else {
-            " [color=green]"
-        };
-        writeln!(dot, "  {} -> {}{};", edge.from, edge.to, attr)?;
-    }
-    let keys: Vec<&u8> = priorities.keys().collect();
-    let mut first = false;
-    for low_high in keys.windows(2) {
-        let low = low_high[0];
-        let high = low_high[1];
-        if !ranks.contains::<Rank>(&Rank {
-            from: *high,
-            to: *low,
-        }) {
-            if !first {
-                first = true;
+        for edge in &self.edges {
+            let attr = match (edge.inverted, edge.in_cycle) {
+                (_, true) => " [color=red, penwidth=3, style=dashed]",
+                (true, false) => " [color=red, penwidth=3]",
+                (false, false) => " [color=green]",
+            };
+            writeln!(dot, "  {} -> {}{};", edge.from, edge.to, attr)?;
+        }
+        let keys: Vec<&u8> = priorities.keys().collect();
+        let mut first = false;
+        for low_high in keys.windows(2) {
+            let low = low_high[0];
+            let high = low_high[1];
+            if !ranks.contains(&Rank {
+                from: *high,
+                to: *low,
+            }) {
+                if !first {
+                    first = true;
+                    writeln!(
+                        dot,
+                        "\n  # Force row ranking by priorities {:?}",
+                        keys
+                    )?;
+                }
+                writeln!(dot, "  # Adding {} -> {}", high, low)?;
+                let high_name = priorities[high][0];
+                let low_name = priorities[low][0];
                 writeln!(
                     dot,
-                    "\n  # Force row ranking by priorities {:?}",
-                    keys
+                    "  {} -> {} [style=invis];",
+                    high_name, low_name
                 )?;
             }
-            writeln!(dot, "  # Adding {} -> {}", high, low)?;
-            let high_name = &priorities.get(high).unwrap()[0];
-            let low_name = &priorities.get(low).unwrap()[0];
-            writeln!(dot, "  {} -> {} [style=invis];", high_name, low_name)?;
         }
+        writeln!(dot, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// Tarjan's algorithm, returning the strongly connected components of the
+/// graph in order of their lowest member.
+fn strongly_connected(adjacent: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        adjacent: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        out: Vec<Vec<usize>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next);
+        s.lowlink[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+
+        let adjacent = s.adjacent;
+        for &w in adjacent[v].iter() {
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.lowlink[v] = s.lowlink[v].min(s.lowlink[w]);
+                }
+                Some(i) if s.on_stack[w] => {
+                    s.lowlink[v] = s.lowlink[v].min(i);
+                }
+                Some(_) => (),
+            }
+        }
+
+        if Some(s.lowlink[v]) == s.index[v] {
+            let mut component = vec![];
+            loop {
+                let w = s.stack.pop().unwrap();
+                s.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            s.out.push(component);
+        }
+    }
+
+    let n = adjacent.len();
+    let mut s = State {
+        adjacent,
+        index: vec![None; n],
+        lowlink: vec![0; n],
+        on_stack: vec![false; n],
+        stack: vec![],
+        next: 0,
+        out: vec![],
+    };
+    for v in 0..n {
+        if s.index[v].is_none() {
+            visit(&mut s, v);
+        }
+    }
+    s.out.sort_unstable_by_key(|c| c[0]);
+    s.out
+}
+
+/// Generate a directed graph of task priorities and task_slot
+/// dependencies, in Graphviz dot syntax and (optionally) JSON.
+pub fn task_graph(
+    app_toml: &Path,
+    path: &Path,
+    json: Option<&Path>,
+) -> Result<()> {
+    let toml = Config::from_file(app_toml)?;
+    let graph = TaskGraph::new(&toml)?;
+
+    let mut dot = File::create(path)?;
+    graph.write_dot(&mut dot)?;
+
+    if let Some(json) = json {
+        let mut out = File::create(json)?;
+        serde_json::to_writer_pretty(&mut out, &graph)?;
+        writeln!(out)?;
+    }
+
+    for cycle in &graph.cycles {
+        eprintln!("Send cycle (possible deadlock): {}", cycle.join(" <-> "));
     }
-    writeln!(dot, "}}")?;
 
     Ok(())
 }
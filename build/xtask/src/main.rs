@@ -17,7 +17,11 @@ mod elf;
 mod flash;
 mod graph;
 mod humility;
+mod ownership;
+mod phash_bench;
 mod print;
+mod qemu;
+mod schema;
 mod sizes;
 mod task_slot;
 
@@ -103,6 +107,15 @@ enum Xtask {
         /// Write JSON out to a file?
         #[clap(long)]
         save: bool,
+
+        /// Instead of the memory map, list the largest symbols and crates
+        /// in the given task (or `kernel`)
+        #[clap(long, conflicts_with_all = &["compare", "save"])]
+        symbols: Option<String>,
+        /// Number of symbols and crates to list with `--symbols`
+        #[clap(long, default_value = "20", requires = "symbols")]
+        top: usize,
+
         /// Allow operation in a dirty checkout, i.e. don't clean before
         /// rebuilding even if it looks like we need to.
         #[clap(long)]
@@ -125,6 +138,25 @@ enum Xtask {
         args: HumilityArgs,
     },
 
+    /// Runs `xtask dist` and boots the resulting image under QEMU
+    ///
+    /// Only boards which QEMU models are supported.  QEMU does not model ITM,
+    /// so tasks should be built with the `semihosting` feature; semihosting
+    /// output is printed to stdout.
+    Run {
+        /// Do not build a new image; just run the existing one
+        #[clap(long, short)]
+        nobuild: bool,
+
+        /// Exit once the test runner reports a result, failing if any test
+        /// case failed
+        #[clap(long)]
+        test: bool,
+
+        #[clap(flatten)]
+        args: RunArgs,
+    },
+
     /// Runs `cargo clippy` on a specified task
     Clippy {
         /// Request verbosity from tools we shell out to.
@@ -151,6 +183,8 @@ enum Xtask {
     /// Generate a graph of task_slot dependencies ordered by priority.
     ///
     /// Priority inversions are denoted by thick red arrows.
+    /// Send cycles (possible deadlocks) are thick dashed red arrows, and the
+    /// tasks involved are outlined in red.
     /// Normal task_slot dependencies are thin green arrows.
     /// Example:
     ///
@@ -161,13 +195,27 @@ enum Xtask {
         /// Output file for Graphviz dot syntax graph.
         #[clap(short, long)]
         output: PathBuf,
+        /// Also write the graph and its analysis to this file as JSON.
+        #[clap(long)]
+        json: Option<PathBuf>,
         /// Path to the image configuration file, in TOML.
         cfg: PathBuf,
     },
 
+    /// Compare the kernel's IRQ lookup table strategies for each app.
+    ///
+    /// Builds a single-level perfect hash, a nested perfect hash, and a
+    /// sorted list from each app's IRQ maps, then prints their sizes and
+    /// lookup costs along with host lookup timings.
+    PhashBench {
+        /// Paths to the image configuration files, in TOML.
+        #[clap(required = true)]
+        cfgs: Vec<PathBuf>,
+    },
+
     /// Print out information related to the build.
     ///
-    /// Currently only useful to print the archive path, but may grow over time.
+    /// Prints either the archive path or the fully resolved configuration.
     Print {
         /// Path to the image configuration file, in TOML.
         cfg: PathBuf,
@@ -176,6 +224,10 @@ enum Xtask {
         #[clap(long)]
         archive: bool,
 
+        /// Print the configuration after applying any inheritance
+        #[clap(long, conflicts_with = "archive")]
+        config: bool,
+
         /// If there are multiple possible images, print this one
         #[clap(long)]
         image_name: Option<String>,
@@ -200,6 +252,24 @@ pub struct HumilityArgs {
     extra_options: Vec<String>,
 }
 
+#[derive(Clone, Debug, Parser)]
+pub struct RunArgs {
+    /// Path to the image configuration file, in TOML.
+    cfg: PathBuf,
+
+    /// Image name to run
+    #[clap(long)]
+    image_name: Option<String>,
+
+    /// Request verbosity from tools we shell out to.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Extra options to pass to QEMU (e.g. `-s -S` to wait for GDB)
+    #[clap(last = true)]
+    extra_options: Vec<String>,
+}
+
 fn main() -> Result<()> {
     // Check whether we're running from the right directory
     if let Ok(root_path) = std::env::var("CARGO_MANIFEST_DIR") {
@@ -252,14 +322,7 @@ fn run(xtask: Xtask) -> Result<()> {
             let chip = ["-c", crate::flash::chip_name(&toml.board)?];
             args.extra_options.push("--force".to_string());
 
-            let image_name = if let Some(ref name) = args.image_name {
-                if !toml.check_image_name(name) {
-                    bail!("Image name {} not declared in TOML", name);
-                }
-                name
-            } else {
-                &toml.image_names[0]
-            };
+            let image_name = toml.image_name(args.image_name.as_ref())?;
 
             humility::run(&args, &chip, Some("flash"), false, image_name)?;
         }
@@ -268,35 +331,33 @@ fn run(xtask: Xtask) -> Result<()> {
             cfg,
             compare,
             save,
+            symbols,
+            top,
             dirty,
         } => {
+            if let Some(name) = &symbols {
+                let toml = Config::from_file(&cfg)?;
+                if name != "kernel" && !toml.tasks.contains_key(name) {
+                    bail!(toml.task_name_suggestion(name));
+                }
+            }
             let allocs = dist::package(verbose, false, &cfg, None, dirty)?;
-            for (_, (a, _)) in allocs {
-                sizes::run(&cfg, &a, false, compare, save)?;
+            for (image_name, (a, _)) in allocs {
+                if let Some(name) = &symbols {
+                    sizes::symbols(&cfg, name, &image_name, top)?;
+                } else {
+                    sizes::run(&cfg, &a, false, compare, save)?;
+                }
             }
         }
         Xtask::Humility { args } => {
             let toml = Config::from_file(&args.cfg)?;
-            let image_name = if let Some(ref name) = args.image_name {
-                if !toml.check_image_name(name) {
-                    bail!("Image name {} not declared in TOML", name);
-                }
-                name
-            } else {
-                &toml.image_names[0]
-            };
+            let image_name = toml.image_name(args.image_name.as_ref())?;
             humility::run(&args, &[], None, true, image_name)?;
         }
         Xtask::Gdb { noflash, mut args } => {
             let toml = Config::from_file(&args.cfg)?;
-            let image_name = if let Some(ref name) = args.image_name {
-                if !toml.check_image_name(name) {
-                    bail!("Image name {} not declared in TOML", name);
-                }
-                name
-            } else {
-                &toml.image_names[0]
-            };
+            let image_name = toml.image_name(args.image_name.as_ref())?;
             if !noflash {
                 dist::package(args.verbose, false, &args.cfg, None, false)?;
                 // Delegate flashing to `humility gdb`, which also modifies
@@ -307,14 +368,7 @@ fn run(xtask: Xtask) -> Result<()> {
         }
         Xtask::Test { args, noflash } => {
             let toml = Config::from_file(&args.cfg)?;
-            let image_name = if let Some(ref name) = args.image_name {
-                if !toml.check_image_name(name) {
-                    bail!("Image name {} not declared in TOML", name);
-                }
-                name
-            } else {
-                &toml.image_names[0]
-            };
+            let image_name = toml.image_name(args.image_name.as_ref())?;
             if !noflash {
                 run(Xtask::Flash {
                     args: args.clone(),
@@ -323,6 +377,16 @@ fn run(xtask: Xtask) -> Result<()> {
             }
             humility::run(&args, &[], Some("test"), false, image_name)?;
         }
+        Xtask::Run {
+            nobuild,
+            test,
+            args,
+        } => {
+            if !nobuild {
+                dist::package(args.verbose, false, &args.cfg, None, false)?;
+            }
+            qemu::run(&args, test)?;
+        }
         Xtask::Clippy {
             verbose,
             cfg,
@@ -334,15 +398,19 @@ fn run(xtask: Xtask) -> Result<()> {
         Xtask::TaskSlots { task_bin } => {
             task_slot::dump_task_slot_table(&task_bin)?;
         }
-        Xtask::Graph { output, cfg } => {
-            graph::task_graph(&cfg, &output)?;
+        Xtask::Graph { output, json, cfg } => {
+            graph::task_graph(&cfg, &output, json.as_deref())?;
+        }
+        Xtask::PhashBench { cfgs } => {
+            phash_bench::run(&cfgs)?;
         }
         Xtask::Print {
             cfg,
             archive,
+            config,
             image_name,
         } => {
-            print::run(&cfg, archive, image_name)
+            print::run(&cfg, archive, config, image_name)
                 .context("could not print information about the build")?;
         }
     }
@@ -0,0 +1,496 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Build-time tracking of which task owns each piece of hardware.
+//!
+//! Resources are claimed through:
+//! - `uses` (peripherals) and `interrupts` in each task's configuration
+//! - GPIO pin lists in task configs (`build-lpc55pins` and `build-stm32pins`)
+//! - I2C controller pins in `[config.i2c]`, which belong to the task that
+//!   uses the controller
+//! - Sockets in `[config.net]`, which belong to their owner task on each
+//!   VLAN where they're bound (named like `udp:7@0x301`)
+//!
+//! Two tasks claiming the same resource (or peripherals with overlapping
+//! address ranges) is an error, unless the app lists them in `[shared]`:
+//! ```toml
+//! [shared]
+//! system_flash = ["net", "control_plane_agent"]
+//! PIO0_9 = ["sprot", "swd"]
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// Something which is owned by one or more tasks
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Resource {
+    Peripheral(String),
+    Interrupt(String),
+    Pin(String),
+    Socket {
+        kind: String,
+        port: u16,
+        vlan: Option<usize>,
+    },
+}
+
+/// The name of a resource, as used in `[shared]`
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Peripheral(s)
+            | Resource::Interrupt(s)
+            | Resource::Pin(s) => write!(f, "{}", s),
+            Resource::Socket {
+                kind,
+                port,
+                vlan: None,
+            } => write!(f, "{}:{}", kind, port),
+            Resource::Socket {
+                kind,
+                port,
+                vlan: Some(v),
+            } => write!(f, "{}:{}@{:#x}", kind, port, v),
+        }
+    }
+}
+
+/// A single task's claim on a resource
+#[derive(Clone, Debug)]
+pub struct Claim {
+    pub task: String,
+    /// Where in the config the claim comes from
+    pub via: String,
+}
+
+/// A resource which is claimed by more than one task without being shared
+#[derive(Debug)]
+pub struct Conflict {
+    pub resource: String,
+    pub claims: Vec<Claim>,
+}
+
+/// Our subset of the global `[config]` table; not `deny_unknown_fields`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct GlobalConfig {
+    i2c: Option<I2cConfig>,
+    net: Option<NetConfig>,
+}
+
+#[derive(Deserialize)]
+struct I2cConfig {
+    controllers: Vec<I2cController>,
+}
+
+#[derive(Deserialize)]
+struct I2cController {
+    controller: u8,
+    ports: BTreeMap<String, I2cPort>,
+}
+
+#[derive(Deserialize)]
+struct I2cPort {
+    pins: Vec<I2cPinSet>,
+    #[serde(default)]
+    muxes: Vec<I2cMux>,
+}
+
+#[derive(Deserialize)]
+struct I2cPinSet {
+    gpio_port: Option<String>,
+    pins: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct I2cMux {
+    enable: Option<I2cPinSet>,
+}
+
+#[derive(Deserialize)]
+struct NetConfig {
+    sockets: BTreeMap<String, SocketConfig>,
+    vlan: Option<VLanConfig>,
+}
+
+#[derive(Deserialize)]
+struct VLanConfig {
+    start: usize,
+    count: usize,
+}
+
+#[derive(Deserialize)]
+struct SocketConfig {
+    kind: String,
+    port: u16,
+    owner: SocketOwner,
+    vlans: Option<Vec<usize>>,
+}
+
+#[derive(Deserialize)]
+struct SocketOwner {
+    name: String,
+}
+
+/// A pin list entry in a task's config, in either the `build-lpc55pins` or
+/// the `build-stm32pins` format.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PinDecl {
+    Lpc55 { pin: Lpc55Pin },
+    Stm32 { port: char, pins: Vec<u8> },
+}
+
+#[derive(Deserialize)]
+struct Lpc55Pin {
+    port: usize,
+    pin: usize,
+}
+
+/// Checks whether a config array entry has the keys of a [`PinDecl`]
+fn is_pin_decl(v: &ordered_toml::Value) -> bool {
+    v.as_table().map_or(false, |t| {
+        t.contains_key("pin")
+            || (t.contains_key("port") && t.contains_key("pins"))
+    })
+}
+
+pub struct Ownership<'a> {
+    toml: &'a Config,
+    claims: BTreeMap<Resource, Vec<Claim>>,
+}
+
+impl<'a> Ownership<'a> {
+    pub fn new(toml: &'a Config) -> Result<Self> {
+        let mut out = Self {
+            toml,
+            claims: BTreeMap::new(),
+        };
+
+        for (name, task) in &toml.tasks {
+            for p in &task.uses {
+                out.claim(Resource::Peripheral(p.clone()), name, "uses");
+            }
+            for irq in task.interrupts.keys() {
+                out.claim(Resource::Interrupt(irq.clone()), name, "interrupts");
+            }
+
+            let config: BTreeMap<String, ordered_toml::Value> =
+                match &task.config {
+                    Some(c) => c.clone().try_into()?,
+                    None => BTreeMap::new(),
+                };
+            for (key, value) in config {
+                // Any array with an entry that looks like a pin declaration
+                // is a pin list, and every entry must then be well-formed.
+                let entries = match value.as_array() {
+                    Some(a) if a.iter().any(is_pin_decl) => a,
+                    _ => continue,
+                };
+                let via = format!("config.{}", key);
+                for (i, e) in entries.iter().enumerate() {
+                    let d: PinDecl =
+                        e.clone().try_into().with_context(|| {
+                            format!("task {}: bad pin in {}[{}]", name, via, i)
+                        })?;
+                    match d {
+                        PinDecl::Lpc55 { pin } => {
+                            let pin = format!("PIO{}_{}", pin.port, pin.pin);
+                            out.claim(Resource::Pin(pin), name, &via);
+                        }
+                        PinDecl::Stm32 { port, pins } => {
+                            for p in pins {
+                                let pin = format!("P{}{}", port, p);
+                                out.claim(Resource::Pin(pin), name, &via);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let global: GlobalConfig = match &toml.config {
+            Some(c) => c.clone().try_into()?,
+            None => GlobalConfig::default(),
+        };
+
+        for c in global.i2c.iter().flat_map(|i2c| &i2c.controllers) {
+            // I2C pins belong to whichever task drives the controller
+            let periph = format!("i2c{}", c.controller);
+            let owner = toml
+                .tasks
+                .iter()
+                .find(|(_, t)| t.uses.contains(&periph))
+                .map(|(name, _)| name.clone())
+                .unwrap_or_else(|| periph.clone());
+
+            for (port, p) in &c.ports {
+                let via = format!("config.i2c {} port {}", periph, port);
+                let enables = p.muxes.iter().filter_map(|m| m.enable.as_ref());
+                for set in p.pins.iter().chain(enables) {
+                    let gpio = set.gpio_port.as_ref().unwrap_or(port);
+                    for n in &set.pins {
+                        let pin = format!("P{}{}", gpio, n);
+                        out.claim(Resource::Pin(pin), &owner, &via);
+                    }
+                }
+            }
+        }
+
+        if let Some(net) = &global.net {
+            for (name, s) in &net.sockets {
+                // Sockets are bound on every VLAN unless they say otherwise
+                let vlans: Vec<Option<usize>> = match (&net.vlan, &s.vlans) {
+                    (None, _) => vec![None],
+                    (Some(v), None) => {
+                        (v.start..v.start + v.count).map(Some).collect()
+                    }
+                    (Some(_), Some(vids)) => {
+                        vids.iter().cloned().map(Some).collect()
+                    }
+                };
+                let via = format!("config.net socket {}", name);
+                for vlan in vlans {
+                    let r = Resource::Socket {
+                        kind: s.kind.clone(),
+                        port: s.port,
+                        vlan,
+                    };
+                    out.claim(r, &s.owner.name, &via);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn claim(&mut self, r: Resource, task: &str, via: &str) {
+        self.claims.entry(r).or_default().push(Claim {
+            task: task.to_string(),
+            via: via.to_string(),
+        });
+    }
+
+    /// Checks whether the `[shared]` table allows all of the given tasks to
+    /// share any of the named resources.
+    fn is_shared<'b>(
+        &self,
+        names: &[String],
+        mut tasks: impl Iterator<Item = &'b str>,
+    ) -> bool {
+        let allowed: BTreeSet<&str> = names
+            .iter()
+            .filter_map(|n| self.toml.shared.get(n))
+            .flatten()
+            .map(|t| t.as_str())
+            .collect();
+        tasks.all(|t| allowed.contains(t))
+    }
+
+    /// Returns every resource claimed by more than one task, along with
+    /// peripherals claimed by different tasks whose address ranges overlap,
+    /// excluding those which are declared in `[shared]`.
+    pub fn conflicts(&self) -> Result<Vec<Conflict>> {
+        for (name, tasks) in &self.toml.shared {
+            if !self.claims.keys().any(|r| r.to_string() == *name) {
+                bail!("[shared] lists {}, which no task claims", name);
+            }
+            for t in tasks {
+                if !self.toml.tasks.contains_key(t) {
+                    bail!(
+                        "[shared] {}: {}",
+                        name,
+                        self.toml.task_name_suggestion(t)
+                    );
+                }
+            }
+        }
+
+        let mut out = vec![];
+        for (r, claims) in &self.claims {
+            let tasks: BTreeSet<&str> =
+                claims.iter().map(|c| c.task.as_str()).collect();
+            if tasks.len() > 1
+                && !self.is_shared(&[r.to_string()], tasks.into_iter())
+            {
+                out.push(Conflict {
+                    resource: r.to_string(),
+                    claims: claims.clone(),
+                });
+            }
+        }
+
+        // Peripherals with different names may still cover the same
+        // registers, e.g. if their sizes were rounded up for the MPU.
+        let ranges: Vec<(&String, std::ops::Range<u64>, &Vec<Claim>)> = self
+            .claims
+            .iter()
+            .filter_map(|(r, claims)| match r {
+                Resource::Peripheral(p) => {
+                    let periph = self.toml.peripherals.get(p)?;
+                    let start = u64::from(periph.address);
+                    Some((p, start..start + u64::from(periph.size), claims))
+                }
+                _ => None,
+            })
+            .collect();
+        for (i, (a, ra, ca)) in ranges.iter().enumerate() {
+            for (b, rb, cb) in &ranges[i + 1..] {
+                if ra.start >= rb.end || rb.start >= ra.end {
+                    continue;
+                }
+                let tasks: BTreeSet<&str> = ca
+                    .iter()
+                    .chain(cb.iter())
+                    .map(|c| c.task.as_str())
+                    .collect();
+                if tasks.len() > 1
+                    && !self.is_shared(
+                        &[a.to_string(), b.to_string()],
+                        tasks.into_iter(),
+                    )
+                {
+                    out.push(Conflict {
+                        resource: format!(
+                            "{} and {} (overlapping address ranges)",
+                            a, b
+                        ),
+                        claims: ca.iter().chain(cb.iter()).cloned().collect(),
+                    });
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peripherals for the test apps: `a` and `b` are exactly adjacent, and
+    /// `c` overlaps the end of `a` and the start of `b`.
+    const CHIP: &str = r#"
+        a = { address = 0x1000, size = 0x100 }
+        b = { address = 0x1100, size = 0x100 }
+        c = { address = 0x1080, size = 0x100 }
+    "#;
+
+    /// Loads an app whose tasks use the given peripherals, along with an
+    /// optional `[shared]` table.
+    fn app(name: &str, uses: &[(&str, &[&str])], shared: &str) -> Config {
+        let dir = std::env::temp_dir().join(format!(
+            "xtask-ownership-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(dir.join("chip")).unwrap();
+        std::fs::write(dir.join("chip/chip.toml"), CHIP).unwrap();
+        std::fs::write(dir.join("chip/memory.toml"), "").unwrap();
+
+        let mut toml = String::from(
+            r#"
+            name = "test"
+            target = "thumbv7em-none-eabihf"
+            board = "test"
+            chip = "chip"
+
+            [kernel]
+            name = "kernel"
+            requires = {flash = 1024, ram = 1024}
+            "#,
+        );
+        for (i, (task, periphs)) in uses.iter().enumerate() {
+            toml += &format!(
+                "[tasks.{}]\nname = \"{}\"\npriority = {}\nuses = {:?}\n",
+                task, task, i, periphs
+            );
+        }
+        toml += &format!("[shared]\n{}\n", shared);
+        std::fs::write(dir.join("app.toml"), toml).unwrap();
+
+        let out = Config::from_file(&dir.join("app.toml")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        out
+    }
+
+    #[test]
+    fn conflicts() {
+        let overlap = |a: &str, b: &str| {
+            format!("{} and {} (overlapping address ranges)", a, b)
+        };
+        let cases: &[(&str, &[(&str, &[&str])], &str, Vec<String>)] = &[
+            (
+                "same",
+                &[("x", &["a"]), ("y", &["a"])],
+                "",
+                vec!["a".to_string()],
+            ),
+            (
+                "shared",
+                &[("x", &["a"]), ("y", &["a"])],
+                r#"a = ["x", "y"]"#,
+                vec![],
+            ),
+            (
+                "partly-shared",
+                &[("x", &["a"]), ("y", &["a"]), ("z", &["a"])],
+                r#"a = ["x", "y"]"#,
+                vec!["a".to_string()],
+            ),
+            (
+                "overlapping",
+                &[("x", &["a"]), ("y", &["c"])],
+                "",
+                vec![overlap("a", "c")],
+            ),
+            ("adjacent", &[("x", &["a"]), ("y", &["b"])], "", vec![]),
+            ("overlapping-same-task", &[("x", &["a", "c"])], "", vec![]),
+            (
+                "overlapping-shared",
+                &[("x", &["a"]), ("y", &["c"])],
+                r#"a = ["x", "y"]"#,
+                vec![],
+            ),
+            (
+                "overlapping-both",
+                &[("x", &["a"]), ("y", &["b"]), ("z", &["c"])],
+                "",
+                vec![overlap("a", "c"), overlap("b", "c")],
+            ),
+        ];
+
+        for (name, uses, shared, expected) in cases {
+            let toml = app(name, uses, shared);
+            let found: Vec<String> = Ownership::new(&toml)
+                .unwrap()
+                .conflicts()
+                .unwrap()
+                .into_iter()
+                .map(|c| c.resource)
+                .collect();
+            assert_eq!(&found, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn bad_shared() {
+        let cases: &[(&str, &str)] = &[
+            ("unclaimed", r#"b = ["x", "y"]"#),
+            ("unknown-task", r#"a = ["x", "w"]"#),
+        ];
+        for (name, shared) in cases {
+            let toml = app(name, &[("x", &["a"]), ("y", &["a"])], shared);
+            let r = Ownership::new(&toml).unwrap().conflicts();
+            assert!(r.is_err(), "{}", name);
+        }
+    }
+}
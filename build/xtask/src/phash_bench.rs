@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Host-side comparison of the kernel's IRQ lookup table strategies.
+//!
+//! For each app, this builds every table strategy from `phash-gen` for the
+//! app's actual IRQ-to-task and task-to-IRQ maps, then reports each table's
+//! size and lookup cost along with a rough host lookup time.  The kernel
+//! build picks the first strategy that works, so this is mostly useful for
+//! seeing how much headroom an app has before it falls back.
+
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Result;
+
+use phash::PerfectHash;
+use phash_gen::{
+    OwnedNestedPerfectHashMap, OwnedPerfectHashMap, OwnedSortedList, Stats,
+};
+
+use crate::config::Config;
+
+/// Number of passes over the keys when timing lookups
+const ROUNDS: usize = 100_000;
+
+/// Times lookups of every key, returning the average time per lookup in
+/// nanoseconds.
+fn time_lookups<K: Copy, V>(keys: &[K], get: impl Fn(K) -> Option<V>) -> f64 {
+    if keys.is_empty() {
+        return 0.0;
+    }
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for &k in keys {
+            black_box(get(black_box(k)));
+        }
+    }
+    start.elapsed().as_nanos() as f64 / (ROUNDS * keys.len()) as f64
+}
+
+fn report(stats: Stats, ns: f64) {
+    println!("    {}", stats);
+    println!("      {:.1} ns per lookup on this host", ns);
+}
+
+/// Builds and times each strategy for the given map.  Empty slots in the
+/// perfect hashes are filled with `invalid`, as the kernel build does.
+fn bench<K, V>(name: &str, values: Vec<(K, V)>, invalid: (K, V))
+where
+    K: PerfectHash + Hash + Eq + Ord + Copy,
+    V: Clone,
+{
+    println!("  {} ({} entries)", name, values.len());
+    let keys: Vec<K> = values.iter().map(|(k, _)| *k).collect();
+    let fill =
+        |v: &Option<(K, V)>| v.clone().unwrap_or_else(|| invalid.clone());
+
+    match OwnedPerfectHashMap::build(values.clone()) {
+        Ok(map) => {
+            let flat: Vec<(K, V)> = map.values.iter().map(fill).collect();
+            let table = phash::PerfectHashMap {
+                m: map.m,
+                shift: map.shift,
+                values: &flat,
+            };
+            report(map.stats(), time_lookups(&keys, |k| table.get(k)));
+        }
+        Err(e) => println!("    PerfectHashMap: {}", e),
+    }
+
+    match OwnedNestedPerfectHashMap::build(values.clone()) {
+        Ok(map) => {
+            let nested: Vec<Vec<(K, V)>> = map
+                .values
+                .iter()
+                .map(|vs| vs.iter().map(fill).collect())
+                .collect();
+            let slices: Vec<&[(K, V)]> =
+                nested.iter().map(|v| v.as_slice()).collect();
+            let table = phash::NestedPerfectHashMap {
+                m: map.m,
+                shift: map.shift,
+                g: &map.g,
+                values: &slices,
+            };
+            report(map.stats(), time_lookups(&keys, |k| table.get(k)));
+        }
+        Err(e) => println!("    NestedPerfectHashMap: {}", e),
+    }
+
+    match OwnedSortedList::build(values) {
+        Ok(list) => {
+            let table = phash::SortedList {
+                values: &list.values,
+            };
+            report(list.stats(), time_lookups(&keys, |k| table.get(k)));
+        }
+        Err(e) => println!("    SortedList: {}", e),
+    }
+}
+
+pub fn run(cfgs: &[PathBuf]) -> Result<()> {
+    for cfg in cfgs {
+        let toml = Config::from_file(cfg)?;
+        let irqs = crate::dist::resolve_irqs(&toml)?;
+        println!("{} ({})", toml.name, cfg.display());
+
+        let irq_task: Vec<_> = irqs
+            .iter()
+            .map(|(&irq, c)| {
+                let owner = abi::InterruptOwner {
+                    task: c.task_index as u32,
+                    notification: c.notification,
+                };
+                (abi::InterruptNum(irq), owner)
+            })
+            .collect();
+
+        let mut task_irq: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for &(irq, owner) in &irq_task {
+            task_irq.entry(owner).or_default().push(irq);
+        }
+
+        bench(
+            "IRQ-to-task",
+            irq_task,
+            (abi::InterruptNum::invalid(), abi::InterruptOwner::invalid()),
+        );
+        bench(
+            "task-to-IRQ",
+            task_irq.into_iter().collect(),
+            (abi::InterruptOwner::invalid(), vec![]),
+        );
+    }
+    Ok(())
+}
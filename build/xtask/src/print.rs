@@ -6,14 +6,23 @@ use std::path::Path;
 
 use anyhow::{bail, Context, Error, Result};
 
+use crate::config::Config;
 use crate::dist::PackageConfig;
 
 pub fn run(
     cfg: &Path,
     archive: bool,
+    config: bool,
     image_name: Option<String>,
 ) -> Result<()> {
-    if archive {
+    if config {
+        let toml = Config::from_file(cfg)?;
+        print!(
+            "{}",
+            toml::to_string(&toml.resolved)
+                .context("could not serialize config")?
+        );
+    } else if archive {
         let config = PackageConfig::new(cfg, false, false)
             .context("could not create build configuration")?;
 
@@ -31,7 +40,10 @@ pub fn run(
 
         println!("{}", final_path.display());
     } else {
-        bail!("I'm not sure what to print. Currently supported: --archive");
+        bail!(
+            "I'm not sure what to print. \
+             Currently supported: --archive, --config"
+        );
     }
 
     Ok(())
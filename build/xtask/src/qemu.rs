@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{Config, RunArgs};
+
+/// Returns the QEMU machine which models the given board
+pub fn machine(board: &str) -> Result<&'static str> {
+    let m = match board {
+        // The Netduino Plus 2 is built around an STM32F405, which has the
+        // same core, memory map, and basic peripherals as the F407 on the
+        // Discovery board.
+        "stm32f4-discovery" => "netduinoplus2",
+        _ => bail!("board {} cannot be run under QEMU", board),
+    };
+    Ok(m)
+}
+
+/// Boots a previously built image under QEMU, with semihosting output going
+/// to stdout.
+///
+/// If `test` is true, the image is expected to contain the test runner; we
+/// stop QEMU once it reports a result, and return an error if any test case
+/// failed.
+pub fn run(args: &RunArgs, test: bool) -> Result<()> {
+    let toml = Config::from_file(&args.cfg)?;
+    let image_name = toml.image_name(args.image_name.as_ref())?;
+    let machine = machine(&toml.board)?;
+
+    // QEMU doesn't model the ITM, so anything logged through it vanishes.
+    let uses_itm = toml.kernel.features.iter().any(|f| f == "itm")
+        || toml
+            .tasks
+            .values()
+            .any(|t| t.features.iter().any(|f| f == "itm"));
+    if uses_itm {
+        eprintln!(
+            "warning: QEMU does not model ITM, so output from tasks built \
+             with the `itm` feature will be lost; use `semihosting` instead"
+        );
+    }
+
+    let elf = Path::new("target")
+        .join(&toml.name)
+        .join("dist")
+        .join(image_name)
+        .join("final.elf");
+
+    let qemu_path = match env::var("HUBRIS_QEMU_PATH") {
+        Ok(path) => path,
+        _ => "qemu-system-arm".to_string(),
+    };
+
+    let mut qemu = Command::new(qemu_path);
+    qemu.arg("-machine")
+        .arg(machine)
+        .arg("-nographic")
+        .arg("-semihosting-config")
+        .arg("enable=on,target=native")
+        .arg("-kernel")
+        .arg(&elf);
+    for opt in &args.extra_options {
+        qemu.arg(opt);
+    }
+    if args.verbose {
+        println!("running {:?}", qemu);
+    }
+
+    if !test {
+        // Let QEMU decide what to do with Ctrl-C, rather than exiting out
+        // from underneath it.
+        ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
+        let status = qemu
+            .status()
+            .with_context(|| format!("failed to run QEMU ({:?})", qemu))?;
+        if !status.success() {
+            bail!("QEMU failed");
+        }
+        return Ok(());
+    }
+
+    // The test runner reports `done pass` or `done FAIL` once every case
+    // has run; echo its output while watching for that line.
+    let mut child = qemu
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run QEMU ({:?})", qemu))?;
+    let stdout = child.stdout.take().context("Failed to take stdout")?;
+    let mut passed = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        println!("{}", line);
+        if let Some(status) = line.trim().strip_prefix("done ") {
+            passed = Some(status == "pass");
+            break;
+        }
+    }
+    // QEMU keeps running the idle task forever, so stop it ourselves
+    let _ = child.kill();
+    child.wait()?;
+
+    match passed {
+        Some(true) => Ok(()),
+        Some(false) => bail!("test suite failed"),
+        None => bail!("QEMU exited before the test suite finished"),
+    }
+}
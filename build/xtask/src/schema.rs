@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Validation of `[tasks.X.config]` blocks against schemas declared by task
+//! crates.
+//!
+//! A task crate declares its schema in its `Cargo.toml`:
+//! ```toml
+//! [package.metadata.task-config]
+//! bar = { type = "integer", doc = "Number of widgets" }
+//! baz = { type = "array", items = "integer", doc = "Widget IDs", default = [] }
+//! ```
+//!
+//! Every key in the task's config must be in the schema, must have the
+//! declared type, and must be present unless the schema gives a `default`
+//! or marks it as `optional`.
+//! Tasks which don't declare a schema are not checked.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use colored::*;
+use ordered_toml::Value;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum ValueType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Array,
+    Table,
+    Any,
+}
+
+impl ValueType {
+    fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Integer => "integer",
+            ValueType::Float => "float",
+            ValueType::Bool => "bool",
+            ValueType::Array => "array",
+            ValueType::Table => "table",
+            ValueType::Any => "any",
+        }
+    }
+
+    fn matches(self, v: &Value) -> bool {
+        match self {
+            ValueType::String => v.is_str(),
+            ValueType::Integer => v.is_integer(),
+            ValueType::Float => v.is_float() || v.is_integer(),
+            ValueType::Bool => v.is_bool(),
+            ValueType::Array => v.is_array(),
+            ValueType::Table => v.is_table(),
+            ValueType::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct FieldSchema {
+    #[serde(rename = "type")]
+    ty: ValueType,
+    /// Element type, for arrays
+    items: Option<ValueType>,
+    doc: String,
+    default: Option<serde_json::Value>,
+    /// The key may be omitted, even though there's no default value
+    #[serde(default)]
+    optional: bool,
+}
+
+type Schema = BTreeMap<String, FieldSchema>;
+
+/// Loads the config schema (if any) for every task crate in the workspace,
+/// indexed by crate name.
+fn load_schemas(
+    metadata: &cargo_metadata::Metadata,
+) -> Result<BTreeMap<String, Schema>> {
+    let mut out = BTreeMap::new();
+    for p in &metadata.packages {
+        if let Some(s) = p.metadata.get("task-config") {
+            let schema: Schema = serde_json::from_value(s.clone())
+                .with_context(|| {
+                    format!("invalid task-config schema in {}", p.name)
+                })?;
+            out.insert(p.name.clone(), schema);
+        }
+    }
+    Ok(out)
+}
+
+/// Checks a single task's config against its schema, returning a list of
+/// errors, each with the config key that it's about (if any).
+fn check_task(
+    config: Option<&Value>,
+    schema: &Schema,
+) -> Vec<(Option<String>, String)> {
+    let empty = ordered_toml::value::Table::new();
+    let table = match config {
+        None => &empty,
+        Some(Value::Table(t)) => t,
+        Some(_) => return vec![(None, "config must be a table".into())],
+    };
+
+    let mut errors = vec![];
+    for (k, v) in table {
+        let field = match schema.get(k) {
+            Some(f) => f,
+            None => {
+                let mut msg = format!("unknown config key `{}`", k);
+                if let Some(s) = schema
+                    .keys()
+                    .filter(|s| strsim::damerau_levenshtein(k, s) <= 3)
+                    .min_by_key(|s| strsim::damerau_levenshtein(k, s))
+                {
+                    msg += &format!("; did you mean `{}`?", s);
+                }
+                errors.push((Some(k.clone()), msg));
+                continue;
+            }
+        };
+
+        if !field.ty.matches(v) {
+            let msg = format!(
+                "`{}` should be {} ({}), not {}",
+                k,
+                field.ty.name(),
+                field.doc,
+                v.type_str()
+            );
+            errors.push((Some(k.clone()), msg));
+        } else if let (Some(items), Some(a)) = (field.items, v.as_array()) {
+            if let Some((i, e)) =
+                a.iter().enumerate().find(|(_, e)| !items.matches(e))
+            {
+                let msg = format!(
+                    "`{}[{}]` should be {} ({}), not {}",
+                    k,
+                    i,
+                    items.name(),
+                    field.doc,
+                    e.type_str()
+                );
+                errors.push((Some(k.clone()), msg));
+            }
+        }
+    }
+
+    for (k, field) in schema {
+        if field.default.is_none() && !field.optional && !table.contains_key(k)
+        {
+            let msg = format!("missing config key `{}` ({})", k, field.doc);
+            errors.push((None, msg));
+        }
+    }
+    errors
+}
+
+/// Checks every task's config block against the schema declared by its
+/// crate (as found in `metadata`), printing every error that's found along
+/// with the file and line it comes from.
+pub fn check_task_configs(
+    toml: &Config,
+    metadata: &cargo_metadata::Metadata,
+) -> Result<()> {
+    let schemas = load_schemas(metadata)?;
+
+    let mut count = 0;
+    for (name, task) in &toml.tasks {
+        let schema = match schemas.get(&task.name) {
+            Some(s) => s,
+            None => continue,
+        };
+        for (key, msg) in check_task(task.config.as_ref(), schema) {
+            let locations = &toml.task_locations;
+            let location = match key
+                .and_then(|k| locations.config_key(name, &k))
+                .or_else(|| locations.task(name))
+            {
+                Some(loc) => loc.to_string(),
+                None => toml.app_toml_path.display().to_string(),
+            };
+            eprint!("{}", "Config error: ".red());
+            eprintln!("{}: task {}: {}", location, name, msg);
+            count += 1;
+        }
+    }
+
+    if count > 0 {
+        bail!("found {} task config error(s)", count);
+    }
+    Ok(())
+}
@@ -381,6 +381,116 @@ pub fn load_task_size<'a>(
     Ok(memory_sizes)
 }
 
+/// Size of a group of symbols with the same (demangled) name
+#[derive(Default)]
+struct SymbolSize {
+    size: u64,
+    count: usize,
+}
+
+/// Prints the largest symbols in the given task (or kernel), and the total
+/// size of its symbols grouped by crate, for each memory region.
+///
+/// This reads the final linked ELF file for the image, i.e. the same file
+/// that's included in the build archive.
+pub fn symbols(
+    cfg: &Path,
+    name: &str,
+    image_name: &str,
+    top: usize,
+) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    let elf_name = Path::new("target")
+        .join(&toml.name)
+        .join("dist")
+        .join(image_name)
+        .join(name);
+    let buffer = std::fs::read(&elf_name)?;
+    let elf = match Object::parse(&buffer)? {
+        Object::Elf(elf) => elf,
+        o => bail!("Invalid Object {:?}", o),
+    };
+
+    // Map of region -> demangled symbol name -> size
+    let mut by_symbol: BTreeMap<&str, BTreeMap<String, SymbolSize>> =
+        BTreeMap::new();
+    for sym in elf.syms.iter() {
+        if sym.st_size == 0
+            || !matches!(
+                sym.st_type(),
+                goblin::elf::sym::STT_FUNC | goblin::elf::sym::STT_OBJECT
+            )
+        {
+            continue;
+        }
+        let region = match toml.output_region(sym.st_value) {
+            Some(r) => r,
+            None => continue,
+        };
+        let mangled = elf.strtab.get_at(sym.st_name).unwrap_or("");
+        // The alternate format strips the trailing hash, so that copies of a
+        // generic function from different crates are grouped together.
+        let demangled = format!("{:#}", rustc_demangle::demangle(mangled));
+        let s = by_symbol
+            .entry(region)
+            .or_default()
+            .entry(demangled)
+            .or_default();
+        s.size += sym.st_size;
+        s.count += 1;
+    }
+
+    for (region, symbols) in &by_symbol {
+        let mut by_crate: BTreeMap<&str, SymbolSize> = BTreeMap::new();
+        for (sym, size) in symbols {
+            let c = by_crate.entry(symbol_crate(sym)).or_default();
+            c.size += size.size;
+            c.count += size.count;
+        }
+        let total: u64 = symbols.values().map(|s| s.size).sum();
+
+        let mut symbols: Vec<_> = symbols.iter().collect();
+        symbols.sort_by(|a, b| b.1.size.cmp(&a.1.size));
+        let mut by_crate: Vec<_> = by_crate.into_iter().collect();
+        by_crate.sort_by(|a, b| b.1.size.cmp(&a.1.size));
+
+        println!(
+            "{}",
+            format!("\n{} {} ({} bytes in symbols)", name, region, total)
+                .bold()
+        );
+        println!("\n  {:>7}  {:>5}  CRATE", "SIZE", "COUNT");
+        for (krate, size) in by_crate.iter().take(top) {
+            println!("  {:>7}  {:>5}  {}", size.size, size.count, krate);
+        }
+        println!("\n  {:>7}  {:>5}  SYMBOL", "SIZE", "COUNT");
+        for (sym, size) in symbols.iter().take(top) {
+            println!("  {:>7}  {:>5}  {}", size.size, size.count, sym);
+        }
+    }
+
+    Ok(())
+}
+
+/// Guesses the crate which a demangled symbol name belongs to
+///
+/// For trait impls (`<foo::Bar as core::fmt::Debug>::fmt`), this is the crate
+/// of the implementing type.  Symbols which don't look like Rust paths (e.g.
+/// from assembly or C) are lumped together.
+fn symbol_crate(sym: &str) -> &str {
+    let sym = sym.trim_start_matches(|c| c == '<' || c == '&');
+    let sym = sym.strip_prefix("mut ").unwrap_or(sym);
+    match sym.split_once("::") {
+        Some((krate, _))
+            if !krate.is_empty()
+                && krate.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            krate
+        }
+        _ => "[other]",
+    }
+}
+
 fn create_sizes(toml: &Config) -> Result<TaskSizes> {
     let mut sizes = IndexMap::new();
 
@@ -9,6 +9,7 @@
 use core::ops::Deref;
 
 use drv_spi_api::SpiError;
+pub use fpga_regmap::{Readable, Register, Writable};
 use userlib::*;
 use zerocopy::{AsBytes, BigEndian, FromBytes, U32};
 
@@ -304,6 +305,26 @@ impl FpgaUserDesign {
         self.server
             .user_design_write(self.device_index, op, addr.into(), data)
     }
+
+    /// Reads a typed register generated by `build-fpga-regmap`
+    #[inline]
+    pub fn read_reg<R: Readable>(&self) -> Result<R, FpgaError> {
+        let mut v = 0u8;
+        self.read_bytes(R::ADDR, v.as_bytes_mut())?;
+        Ok(R::from_raw(v))
+    }
+
+    /// Writes a typed register generated by `build-fpga-regmap`.  For
+    /// `WriteOp::BitSet` and `WriteOp::BitClear`, `value` is used as the mask
+    /// of bits to set or clear.
+    #[inline]
+    pub fn write_reg<R: Writable>(
+        &self,
+        op: WriteOp,
+        value: R,
+    ) -> Result<(), FpgaError> {
+        self.write_bytes(op, R::ADDR, &[value.raw()])
+    }
 }
 
 /// Poll the device state of the FPGA to determine if it is either ready to receive
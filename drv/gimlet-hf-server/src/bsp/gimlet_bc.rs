@@ -24,30 +24,9 @@ pub(crate) fn init(qspi: &Qspi, sys: &sys_api::Sys) -> Config {
     // PB2 SP_FLASH_TO_SP_RESET_L
     // PB1 SP_TO_SP3_FLASH_MUX_SELECT <-- low means us
     //
-    sys.gpio_configure_alternate(
-        sys_api::Port::F.pin(6).and_pin(7).and_pin(10),
-        sys_api::OutputType::PushPull,
-        sys_api::Speed::VeryHigh,
-        sys_api::Pull::None,
-        sys_api::Alternate::AF9,
-    )
-    .unwrap();
-    sys.gpio_configure_alternate(
-        sys_api::Port::F.pin(8).and_pin(9),
-        sys_api::OutputType::PushPull,
-        sys_api::Speed::VeryHigh,
-        sys_api::Pull::None,
-        sys_api::Alternate::AF10,
-    )
-    .unwrap();
-    sys.gpio_configure_alternate(
-        sys_api::Port::G.pin(6),
-        sys_api::OutputType::PushPull,
-        sys_api::Speed::VeryHigh,
-        sys_api::Pull::None,
-        sys_api::Alternate::AF10,
-    )
-    .unwrap();
+    // The QSPI pins are declared in `app.toml` and configured by the
+    // generated `setup_pins`.
+    crate::setup_pins(sys).unwrap();
 
     Config {
         sp_host_mux_select: sys_api::Port::B.pin(1),
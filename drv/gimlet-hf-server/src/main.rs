@@ -47,6 +47,8 @@ task_slot!(HASH, hash_driver);
 
 const QSPI_IRQ: u32 = 1;
 
+include!(concat!(env!("OUT_DIR"), "/pin_config.rs"));
+
 struct Config {
     pub sp_host_mux_select: sys_api::PinSet,
     pub reset: sys_api::PinSet,
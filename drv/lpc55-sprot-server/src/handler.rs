@@ -5,6 +5,8 @@
 mod sprockets;
 
 use crate::IoStatus;
+#[cfg(feature = "udprpc-key")]
+use dice::{HandoffData, SeedBuf, UdpRpcData};
 use drv_sprot_api::*;
 use drv_update_api::*;
 use ringbuf::*;
@@ -25,17 +27,32 @@ pub struct Handler {
     pub update: Update,
     count: usize,
     prev: PrevMsg,
+    /// Key for the SP's `udprpc` task, if stage0 handed one off
+    udprpc_key: Option<[u8; 32]>,
 }
 
 pub fn new() -> Handler {
     Handler {
         sprocket: crate::handler::sprockets::init(),
         update: drv_update_api::Update::from(UPDATE_SERVER.get_task_id()),
+        udprpc_key: udprpc_key(),
         prev: PrevMsg::None,
         count: 0,
     }
 }
 
+/// Reads the `udprpc` key from the DICE handoff region, which the app must
+/// grant us with `uses = ["dice_udprpc"]`.
+#[cfg(feature = "udprpc-key")]
+fn udprpc_key() -> Option<[u8; 32]> {
+    UdpRpcData::from_mem().map(|d| *d.key.as_bytes())
+}
+
+#[cfg(not(feature = "udprpc-key"))]
+fn udprpc_key() -> Option<[u8; 32]> {
+    None
+}
+
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
     None,
@@ -249,6 +266,14 @@ impl Handler {
                 let rsp: Result<ImageVersion, u32> = Ok(version);
                 hubpack::serialize(tx_payload, &rsp)?
             }
+            MsgType::UdpRpcKeyReq => {
+                let key = self
+                    .udprpc_key
+                    .as_ref()
+                    .ok_or(SprotError::NotImplemented)?;
+                tx_payload[..key.len()].copy_from_slice(key);
+                key.len()
+            }
             MsgType::SinkReq => {
                 // The first two bytes of a SinkReq payload are the U16
                 // mod 2^16 sequence number.
@@ -268,6 +293,7 @@ impl Handler {
             | MsgType::UpdAbortUpdateRsp
             | MsgType::UpdFinishImageUpdateRsp
             | MsgType::UpdCurrentVersionRsp
+            | MsgType::UdpRpcKeyRsp
             | MsgType::Unknown => {
                 status.rx_invalid = status.rx_invalid.wrapping_add(1);
                 return Err(SprotError::BadMessageType);
@@ -290,6 +316,7 @@ fn req_msgtype_to_rsp_msgtype(msgtype: MsgType) -> MsgType {
         MsgType::UpdAbortUpdateReq => MsgType::UpdAbortUpdateRsp,
         MsgType::UpdFinishImageUpdateReq => MsgType::UpdFinishImageUpdateRsp,
         MsgType::UpdCurrentVersionReq => MsgType::UpdCurrentVersionRsp,
+        MsgType::UdpRpcKeyReq => MsgType::UdpRpcKeyRsp,
         MsgType::SinkReq => MsgType::SinkRsp,
 
         // All of the unexpected messages
@@ -305,6 +332,7 @@ fn req_msgtype_to_rsp_msgtype(msgtype: MsgType) -> MsgType {
         | MsgType::UpdAbortUpdateRsp
         | MsgType::UpdFinishImageUpdateRsp
         | MsgType::UpdCurrentVersionRsp
+        | MsgType::UdpRpcKeyRsp
         | MsgType::Unknown => {
             panic!("MsgType is not a request: {}", msgtype as u8)
         }
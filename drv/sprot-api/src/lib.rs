@@ -345,6 +345,12 @@ pub enum MsgType {
     UpdCurrentVersionReq = 20,
     UpdCurrentVersionRsp = 21,
 
+    /// Request the per-device key for the SP's `udprpc` task, which is
+    /// derived from the DICE CDI
+    UdpRpcKeyReq = 22,
+    /// Payload contains the 32-byte `udprpc` key
+    UdpRpcKeyRsp = 23,
+
     /// Reserved value.
     Unknown = 0xff,
 }
@@ -374,6 +380,8 @@ impl From<u8> for MsgType {
             19 => MsgType::UpdFinishImageUpdateRsp,
             20 => MsgType::UpdCurrentVersionReq,
             21 => MsgType::UpdCurrentVersionRsp,
+            22 => MsgType::UdpRpcKeyReq,
+            23 => MsgType::UdpRpcKeyRsp,
             _ => MsgType::Unknown,
         }
     }
@@ -12,6 +12,7 @@
 
 #![no_std]
 
+use core::cell::Cell;
 use core::convert::TryFrom;
 
 #[cfg(feature = "h743")]
@@ -22,7 +23,17 @@ use stm32h7::stm32h753 as device;
 
 pub mod ring;
 
-use crate::ring::BUFSZ;
+use crate::ring::{RxRingStats, TxRingStats, BUFSZ};
+
+/// Counters for the Ethernet DMA, as returned by `Ethernet::stats`. These all
+/// wrap on overflow.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EthStats {
+    pub tx: TxRingStats,
+    pub rx: RxRingStats,
+    /// Packets dropped by the DMA, e.g. because the Rx ring was full
+    pub rx_missed: u32,
+}
 
 /// Control block for ethernet driver.
 pub struct Ethernet {
@@ -46,6 +57,27 @@ pub struct Ethernet {
     mdio_timer: &'static device::tim16::RegisterBlock,
     /// Notification mask for the timer interrupt.
     mdio_timer_irq_mask: u32,
+
+    /// Running total of the DMA's missed packet counter, which clears when
+    /// it's read.
+    rx_missed: Cell<u32>,
+}
+
+/// Returns the bin in the MAC's 64-bin hash filter for `addr`, which
+/// is the upper 6 bits of the bit-reversed Ethernet CRC of the address.
+fn filter_hash(addr: &[u8; 6]) -> u32 {
+    let mut crc = !0u32;
+    for &b in addr {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    (!crc).reverse_bits() >> 26
 }
 
 /// As the name implies, this spins until a predicate becomes true, in a crappy
@@ -186,9 +218,10 @@ impl Ethernet {
         mtl.mtlrx_qomr.write(|w| w.rsf().set_bit());
 
         // MAC block config:
-        // Enable promiscuous receive. TODO: we will want to set up the filters
-        // later, once we figure out how we assign MAC addresses across the
-        // redundant segments.
+        // Enable promiscuous receive, until the owner programs the hash filter
+        // with `set_address_filter`. TODO: we will want to set up the perfect
+        // filters later, once we figure out how we assign MAC addresses
+        // across the redundant segments.
         mac.macpfr.write(|w| w.pr().set_bit());
         // Force 100mbps full-duplex. TODO: it would be polite to negotiate
         // this, but the KSZ-series switches we talk to won't negotiate.
@@ -255,7 +288,59 @@ impl Ethernet {
             rx_ring,
             mdio_timer,
             mdio_timer_irq_mask,
+            rx_missed: Cell::new(0),
+        }
+    }
+
+    /// Returns counters from the DMA rings and hardware.
+    pub fn stats(&self) -> EthStats {
+        let missed = self.dma.dmacmfcr.read().mfc().bits();
+        let rx_missed = self.rx_missed.get().wrapping_add(u32::from(missed));
+        self.rx_missed.set(rx_missed);
+
+        EthStats {
+            tx: self.tx_ring.stats(),
+            rx: self.rx_ring.stats(),
+            rx_missed,
+        }
+    }
+
+    /// Programs the MAC's hash filter to accept frames sent to each of
+    /// `addrs`, and takes the MAC out of promiscuous mode. `addrs` must
+    /// include our own unicast addresses (one per VLAN), as well as the
+    /// multicast groups that we want to receive.
+    ///
+    /// Unicast and multicast destinations are both checked against the hash
+    /// table, so that every VLAN's address fits, however many there are.
+    /// Broadcast frames are always accepted, and VLAN tags aren't filtered
+    /// here (the Rx ring drops frames for VIDs we don't know). Since this is
+    /// a hash, frames for other addresses in the same bins get through too,
+    /// so callers must still filter incoming frames themselves.
+    pub fn set_address_filter(&self, addrs: impl IntoIterator<Item = [u8; 6]>) {
+        let mut table = 0u64;
+        for addr in addrs {
+            table |= 1 << filter_hash(&addr);
         }
+        self.mac.macht0r.write(|w| unsafe { w.bits(table as u32) });
+        self.mac
+            .macht1r
+            .write(|w| unsafe { w.bits((table >> 32) as u32) });
+        self.mac.macpfr.write(|w| {
+            w.huc()
+                .set_bit() // Hash unicast destinations...
+                .hmc()
+                .set_bit() // ...and multicast destinations
+                .hpf()
+                .clear_bit() // Don't also accept perfect filter matches
+                .pm()
+                .clear_bit() // Don't pass all multicast frames
+                .dbf()
+                .clear_bit() // Accept broadcasts
+                .vtfe()
+                .clear_bit() // Don't filter VLAN tags
+                .pr()
+                .clear_bit()
+        });
     }
 
     /// Maximum number of packets that can be sent in a burst, assuming the
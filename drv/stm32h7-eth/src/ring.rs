@@ -108,6 +108,15 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Counters for a `TxRing`, as returned by `TxRing::stats`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TxRingStats {
+    /// Packets handed to the hardware
+    pub packets: u32,
+    /// Attempts to send while the ring was full
+    pub full: u32,
+}
+
 /// Control block for a ring of `TxDesc` records and associated `Buffer`s.
 pub struct TxRing {
     /// The descriptor ring storage.
@@ -118,6 +127,7 @@ pub struct TxRing {
     /// next transmitted packet. This must be in the range `0..storage.len()` at
     /// all times.
     next: Cell<usize>,
+    stats: Cell<TxRingStats>,
 }
 
 impl TxRing {
@@ -156,9 +166,22 @@ impl TxRing {
             storage,
             buffers,
             next: Cell::new(0),
+            stats: Cell::new(TxRingStats::default()),
         }
     }
 
+    /// Returns the ring's counters, which wrap on overflow.
+    pub fn stats(&self) -> TxRingStats {
+        self.stats.get()
+    }
+
+    fn count(&self, f: impl FnOnce(&mut TxRingStats) -> &mut u32) {
+        let mut stats = self.stats.get();
+        let c = f(&mut stats);
+        *c = c.wrapping_add(1);
+        self.stats.set(stats);
+    }
+
     /// Returns the base pointer of the `TxDesc` ring. This needs to be loaded
     /// into the DMA controller so it knows where to look for descriptors.
     pub fn base_ptr(&self) -> *const TxDesc {
@@ -217,6 +240,7 @@ impl TxRing {
         let tdes3 = d.tdes[3].load(Ordering::Acquire);
         let own = tdes3 & (1 << TDES3_OWN_BIT) != 0;
         if own {
+            self.count(|s| &mut s.full);
             None
         } else {
             // Descriptor is free. Since we keep the descriptors paired with
@@ -254,6 +278,7 @@ impl TxRing {
             } else {
                 self.next.get() + 1
             });
+            self.count(|s| &mut s.packets);
 
             Some(result)
         }
@@ -296,6 +321,7 @@ impl TxRing {
         let tdes3 = d.tdes[1][3].load(Ordering::Acquire);
         let own2 = tdes3 & (1 << TDES3_OWN_BIT) != 0;
         if own1 || own2 {
+            self.count(|s| &mut s.full);
             None
         } else {
             // Descriptor is free. Since we keep the descriptors paired with
@@ -342,6 +368,7 @@ impl TxRing {
             } else {
                 self.next.get() + 1
             });
+            self.count(|s| &mut s.packets);
 
             Some(result)
         }
@@ -396,6 +423,19 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Counters for an `RxRing`, as returned by `RxRing::stats`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RxRingStats {
+    /// Packets handed to the netstack
+    pub packets: u32,
+    /// Packets dropped because the hardware flagged an error, or because they
+    /// didn't fit in a single buffer
+    pub errors: u32,
+    /// Packets dropped because they had no VLAN tag, or one outside of the
+    /// configured range (only with the `vlan` feature)
+    pub vlan_dropped: u32,
+}
+
 /// Control block for a ring of `RxDesc` records and associated `Buffer`s.
 pub struct RxRing {
     /// The descriptor ring storage.
@@ -406,6 +446,7 @@ pub struct RxRing {
     /// received packet. This must be in the range `0..storage.len()` at all
     /// times.
     next: Cell<usize>,
+    stats: Cell<RxRingStats>,
 }
 
 impl RxRing {
@@ -439,9 +480,22 @@ impl RxRing {
             storage,
             buffers,
             next: Cell::new(0),
+            stats: Cell::new(RxRingStats::default()),
         }
     }
 
+    /// Returns the ring's counters, which wrap on overflow.
+    pub fn stats(&self) -> RxRingStats {
+        self.stats.get()
+    }
+
+    fn count(&self, f: impl FnOnce(&mut RxRingStats) -> &mut u32) {
+        let mut stats = self.stats.get();
+        let c = f(&mut stats);
+        *c = c.wrapping_add(1);
+        self.stats.set(stats);
+    }
+
     /// Returns the base pointer of the `RxDesc` ring. This needs to be loaded
     /// into the DMA controller so it knows where to look for descriptors.
     pub fn base_ptr(&self) -> *const RxDesc {
@@ -512,6 +566,7 @@ impl RxRing {
             } else {
                 self.next.get() + 1
             });
+            self.count(|s| &mut s.errors);
             any_dropped = true;
         }
     }
@@ -587,6 +642,7 @@ impl RxRing {
         } else {
             self.next.get() + 1
         });
+        self.count(|s| &mut s.packets);
 
         result
     }
@@ -655,6 +711,11 @@ impl RxRing {
             } else {
                 self.next.get() + 1
             });
+            if packet_okay {
+                self.count(|s| &mut s.vlan_dropped);
+            } else {
+                self.count(|s| &mut s.errors);
+            }
             any_dropped = true;
         }
     }
@@ -725,6 +786,7 @@ impl RxRing {
         } else {
             self.next.get() + 1
         });
+        self.count(|s| &mut s.packets);
 
         retval
     }
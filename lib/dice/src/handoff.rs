@@ -4,7 +4,7 @@
 
 use crate::{
     AliasCert, AliasOkm, RngSeed, SpMeasureCert, SpMeasureOkm,
-    TrustQuorumDheCert, TrustQuorumDheOkm,
+    TrustQuorumDheCert, TrustQuorumDheOkm, UdpRpcOkm,
 };
 use core::ops::Range;
 use dice_mfg_msgs::SizedBlob;
@@ -24,13 +24,15 @@ const SPMEASURE_RANGE: Range<usize> =
     ALIAS_RANGE.end..(ALIAS_RANGE.end + 0x800);
 const RNG_RANGE: Range<usize> =
     SPMEASURE_RANGE.end..(SPMEASURE_RANGE.end + 0x100);
+const UDPRPC_RANGE: Range<usize> = RNG_RANGE.end..(RNG_RANGE.end + 0x100);
 
 // ensure memory ranges are within MEM_RANGE and do not overlap
 sa::const_assert!(MEM_RANGE.start <= CERTS_RANGE.start);
 sa::const_assert!(CERTS_RANGE.end <= ALIAS_RANGE.start);
 sa::const_assert!(ALIAS_RANGE.end <= SPMEASURE_RANGE.start);
 sa::const_assert!(SPMEASURE_RANGE.end <= RNG_RANGE.start);
-sa::const_assert!(RNG_RANGE.end <= MEM_RANGE.end);
+sa::const_assert!(RNG_RANGE.end <= UDPRPC_RANGE.start);
+sa::const_assert!(UDPRPC_RANGE.end <= MEM_RANGE.end);
 
 /// The Handoff type is a thin wrapper over the memory region used to transfer
 /// DICE artifacts (seeds & certs) from stage0 to hubris tasks. It is intended
@@ -311,3 +313,46 @@ impl RngData {
         }
     }
 }
+
+/// Type to represent DICE derived artifacts used by the task that hands the
+/// SP its `udprpc` key. Stage0 will construct an instance of this type and
+/// write it to memory using the Handoff type above. The receiving hubris task
+/// will then construct an instance from the serialized value using the
+/// 'from_mem' constructor.
+#[derive(Deserialize, Serialize, SerializedSize)]
+pub struct UdpRpcData {
+    pub magic: [u8; 16],
+    pub key: UdpRpcOkm,
+}
+
+// Handoff DICE artifacts to task serving the udprpc key.
+//
+// SAFETY: The memory range denoted by MEM_RANGE is checked to be nonoverlapping
+// by static assertion above. We ensure this region is sufficiently large to
+// hold UdpRpcData with another static assert.
+unsafe impl HandoffData for UdpRpcData {
+    const EXPECTED_MAGIC: [u8; 16] = [
+        0x7d, 0x1e, 0x93, 0x0a, 0xc4, 0x52, 0x6b, 0xe8, 0x21, 0xf7, 0x3c, 0x85,
+        0x4e, 0xa9, 0x16, 0xd0,
+    ];
+    const MEM_RANGE: Range<usize> = UDPRPC_RANGE;
+
+    fn get_magic(&self) -> [u8; 16] {
+        self.magic
+    }
+}
+
+// ensure UdpRpcData handoff memory is large enough store data
+sa::const_assert!(
+    UdpRpcData::MEM_RANGE.end - UdpRpcData::MEM_RANGE.start
+        >= <UdpRpcData as SerializedSize>::MAX_SIZE
+);
+
+impl UdpRpcData {
+    pub fn new(key: UdpRpcOkm) -> Self {
+        Self {
+            magic: Self::EXPECTED_MAGIC,
+            key,
+        }
+    }
+}
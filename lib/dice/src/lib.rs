@@ -36,6 +36,7 @@ mod spmeasure_cert_tmpl;
 mod trust_quorum_dhe_cert_tmpl;
 pub use crate::handoff::{
     AliasData, CertData, Handoff, HandoffData, RngData, SpMeasureData,
+    UdpRpcData,
 };
 
 pub const SEED_LENGTH: usize = SECRETKEY_SEED_LENGTH;
@@ -264,6 +265,25 @@ impl TrustQuorumDheOkm {
     }
 }
 
+/// UdpRpcOkm is a type that represents the output keying material (OKM) used
+/// as the per-device key authenticating the SP's `udprpc` task. It's derived
+/// from the CDI rather than CDI_L1 so that it survives updates to the RoT
+/// firmware.
+#[derive(Deserialize, Serialize, SerializedSize, Zeroize, ZeroizeOnDrop)]
+pub struct UdpRpcOkm([u8; SEED_LENGTH]);
+
+impl SeedBuf for UdpRpcOkm {
+    fn as_bytes(&self) -> &[u8; SEED_LENGTH] {
+        &self.0
+    }
+}
+
+impl UdpRpcOkm {
+    pub fn from_cdi(cdi: &Cdi) -> Self {
+        Self(okm_from_seed_no_extract(cdi, "udprpc".as_bytes()))
+    }
+}
+
 #[derive(Deserialize, Serialize, SerializedSize, Zeroize, ZeroizeOnDrop)]
 pub struct RngSeed([u8; SEED_LENGTH]);
 
@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Traits implemented by the typed registers generated by
+//! `build-fpga-regmap`.
+//!
+//! This crate has no dependencies, so that the generated register maps can be
+//! used both by drivers (via `drv-fpga-api`) and by host-side tools decoding
+//! register dumps.
+
+#![no_std]
+
+/// Software access to a register field, as specified in the SystemRDL
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub const fn readable(self) -> bool {
+        matches!(self, Access::Read | Access::ReadWrite)
+    }
+
+    pub const fn writable(self) -> bool {
+        matches!(self, Access::Write | Access::ReadWrite)
+    }
+}
+
+/// Description of a single field within a register
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub mask: u8,
+    pub access: Access,
+}
+
+/// A typed 8-bit FPGA register
+pub trait Register: Copy {
+    /// Name of the register in the register map
+    const NAME: &'static str;
+
+    /// Address of the register within the user design
+    const ADDR: u16;
+
+    /// Register value at reset
+    const RESET: Self;
+
+    const FIELDS: &'static [Field];
+
+    fn from_raw(raw: u8) -> Self;
+    fn raw(self) -> u8;
+
+    /// Iterates over the fields of this register and their values
+    fn fields(self) -> FieldValues<Self> {
+        FieldValues {
+            reg: self,
+            index: 0,
+        }
+    }
+}
+
+/// Marker for registers with at least one software-readable field
+pub trait Readable: Register {}
+
+/// Marker for registers with at least one software-writable field
+pub trait Writable: Register {}
+
+/// Iterator returned by [`Register::fields`], yielding each field along with
+/// its value (shifted down to bit 0).
+pub struct FieldValues<R> {
+    reg: R,
+    index: usize,
+}
+
+impl<R: Register> Iterator for FieldValues<R> {
+    type Item = (&'static Field, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let f = R::FIELDS.get(self.index)?;
+        self.index += 1;
+        let v = (self.reg.raw() & f.mask) >> f.mask.trailing_zeros();
+        Some((f, v))
+    }
+}
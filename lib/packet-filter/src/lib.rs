@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Building blocks for filtering incoming packets by source: token buckets
+//! for rate limiting, a small table of per-source buckets, and IPv6 prefix
+//! matching.
+//!
+//! These don't depend on the network stack (addresses are plain `[u8; 16]`
+//! and times are milliseconds from any monotonic clock), so that they can be
+//! tested on the host.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::cell::Cell;
+
+/// Buckets count tokens in thousandths of a packet, so that they can be
+/// refilled every millisecond without losing precision.
+pub const TOKENS_PER_PACKET: u32 = 1000;
+
+#[derive(Copy, Clone, Debug)]
+pub struct TokenBucketConfig {
+    /// Sustained rate, in packets per second
+    pub rate: u32,
+    /// Number of packets which may arrive back-to-back
+    pub burst: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bucket {
+    tokens: u32,
+    /// Time of the last refill, in milliseconds
+    last: u64,
+}
+
+impl Bucket {
+    /// Returns a bucket which can take `config.burst` packets at time `now`.
+    pub fn full(config: TokenBucketConfig, now: u64) -> Self {
+        Self {
+            tokens: config.burst * TOKENS_PER_PACKET,
+            last: now,
+        }
+    }
+
+    /// Refills the bucket for the time since it was last used, then takes a
+    /// packet's worth of tokens from it if there are enough.
+    pub fn take(&mut self, config: TokenBucketConfig, now: u64) -> bool {
+        let elapsed = now.saturating_sub(self.last);
+        let cap = u64::from(config.burst * TOKENS_PER_PACKET);
+        let refill = elapsed.saturating_mul(u64::from(config.rate));
+        self.tokens = (u64::from(self.tokens) + refill).min(cap) as u32;
+        self.last = now;
+
+        if self.tokens >= TOKENS_PER_PACKET {
+            self.tokens -= TOKENS_PER_PACKET;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Per-source bucket, for the source address it's paired with
+type SourceBucket = Cell<Option<([u8; 16], Bucket)>>;
+
+/// Token buckets for up to `N` source addresses. When the table is full, a
+/// new source replaces the one that was seen least recently.
+pub struct SourceTable<const N: usize> {
+    slots: [SourceBucket; N],
+}
+
+impl<const N: usize> Default for SourceTable<N> {
+    fn default() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: SourceBucket = Cell::new(None);
+        Self { slots: [EMPTY; N] }
+    }
+}
+
+impl<const N: usize> SourceTable<N> {
+    /// Takes a token from the bucket for `src`, making one (and evicting the
+    /// least recently seen source, if need be) if there isn't one. If the
+    /// table has no slots at all, every packet is accepted.
+    pub fn take(
+        &self,
+        src: [u8; 16],
+        config: TokenBucketConfig,
+        now: u64,
+    ) -> bool {
+        let slot = self
+            .slots
+            .iter()
+            .find(|s| s.get().map(|(a, _)| a) == Some(src))
+            .or_else(|| self.slots.iter().find(|s| s.get().is_none()))
+            .or_else(|| {
+                self.slots
+                    .iter()
+                    .min_by_key(|s| s.get().map(|(_, b)| b.last))
+            });
+        let Some(slot) = slot else {
+            return true;
+        };
+
+        let mut b = match slot.get() {
+            Some((a, b)) if a == src => b,
+            _ => Bucket::full(config, now),
+        };
+        let ok = b.take(config, now);
+        slot.set(Some((src, b)));
+        ok
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Prefix {
+    pub addr: [u8; 16],
+    pub len: u8,
+}
+
+impl Prefix {
+    pub fn contains(&self, addr: [u8; 16]) -> bool {
+        let bytes = usize::from(self.len / 8);
+        let bits = self.len % 8;
+        if addr[..bytes] != self.addr[..bytes] {
+            return false;
+        }
+        // The trailing partial byte, if any
+        bits == 0 || {
+            let mask = !(0xffu8 >> bits);
+            addr[bytes] & mask == self.addr[bytes] & mask
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: TokenBucketConfig = TokenBucketConfig { rate: 10, burst: 3 };
+
+    fn addr(last: u8) -> [u8; 16] {
+        let mut a = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        a[15] = last;
+        a
+    }
+
+    #[test]
+    fn burst() {
+        let mut b = Bucket::full(CONFIG, 0);
+        for _ in 0..CONFIG.burst {
+            assert!(b.take(CONFIG, 0));
+        }
+        assert!(!b.take(CONFIG, 0));
+    }
+
+    #[test]
+    fn refill() {
+        let mut b = Bucket::full(CONFIG, 0);
+        for _ in 0..CONFIG.burst {
+            assert!(b.take(CONFIG, 0));
+        }
+
+        // At 10 packets per second, a packet's worth of tokens takes 100 ms,
+        // and it doesn't matter how that time is split up.
+        assert!(!b.take(CONFIG, 50));
+        assert!(!b.take(CONFIG, 99));
+        assert!(b.take(CONFIG, 100));
+        assert!(!b.take(CONFIG, 100));
+        assert!(b.take(CONFIG, 200));
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let mut b = Bucket::full(CONFIG, 0);
+        assert!(b.take(CONFIG, 0));
+
+        // A long idle period only refills the bucket up to the burst size.
+        let later = 1_000_000;
+        for _ in 0..CONFIG.burst {
+            assert!(b.take(CONFIG, later));
+        }
+        assert!(!b.take(CONFIG, later));
+    }
+
+    #[test]
+    fn refill_ignores_time_going_backwards() {
+        let mut b = Bucket::full(CONFIG, 1000);
+        for _ in 0..CONFIG.burst {
+            assert!(b.take(CONFIG, 1000));
+        }
+        assert!(!b.take(CONFIG, 0));
+    }
+
+    #[test]
+    fn sources_have_separate_buckets() {
+        let t = SourceTable::<2>::default();
+        for _ in 0..CONFIG.burst {
+            assert!(t.take(addr(1), CONFIG, 0));
+        }
+        assert!(!t.take(addr(1), CONFIG, 0));
+        assert!(t.take(addr(2), CONFIG, 0));
+    }
+
+    #[test]
+    fn evicts_least_recently_seen_source() {
+        let t = SourceTable::<2>::default();
+        for _ in 0..CONFIG.burst {
+            assert!(t.take(addr(1), CONFIG, 0));
+        }
+        for _ in 0..CONFIG.burst {
+            assert!(t.take(addr(2), CONFIG, 10));
+        }
+
+        // Source 1 is still over its limit, so it keeps its bucket.
+        assert!(!t.take(addr(1), CONFIG, 20));
+
+        // Source 2 was seen least recently, so a new source takes its slot
+        // and starts with a full bucket, while source 1 keeps its own.
+        assert!(t.take(addr(3), CONFIG, 30));
+        assert!(!t.take(addr(1), CONFIG, 35));
+
+        // When source 2 comes back, it replaces source 3 (now the least
+        // recently seen) and gets a fresh bucket, having lost its history.
+        for _ in 0..CONFIG.burst {
+            assert!(t.take(addr(2), CONFIG, 40));
+        }
+        assert!(!t.take(addr(2), CONFIG, 40));
+        assert!(!t.take(addr(1), CONFIG, 40));
+    }
+
+    #[test]
+    fn empty_table_accepts_everything() {
+        let t = SourceTable::<0>::default();
+        for _ in 0..10 {
+            assert!(t.take(addr(1), CONFIG, 0));
+        }
+    }
+
+    #[test]
+    fn prefix_contains() {
+        let link_local = Prefix {
+            addr: addr(0),
+            len: 10,
+        };
+        assert!(link_local.contains(addr(1)));
+        let mut a = addr(1);
+        a[1] = 0xc0;
+        assert!(!link_local.contains(a));
+
+        let host = Prefix {
+            addr: addr(5),
+            len: 128,
+        };
+        assert!(host.contains(addr(5)));
+        assert!(!host.contains(addr(6)));
+
+        let any = Prefix {
+            addr: [0; 16],
+            len: 0,
+        };
+        assert!(any.contains(addr(7)));
+    }
+}
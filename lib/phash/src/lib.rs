@@ -21,6 +21,9 @@ impl PerfectHash for u32 {
 
 pub struct PerfectHashMap<'a, K, V> {
     pub m: u32,
+    /// Right shift applied to each hash before it's reduced to an index,
+    /// which selects the hash family (i.e. which bits of the hash are used)
+    pub shift: u32,
     pub values: &'a [(K, V)],
 }
 
@@ -32,7 +35,7 @@ impl<'a, K: Copy + PerfectHash + PartialEq, V> PerfectHashMap<'a, K, V> {
         if self.values.is_empty() {
             return None;
         }
-        let i = key.phash(self.m) % self.values.len();
+        let i = (key.phash(self.m) >> self.shift) % self.values.len();
         if key == self.values[i].0 {
             Some(&self.values[i].1)
         } else {
@@ -49,6 +52,8 @@ impl<'a, K: Copy + PerfectHash + PartialEq, V> PerfectHashMap<'a, K, V> {
 
 pub struct NestedPerfectHashMap<'a, K, V> {
     pub m: u32,
+    /// Right shift applied to each hash, as in `PerfectHashMap`
+    pub shift: u32,
     pub g: &'a [u32],
     pub values: &'a [&'a [(K, V)]],
 }
@@ -61,11 +66,11 @@ impl<'a, K: Copy + PerfectHash + PartialEq, V> NestedPerfectHashMap<'a, K, V> {
         if self.g.is_empty() {
             return None;
         }
-        let i = key.phash(self.m) % self.g.len();
+        let i = (key.phash(self.m) >> self.shift) % self.g.len();
         if self.values[i].is_empty() {
             return None;
         }
-        let j = key.phash(self.g[i]) % self.values[i].len();
+        let j = (key.phash(self.g[i]) >> self.shift) % self.values[i].len();
         if key == self.values[i][j].0 {
             Some(&self.values[i][j].1)
         } else {
@@ -23,56 +23,64 @@ impl Parse for Config {
 ///
 /// The type and value must be compatible, e.g. if the type is an array, then
 /// the value should also be an array.  This supports arrays, tuples, slices,
-/// references, and primitive values.
+/// references, and primitive values.  Mismatches are reported as errors
+/// pointing at the offending type.
 fn config_to_token(
     ty: &syn::Type,
     v: &toml::Value,
-) -> proc_macro2::TokenStream {
+) -> Result<proc_macro2::TokenStream> {
+    let as_array = |kind: &str| {
+        v.as_array().ok_or_else(|| {
+            syn::Error::new_spanned(
+                ty,
+                format!(
+                    "expected TOML array for {} type {}; got {}",
+                    kind,
+                    ty.to_token_stream(),
+                    v
+                ),
+            )
+        })
+    };
     match ty {
         syn::Type::Tuple(a) => {
-            let v: Vec<proc_macro2::TokenStream> = v
-                .as_array()
-                .expect(&format!(
-                    "Expected TOML array for tuple type {}; got {}",
-                    ty.to_token_stream().to_string(),
-                    v
-                ))
-                .into_iter()
+            let vs = as_array("tuple")?;
+            if vs.len() != a.elems.len() {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "expected {} values for tuple type {}; got {}",
+                        a.elems.len(),
+                        ty.to_token_stream(),
+                        v
+                    ),
+                ));
+            }
+            let v = vs
+                .iter()
                 .zip(a.elems.iter())
                 .map(|(v, t)| config_to_token(t, v))
-                .collect();
-            quote! { ( #(#v),* ) }
+                .collect::<Result<Vec<_>>>()?;
+            Ok(quote! { ( #(#v),* ) })
         }
         syn::Type::Array(a) => {
-            let v: Vec<proc_macro2::TokenStream> = v
-                .as_array()
-                .expect(&format!(
-                    "Expected TOML array for array type {}; got {}",
-                    ty.to_token_stream().to_string(),
-                    v
-                ))
-                .into_iter()
+            let v = as_array("array")?
+                .iter()
                 .map(|v| config_to_token(&a.elem, v))
-                .collect();
-            quote! { [ #(#v),* ] }
+                .collect::<Result<Vec<_>>>()?;
+            Ok(quote! { [ #(#v),* ] })
         }
         syn::Type::Slice(s) => {
-            let v: Vec<proc_macro2::TokenStream> = v
-                .as_array()
-                .expect(&format!(
-                    "Expected TOML array for slice type {}; got {}",
-                    ty.to_token_stream().to_string(),
-                    v
-                ))
-                .into_iter()
+            let v = as_array("slice")?
+                .iter()
                 .map(|v| config_to_token(&s.elem, v))
-                .collect();
-            quote! { [ #(#v),* ] }
+                .collect::<Result<Vec<_>>>()?;
+            Ok(quote! { [ #(#v),* ] })
         }
         syn::Type::Reference(r) => {
             let mut out: proc_macro2::TokenStream = "&".parse().unwrap();
-            out.extend(config_to_token(&r.elem, v));
-            out
+            out.extend(config_to_token(&r.elem, v)?);
+            Ok(out)
         }
         syn::Type::Path(_) => {
             // We assume that strings should be inserted verbatim into the
@@ -83,15 +91,46 @@ fn config_to_token(
             } else {
                 v.to_string()
             };
-            v.parse()
-                .expect(&format!("Could not parse {}", v.to_string()))
-        }
-        _ => {
-            panic!("Got unhandled type {}", ty.to_token_stream().to_string())
+            v.parse().map_err(|_| {
+                syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "could not parse `{}` as {}",
+                        v,
+                        ty.to_token_stream()
+                    ),
+                )
+            })
         }
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            format!("unhandled type {}", ty.to_token_stream()),
+        )),
     }
 }
 
+/// Returns the path of the task crate's `Cargo.toml`
+fn manifest_path() -> Option<String> {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").ok()?;
+    let path = std::path::Path::new(&dir).join("Cargo.toml");
+    path.to_str().map(str::to_owned)
+}
+
+/// Looks up the default for a config key in the schema that the task crate
+/// declares under `[package.metadata.task-config]` in its `Cargo.toml`.
+fn schema_default(key: &str) -> Option<toml::Value> {
+    let manifest: toml::Value =
+        toml::from_str(&std::fs::read_to_string(manifest_path()?).ok()?)
+            .ok()?;
+    manifest
+        .get("package")?
+        .get("metadata")?
+        .get("task-config")?
+        .get(key)?
+        .get("default")
+        .cloned()
+}
+
 /// The `task_config!` macro defines a `struct TASK_CONFIG` which is pulled
 /// from the Hubris task config.
 ///
@@ -134,46 +173,108 @@ fn config_to_token(
 /// }
 /// ```
 ///
+/// Keys which are missing from the config block fall back to the `default`
+/// in the task crate's `[package.metadata.task-config]` schema, which
+/// `xtask dist` also uses to check the config before building:
+/// ```toml
+/// [package.metadata.task-config]
+/// count = { type = "integer", doc = "Number of LEDs" }
+/// leds = { type = "array", items = "array", doc = "LED pins", default = [] }
+/// ```
+///
 /// At the moment, this only supports tasks which are instantiated _once_ and
 /// configured through the task configuration block (e.g. the SPI driver
 /// cannot be configured using this macro).
 #[proc_macro]
 pub fn task_config(tokens: TokenStream) -> TokenStream {
-    let task_config_str = std::env::var("HUBRIS_TASK_CONFIG")
-        .expect("Missing HUBRIS_TASK_CONFIG variable");
-    let config = toml::from_str::<toml::Value>(&task_config_str)
-        .expect("Could not parse HUBRIS_TASK_CONFIG");
-
     let input = parse_macro_input!(tokens as Config);
+    match expand(input) {
+        Ok(t) => t.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: Config) -> Result<proc_macro2::TokenStream> {
+    let task_name = std::env::var("HUBRIS_TASK_NAME")
+        .unwrap_or_else(|_| "<unknown>".to_string());
+
+    // A task with no config block doesn't get `HUBRIS_TASK_CONFIG`, which is
+    // fine as long as every field has a default.
+    let config = match std::env::var("HUBRIS_TASK_CONFIG") {
+        Ok(s) => toml::from_str::<toml::Value>(&s).map_err(|e| {
+            syn::Error::new(
+                proc_macro2::Span::call_site(),
+                format!("could not parse HUBRIS_TASK_CONFIG: {}", e),
+            )
+        })?,
+        Err(_) => toml::Value::Table(Default::default()),
+    };
+
     let values = input
         .items
         .iter()
         .map(|f| {
-            let ident = f.ident.as_ref().expect("Missing ident");
-            let v = config.get(ident.to_string()).expect(&format!(
-                "Missing config parameter in TOML file: {}",
-                ident.to_string()
-            ));
-            let vs = config_to_token(&f.ty, v);
-            quote! { #ident: #vs }
+            let ident = f.ident.as_ref().unwrap();
+            let key = ident.to_string();
+            let v = match config
+                .get(&key)
+                .cloned()
+                .or_else(|| schema_default(&key))
+            {
+                Some(v) => v,
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        format!(
+                            "missing `{}` in [tasks.{}.config], and the \
+                             task-config schema has no default for it",
+                            key, task_name
+                        ),
+                    ))
+                }
+            };
+            let vs = config_to_token(&f.ty, &v).map_err(|e| {
+                let mut out = syn::Error::new_spanned(
+                    ident,
+                    format!(
+                        "invalid `{}` in [tasks.{}.config]",
+                        key, task_name
+                    ),
+                );
+                out.combine(e);
+                out
+            })?;
+            Ok(quote! { #ident: #vs })
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>>>()?;
 
-    let app_toml_path = std::env::var("HUBRIS_APP_TOML")
-        .expect("Could not find 'HUBRIS_APP_TOML' environment variable");
+    let app_toml_path = std::env::var("HUBRIS_APP_TOML").map_err(|_| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "could not find 'HUBRIS_APP_TOML' environment variable",
+        )
+    })?;
     let fields = input.items.iter();
 
+    // Likewise for the task's `Cargo.toml`, since its schema supplies the
+    // defaults for missing keys (see the app TOML hack below)
+    let manifest = manifest_path().map(|path| {
+        quote! {
+            const MANIFEST_TO_ENSURE_REBUILD: &[u8] = include_bytes!(#path);
+        }
+    });
+
     // Once `proc_macro::tracked_env::var` is stable, we won't need to use
     // this hack, but until then, we include the app TOML file to force
     // rebuilds if it changes (and trust it's optimized out by the compiler)
-    quote! {
+    Ok(quote! {
         const APP_TOML_TO_ENSURE_REBUILD: &[u8] = include_bytes!(#app_toml_path);
+        #manifest
         struct Config {
             #(#fields),*
         }
         const TASK_CONFIG: Config = Config {
             #(#values),*
         };
-    }
-    .into()
+    })
 }
@@ -0,0 +1,445 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Authenticated (and optionally encrypted) framing for `udprpc`.
+//!
+//! Every request is wrapped as
+//! ```text
+//! | AuthHeader | body | tag |
+//! ```
+//! where `body` is an ordinary `udprpc` request, and `tag` is an
+//! HMAC-SHA256 of the header and body (as sent). Requests whose tag doesn't
+//! check out are rejected before anything else is looked at, with a bare
+//! one-byte `Reject::BadMac` code.
+//!
+//! For replay protection, each request carries the server's `epoch` (a
+//! random value picked at boot) and a `counter`, which must be greater than
+//! that of the last request we accepted (and less than 2^63). Otherwise, the
+//! reply body is the `Reject::BadNonce` code followed by the last accepted
+//! counter, in a header carrying the current epoch; a client can start a
+//! session by sending any request with an epoch of zero.
+//!
+//! Replies to authenticated requests are wrapped in the same way, echoing the
+//! request's counter and flags. If the request sets `FLAG_ENCRYPTED`, its body
+//! and the reply's body are encrypted with ChaCha20 (the original variant,
+//! with a 64-bit nonce), using `counter << 1` as the nonce for the request and
+//! `counter << 1 | 1` for the reply.
+//!
+//! Keys are derived from a 32-byte per-device key, which the caller supplies.
+//! Requests and replies are MACed with different keys,
+//! `HMAC-SHA256(key, "udprpc mac request")` and
+//! `HMAC-SHA256(key, "udprpc mac reply")`, so that a reply (which has the
+//! same layout as a request, and may echo its counter) can't be reflected
+//! back to us as a request. The encryption key is
+//! `HMAC-SHA256(key, "udprpc enc" || epoch)` (with the epoch as eight
+//! little-endian bytes), so that a counter is never reused with the same
+//! encryption key. If there's no key, every request is rejected with
+//! `Reject::NoKey`.
+
+#![cfg_attr(not(test), no_std)]
+
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::{ChaCha20Legacy, Key, LegacyNonce};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use unwrap_lite::UnwrapLite;
+use zerocopy::{AsBytes, FromBytes, LittleEndian, U64};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Set in `AuthHeader::flags` if the body is encrypted
+pub const FLAG_ENCRYPTED: u8 = 1 << 0;
+
+pub const HEADER_SIZE: usize = core::mem::size_of::<AuthHeader>();
+pub const TAG_SIZE: usize = 32;
+
+/// Counters must be less than this, so that we can shift them to make room
+/// for the direction bit in the nonce
+pub const MAX_COUNTER: u64 = 1 << 63;
+
+/// Reasons for rejecting a request, which the caller maps to the first byte
+/// of its reply
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Reject {
+    /// The request is too short to hold a header and tag
+    TooShort,
+    /// The request's tag is wrong
+    BadMac,
+    /// The request's epoch is not the current one, or its counter is not
+    /// greater than that of the last request we accepted
+    BadNonce,
+    /// We have no key with which to authenticate requests
+    NoKey,
+}
+
+/// Header for an authenticated request or reply
+///
+/// `humility` must cooperate with this layout
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct AuthHeader {
+    pub epoch: U64<LittleEndian>,
+    pub counter: U64<LittleEndian>,
+    pub flags: u8,
+}
+
+#[derive(Copy, Clone)]
+enum Direction {
+    Request = 0,
+    Reply = 1,
+}
+
+struct Keys {
+    mac_request: [u8; 32],
+    mac_reply: [u8; 32],
+    enc: [u8; 32],
+}
+
+impl Keys {
+    fn new(key: &[u8; 32], epoch: u64) -> Self {
+        let derive = |label: &[u8], context: &[u8]| {
+            let mut mac = HmacSha256::new_varkey(key).unwrap_lite();
+            mac.update(label);
+            mac.update(context);
+            let mut out = [0; 32];
+            out.copy_from_slice(&mac.finalize().into_bytes());
+            out
+        };
+        Self {
+            mac_request: derive(b"udprpc mac request", &[]),
+            mac_reply: derive(b"udprpc mac reply", &[]),
+            enc: derive(b"udprpc enc", &epoch.to_le_bytes()),
+        }
+    }
+
+    fn mac(&self, dir: Direction) -> HmacSha256 {
+        let key = match dir {
+            Direction::Request => &self.mac_request,
+            Direction::Reply => &self.mac_reply,
+        };
+        HmacSha256::new_varkey(key).unwrap_lite()
+    }
+
+    /// Encrypts or decrypts `data` in place.
+    fn apply_keystream(&self, counter: u64, dir: Direction, data: &mut [u8]) {
+        let nonce = (counter << 1 | dir as u64).to_le_bytes();
+        let mut cipher = ChaCha20Legacy::new(
+            Key::from_slice(&self.enc),
+            LegacyNonce::from_slice(&nonce),
+        );
+        cipher.apply_keystream(data);
+    }
+}
+
+pub struct Auth {
+    keys: Option<Keys>,
+    epoch: u64,
+    /// Counter of the last request we accepted in this epoch
+    last_counter: u64,
+}
+
+impl Auth {
+    pub fn new(key: Option<[u8; 32]>, epoch: u64) -> Self {
+        Self {
+            keys: key.map(|k| Keys::new(&k, epoch)),
+            epoch,
+            last_counter: 0,
+        }
+    }
+
+    /// Returns `true` if we have a key, and so can accept requests
+    pub fn has_key(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    /// Sets the key, if it wasn't available when we were created
+    pub fn set_key(&mut self, key: [u8; 32]) {
+        self.keys = Some(Keys::new(&key, self.epoch));
+    }
+
+    /// Checks the request in `rx`, decrypting it if need be, and passes its
+    /// body to `dispatch` if it's acceptable. Returns the length of the
+    /// reply written to `tx`.
+    ///
+    /// `dispatch` takes a request body and a buffer for the reply body, and
+    /// returns the length of the reply body. `code` gives the byte with which
+    /// to report each kind of rejection.
+    pub fn handle(
+        &mut self,
+        rx: &mut [u8],
+        tx: &mut [u8],
+        dispatch: impl FnOnce(&[u8], &mut [u8]) -> usize,
+        code: impl Fn(Reject) -> u8,
+    ) -> usize {
+        let Some(keys) = &self.keys else {
+            tx[0] = code(Reject::NoKey);
+            return 1;
+        };
+        if rx.len() < HEADER_SIZE + TAG_SIZE {
+            tx[0] = code(Reject::TooShort);
+            return 1;
+        }
+        let (signed, tag) = rx.split_at_mut(rx.len() - TAG_SIZE);
+        let mut mac = keys.mac(Direction::Request);
+        mac.update(signed);
+        if mac.verify(tag).is_err() {
+            tx[0] = code(Reject::BadMac);
+            return 1;
+        }
+
+        let (header, body) = signed.split_at_mut(HEADER_SIZE);
+        let header = AuthHeader::read_from(&*header).unwrap_lite();
+        let counter = header.counter.get();
+        let fresh = header.epoch.get() == self.epoch
+            && counter > self.last_counter
+            && counter < MAX_COUNTER;
+
+        let (out_header, out_body) = tx.split_at_mut(HEADER_SIZE);
+        let body_space = out_body.len() - TAG_SIZE;
+        let out_body = &mut out_body[..body_space];
+        let (n, flags) = if fresh {
+            self.last_counter = counter;
+            let encrypted = header.flags & FLAG_ENCRYPTED != 0;
+            if encrypted {
+                keys.apply_keystream(counter, Direction::Request, body);
+            }
+            let n = dispatch(body, out_body);
+            if encrypted {
+                let reply = &mut out_body[..n];
+                keys.apply_keystream(counter, Direction::Reply, reply);
+            }
+            (n, header.flags)
+        } else {
+            // The client may not know the current epoch, and so wouldn't be
+            // able to decrypt this reply; it's sent in the clear.
+            out_body[0] = code(Reject::BadNonce);
+            out_body[1..9].copy_from_slice(&self.last_counter.to_le_bytes());
+            (9, 0)
+        };
+
+        let out = AuthHeader {
+            epoch: U64::new(self.epoch),
+            counter: header.counter,
+            flags,
+        };
+        out_header.copy_from_slice(out.as_bytes());
+        let len = HEADER_SIZE + n;
+        let mut mac = keys.mac(Direction::Reply);
+        mac.update(&tx[..len]);
+        tx[len..][..TAG_SIZE].copy_from_slice(&mac.finalize().into_bytes());
+        len + TAG_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x5a; 32];
+    const EPOCH: u64 = 0x1234_5678_9abc_def0;
+
+    fn code(r: Reject) -> u8 {
+        match r {
+            Reject::TooShort => 1,
+            Reject::BadMac => 6,
+            Reject::BadNonce => 7,
+            Reject::NoKey => 8,
+        }
+    }
+
+    /// Builds a request as a client would, MACed with the key for `dir`
+    fn request(
+        epoch: u64,
+        counter: u64,
+        flags: u8,
+        body: &[u8],
+        dir: Direction,
+    ) -> Vec<u8> {
+        let keys = Keys::new(&KEY, epoch);
+        let header = AuthHeader {
+            epoch: U64::new(epoch),
+            counter: U64::new(counter),
+            flags,
+        };
+        let mut out = header.as_bytes().to_vec();
+        let mut body = body.to_vec();
+        if flags & FLAG_ENCRYPTED != 0 {
+            keys.apply_keystream(counter, Direction::Request, &mut body);
+        }
+        out.extend_from_slice(&body);
+        let mut mac = keys.mac(dir);
+        mac.update(&out);
+        out.extend_from_slice(&mac.finalize().into_bytes());
+        out
+    }
+
+    /// Runs `req` through `auth` with a dispatcher which echoes the request
+    /// body, returning the reply and the body seen by the dispatcher (if it
+    /// was called)
+    fn roundtrip(
+        auth: &mut Auth,
+        mut req: Vec<u8>,
+    ) -> (Vec<u8>, Option<Vec<u8>>) {
+        let mut tx = [0u8; 256];
+        let mut seen = None;
+        let n = auth.handle(
+            &mut req,
+            &mut tx,
+            |rx, tx| {
+                seen = Some(rx.to_vec());
+                tx[..rx.len()].copy_from_slice(rx);
+                rx.len()
+            },
+            code,
+        );
+        (tx[..n].to_vec(), seen)
+    }
+
+    /// Checks the reply's tag, and returns its header and body
+    fn open_reply(reply: &[u8]) -> (AuthHeader, Vec<u8>) {
+        let (signed, tag) = reply.split_at(reply.len() - TAG_SIZE);
+        let header = AuthHeader::read_from(&signed[..HEADER_SIZE]).unwrap();
+        let keys = Keys::new(&KEY, header.epoch.get());
+        let mut mac = keys.mac(Direction::Reply);
+        mac.update(signed);
+        mac.verify(tag).expect("reply tag should check out");
+        (header, signed[HEADER_SIZE..].to_vec())
+    }
+
+    #[test]
+    fn accepts_good_request() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let req = request(EPOCH, 1, 0, b"hello", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert_eq!(seen.as_deref(), Some(&b"hello"[..]));
+
+        let (header, body) = open_reply(&reply);
+        assert_eq!(header.epoch.get(), EPOCH);
+        assert_eq!(header.counter.get(), 1);
+        assert_eq!(header.flags, 0);
+        assert_eq!(body, b"hello");
+    }
+
+    #[test]
+    fn rejects_bad_mac_before_dispatch() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let mut req = request(EPOCH, 1, 0, b"hello", Direction::Request);
+        req[HEADER_SIZE] ^= 1;
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_eq!(reply, [code(Reject::BadMac)]);
+    }
+
+    #[test]
+    fn rejects_reflected_reply() {
+        // A reply has the same layout as a request, but is MACed with the
+        // reply key, so it can't be fed back to us.
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let req = request(EPOCH, 1, 0, b"hello", Direction::Reply);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_eq!(reply, [code(Reject::BadMac)]);
+    }
+
+    #[test]
+    fn rejects_short_request() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let (reply, seen) = roundtrip(&mut auth, vec![0; TAG_SIZE]);
+        assert!(seen.is_none());
+        assert_eq!(reply, [code(Reject::TooShort)]);
+    }
+
+    #[test]
+    fn rejects_everything_without_key() {
+        let mut auth = Auth::new(None, EPOCH);
+        let req = request(EPOCH, 1, 0, b"hello", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_eq!(reply, [code(Reject::NoKey)]);
+    }
+
+    #[test]
+    fn accepts_requests_once_key_is_set() {
+        let mut auth = Auth::new(None, EPOCH);
+        assert!(!auth.has_key());
+        auth.set_key(KEY);
+        assert!(auth.has_key());
+
+        let req = request(EPOCH, 1, 0, b"hello", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert_eq!(seen.as_deref(), Some(&b"hello"[..]));
+        let (_, body) = open_reply(&reply);
+        assert_eq!(body, b"hello");
+    }
+
+    /// Checks that `reply` is a `BadNonce` rejection reporting `last`
+    fn assert_bad_nonce(reply: &[u8], last: u64) {
+        let (header, body) = open_reply(reply);
+        assert_eq!(header.epoch.get(), EPOCH);
+        assert_eq!(header.flags, 0);
+        assert_eq!(body[0], code(Reject::BadNonce));
+        assert_eq!(body[1..], last.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_stale_counters() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let req = request(EPOCH, 5, 0, b"a", Direction::Request);
+        let (_, seen) = roundtrip(&mut auth, req.clone());
+        assert!(seen.is_some());
+
+        // Replaying the same request, or using an older counter, fails
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_bad_nonce(&reply, 5);
+        let req = request(EPOCH, 4, 0, b"a", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_bad_nonce(&reply, 5);
+
+        // Counters may skip ahead, but not past MAX_COUNTER
+        let req = request(EPOCH, 9, 0, b"a", Direction::Request);
+        assert!(roundtrip(&mut auth, req).1.is_some());
+        let req = request(EPOCH, MAX_COUNTER, 0, b"a", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_bad_nonce(&reply, 9);
+    }
+
+    #[test]
+    fn rejects_other_epochs() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+
+        // A client starts a session with an epoch of zero, and learns the
+        // current epoch from the rejection.
+        let req = request(0, 1, 0, b"a", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_bad_nonce(&reply, 0);
+
+        let req = request(EPOCH + 1, 1, 0, b"a", Direction::Request);
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert!(seen.is_none());
+        assert_bad_nonce(&reply, 0);
+    }
+
+    #[test]
+    fn encrypts_both_directions() {
+        let mut auth = Auth::new(Some(KEY), EPOCH);
+        let plain = b"a request body";
+        let req = request(EPOCH, 3, FLAG_ENCRYPTED, plain, Direction::Request);
+        assert!(!req.windows(plain.len()).any(|w| w == plain));
+
+        let (reply, seen) = roundtrip(&mut auth, req);
+        assert_eq!(seen.as_deref(), Some(&plain[..]));
+
+        let (header, mut body) = open_reply(&reply);
+        assert_eq!(header.counter.get(), 3);
+        assert_eq!(header.flags, FLAG_ENCRYPTED);
+        assert_ne!(body, plain);
+        let keys = Keys::new(&KEY, EPOCH);
+        keys.apply_keystream(3, Direction::Reply, &mut body);
+        assert_eq!(body, plain);
+    }
+}
@@ -8,7 +8,7 @@ use dice_crate::{
     AliasCertBuilder, AliasData, AliasOkm, Cdi, CdiL1, CertSerialNumber,
     DeviceIdOkm, Handoff, RngData, RngSeed, SeedBuf, SerialNumber,
     SpMeasureCertBuilder, SpMeasureData, SpMeasureOkm,
-    TrustQuorumDheCertBuilder, TrustQuorumDheOkm,
+    TrustQuorumDheCertBuilder, TrustQuorumDheOkm, UdpRpcData, UdpRpcOkm,
 };
 use lpc55_pac::Peripherals;
 use salty::signature::Keypair;
@@ -98,6 +98,13 @@ fn gen_rng_artifacts(cdi_l1: &CdiL1, handoff: &Handoff) {
     handoff.store(&rng_data);
 }
 
+fn gen_udprpc_artifacts(cdi: &Cdi, handoff: &Handoff) {
+    let udprpc_okm = UdpRpcOkm::from_cdi(cdi);
+    let udprpc_data = UdpRpcData::new(udprpc_okm);
+
+    handoff.store(&udprpc_data);
+}
+
 fn gen_fwid(image: &Image) -> [u8; 32] {
     // Collect hash(es) of TCB. The first TCB Component Identifier (TCI)
     // calculated is the Hubris image. The DICE specs call this collection
@@ -126,6 +133,7 @@ pub fn run(image: &Image) {
     };
 
     let deviceid_keypair = gen_deviceid_keypair(&cdi);
+    gen_udprpc_artifacts(&cdi, &handoff);
 
     let mut serial_numbers =
         gen_mfg_artifacts(&deviceid_keypair, &peripherals, &handoff);
@@ -29,6 +29,8 @@ struct Generated {
     tasks: Vec<TokenStream>,
     regions: Vec<TokenStream>,
     irq_code: TokenStream,
+    /// Description of the interrupt tables' size and lookup cost
+    irq_stats: Vec<String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -179,7 +181,7 @@ fn process_config() -> Result<Generated> {
     let task_irq_map = per_task_irqs.into_iter().collect::<Vec<_>>();
 
     let target = build_util::target();
-    let irq_code = if target.starts_with("thumbv6m") {
+    let hash = if target.starts_with("thumbv6m") {
         // On ARMv6-M we have no hardware division, which the perfect hash table
         // relies on (to get efficient integer remainder). Fall back to a good
         // old sorted list with binary search instead.
@@ -187,103 +189,69 @@ fn process_config() -> Result<Generated> {
         // This means our dispatch time for interrupts on ARMv6-M is O(log N)
         // instead of O(1), but these parts also tend to have few interrupts,
         // so, not the end of the world.
-
-        let task_irq_map = phash_gen::OwnedSortedList::build(task_irq_map)
-            .context("building task-to-IRQ map")?;
-        let irq_task_map = phash_gen::OwnedSortedList::build(irq_task_map)
-            .context("building IRQ-to-task map")?;
-
-        // Generate text for the Interrupt and InterruptSet tables stored in the
-        // sorted lists
-        let irq_task_literal = fmt_sorted_list(&irq_task_map, fmt_irq_task);
-        let task_irq_literal = fmt_sorted_list(&task_irq_map, fmt_task_irq);
-
-        quote::quote! {
-            pub const HUBRIS_IRQ_TASK_LOOKUP:
-                phash::SortedList<abi::InterruptNum, abi::InterruptOwner>
-                = #irq_task_literal;
-            pub const HUBRIS_TASK_IRQ_LOOKUP:
-                phash::SortedList<
-                abi::InterruptOwner,
-                &'static [abi::InterruptNum],
-                > = #task_irq_literal;
-        }
+        false
     } else if target.starts_with("thumbv7m")
         || target.starts_with("thumbv7em")
         || target.starts_with("thumbv8m")
     {
-        // First, try to build it as a single-level perfect hash map, which is
-        // cheaper but won't always succeed.
-        let map1 = if let Ok(task_irq_map) =
-            phash_gen::OwnedPerfectHashMap::build(task_irq_map.clone())
-        {
-            let map_literal =
-                fmt_perfect_hash_map(&task_irq_map, fmt_opt_task_irq);
-            quote::quote! {
-                pub const HUBRIS_TASK_IRQ_LOOKUP:
-                    phash::PerfectHashMap<
-                    '_,
-                    abi::InterruptOwner,
-                    &'static [abi::InterruptNum],
-                    > = #map_literal;
-            }
-        } else {
-            // Single-level perfect hash failed, make it work with a nested map.
-            let task_irq_map =
-                phash_gen::OwnedNestedPerfectHashMap::build(task_irq_map)
-                    .context("building task-to-IRQ perfect hash")?;
-            let task_irq_literal =
-                fmt_nested_perfect_hash_map(&task_irq_map, fmt_opt_task_irq);
-            quote::quote! {
-                pub const HUBRIS_TASK_IRQ_LOOKUP:
-                    phash::NestedPerfectHashMap<
-                    abi::InterruptOwner,
-                    &'static [abi::InterruptNum],
-                    > = #task_irq_literal;
-            }
-        };
-
-        // And now repeat the process for the IRQ-to-task direction.
-        let map2 = if let Ok(irq_task_map) =
-            phash_gen::OwnedPerfectHashMap::build(irq_task_map.clone())
-        {
-            let map_literal =
-                fmt_perfect_hash_map(&irq_task_map, fmt_opt_irq_task);
-            quote::quote! {
-                pub const HUBRIS_IRQ_TASK_LOOKUP:
-                    phash::PerfectHashMap<
-                    '_,
-                    abi::InterruptNum,
-                    abi::InterruptOwner,
-                    > = #map_literal;
-            }
-        } else {
-            let irq_task_map =
-                phash_gen::OwnedNestedPerfectHashMap::build(irq_task_map)
-                    .context("building IRQ-to-task perfect hash")?;
-            let map_literal =
-                fmt_nested_perfect_hash_map(&irq_task_map, fmt_opt_irq_task);
-            quote::quote! {
-                pub const HUBRIS_IRQ_TASK_LOOKUP:
-                    phash::NestedPerfectHashMap<
-                    abi::InterruptNum,
-                    abi::InterruptOwner,
-                    > = #map_literal;
-            }
-        };
-
-        quote::quote! {
-            #map1
-            #map2
-        }
+        true
     } else {
         panic!("Don't know the target {target}");
     };
 
+    // Use a single-level perfect hash if possible, then a nested perfect
+    // hash, falling back to a sorted list if neither can be found.
+    let task_irq_map = phash_gen::OwnedLookup::build(task_irq_map, hash)
+        .context("building task-to-IRQ map")?;
+    let irq_task_map = phash_gen::OwnedLookup::build(irq_task_map, hash)
+        .context("building IRQ-to-task map")?;
+
+    // Cargo hides a build script's output unless it fails, so the stats are
+    // only shown (as warnings) when asked for, e.g. by `xtask dist -v`. A
+    // failure to find a perfect hash is always shown.
+    let show_stats = build_util::env_var("HUBRIS_IRQ_STATS").is_ok();
+    let mut irq_stats = vec![];
+    for (name, stats, is_list) in [
+        (
+            "task-to-IRQ",
+            task_irq_map.stats(),
+            matches!(task_irq_map, phash_gen::OwnedLookup::SortedList(..)),
+        ),
+        (
+            "IRQ-to-task",
+            irq_task_map.stats(),
+            matches!(irq_task_map, phash_gen::OwnedLookup::SortedList(..)),
+        ),
+    ] {
+        let msg = format!("{name} map: {stats}");
+        if hash && is_list && stats.entries > 0 {
+            println!("cargo:warning=no perfect hash found; {msg}");
+        } else if show_stats {
+            println!("cargo:warning={msg}");
+        }
+        irq_stats.push(msg);
+    }
+
+    let task_irq_literal = fmt_lookup(&task_irq_map, fmt_opt_task_irq);
+    let task_irq_type = lookup_type(
+        &task_irq_map,
+        quote::quote! { abi::InterruptOwner, &'static [abi::InterruptNum] },
+    );
+    let irq_task_literal = fmt_lookup(&irq_task_map, fmt_opt_irq_task);
+    let irq_task_type = lookup_type(
+        &irq_task_map,
+        quote::quote! { abi::InterruptNum, abi::InterruptOwner },
+    );
+    let irq_code = quote::quote! {
+        pub const HUBRIS_TASK_IRQ_LOOKUP: #task_irq_type = #task_irq_literal;
+        pub const HUBRIS_IRQ_TASK_LOOKUP: #irq_task_type = #irq_task_literal;
+    };
+
     Ok(Generated {
         tasks: task_descs,
         regions: region_descs,
         irq_code,
+        irq_stats,
     })
 }
 
@@ -449,6 +417,9 @@ fn generate_statics(gen: &Generated) -> Result<()> {
     /////////////////////////////////////////////////////////
     // Interrupt table
 
+    for line in &gen.irq_stats {
+        writeln!(file, "// {}", line)?;
+    }
     writeln!(file, "{}", gen.irq_code)?;
 
     drop(file);
@@ -499,9 +470,9 @@ fn fmt_irq_task(irq: &u32, owner: &InterruptConfig) -> TokenStream {
 
 fn fmt_sorted_list<K, V>(
     list: &phash_gen::OwnedSortedList<K, V>,
-    element: impl Fn(&K, &V) -> TokenStream,
+    element: impl Fn(Option<&(K, V)>) -> TokenStream,
 ) -> TokenStream {
-    let values = list.values.iter().map(|(k, v)| element(k, v));
+    let values = list.values.iter().map(|v| element(Some(v)));
     quote::quote! {
         phash::SortedList {
             values: &[#(#values,)*],
@@ -515,9 +486,11 @@ fn fmt_perfect_hash_map<K, V>(
 ) -> TokenStream {
     let values = map.values.iter().map(|o| element(o.as_ref()));
     let m = map.m;
+    let shift = map.shift;
     quote::quote! {
         phash::PerfectHashMap {
             m: #m,
+            shift: #shift,
             values: &[#(#values,)*],
         }
     }
@@ -534,12 +507,47 @@ fn fmt_nested_perfect_hash_map<K, V>(
         }
     });
     let m = map.m;
+    let shift = map.shift;
     let g = &map.g;
     quote::quote! {
         phash::NestedPerfectHashMap {
             m: #m,
+            shift: #shift,
             g: &[#(#g,)*],
             values: &[#(#values,)*],
         }
     }
 }
+
+fn fmt_lookup<K, V>(
+    lookup: &phash_gen::OwnedLookup<K, V>,
+    element: impl Fn(Option<&(K, V)>) -> TokenStream,
+) -> TokenStream {
+    match lookup {
+        phash_gen::OwnedLookup::PerfectHash(m) => {
+            fmt_perfect_hash_map(m, element)
+        }
+        phash_gen::OwnedLookup::NestedPerfectHash(m) => {
+            fmt_nested_perfect_hash_map(m, element)
+        }
+        phash_gen::OwnedLookup::SortedList(m) => fmt_sorted_list(m, element),
+    }
+}
+
+/// Returns the `phash` type for a lookup table, given its key and value types
+fn lookup_type<K, V>(
+    lookup: &phash_gen::OwnedLookup<K, V>,
+    kv: TokenStream,
+) -> TokenStream {
+    match lookup {
+        phash_gen::OwnedLookup::PerfectHash(..) => {
+            quote::quote! { phash::PerfectHashMap<'static, #kv> }
+        }
+        phash_gen::OwnedLookup::NestedPerfectHash(..) => {
+            quote::quote! { phash::NestedPerfectHashMap<'static, #kv> }
+        }
+        phash_gen::OwnedLookup::SortedList(..) => {
+            quote::quote! { phash::SortedList<'static, #kv> }
+        }
+    }
+}
@@ -419,6 +419,10 @@ impl From<SensorErrorConvert> for MeasurementError {
             SensorError::DeviceUnavailable => Self::DeviceUnavailable,
             SensorError::DeviceTimeout => Self::DeviceTimeout,
             SensorError::DeviceOff => Self::DeviceOff,
+            // We never ask for sensor history: the MGS protocol (from
+            // `gateway-messages`) has no message to carry it, so it isn't
+            // forwarded to MGS, and is read through hiffy or udprpc instead.
+            SensorError::NoHistory => Self::InvalidSensor,
         }
     }
 }
@@ -17,10 +17,10 @@ use task_control_plane_agent_api::{
     ControlPlaneAgentError, Identity, UartClient,
 };
 use task_net_api::{
-    Address, LargePayloadBehavior, Net, RecvError, SendError, SocketName,
-    UdpMetadata,
+    Address, LargePayloadBehavior, LinkState, Net, RecvError, SendError,
+    SocketName, UdpMetadata, LINK_EVENTS_PER_READ,
 };
-use userlib::{sys_set_timer, task_slot};
+use userlib::{sys_refresh_task_id, sys_set_timer, task_slot, Generation};
 
 mod inventory;
 mod mgs_common;
@@ -58,6 +58,8 @@ enum Log {
     UpdatePartial { bytes_written: u32 },
     UpdateComplete,
     HostFlashSectorsErased { num_sectors: usize },
+    LinkEvent { link: u8, state: LinkState },
+    LinkEventsMissed { count: u32 },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -142,6 +144,9 @@ const USART_IRQ: u32 = 1 << 1;
 // Must not conflict with IRQs above!
 const TIMER_IRQ: u32 = 1 << 2;
 
+// Must match `link-notify` in app.toml!
+const LINK_IRQ: u32 = 1 << 3;
+
 const SOCKET: SocketName = SocketName::control_plane_agent;
 
 #[export_name = "main"]
@@ -235,7 +240,7 @@ impl ServerImpl {
 
 impl NotificationHandler for ServerImpl {
     fn current_notification_mask(&self) -> u32 {
-        NET_IRQ | USART_IRQ | TIMER_IRQ
+        NET_IRQ | USART_IRQ | TIMER_IRQ | LINK_IRQ
     }
 
     fn handle_notification(&mut self, bits: u32) {
@@ -247,7 +252,13 @@ impl NotificationHandler for ServerImpl {
             self.mgs_handler.handle_timer_fired();
         }
 
+        // If a management link has come back up, retry any packet that we
+        // were holding on to, rather than waiting for `net` to wake us.
+        let link_up =
+            (bits & LINK_IRQ) != 0 && self.net_handler.read_link_events();
+
         if (bits & NET_IRQ) != 0
+            || link_up
             || self.mgs_handler.wants_to_send_packet_to_mgs()
         {
             self.net_handler.run_until_blocked(&mut self.mgs_handler);
@@ -402,6 +413,11 @@ struct NetHandler {
     tx_buf: &'static mut [u8; gateway_messages::MAX_SERIALIZED_SIZE],
     rx_buf: &'static mut [u8; gateway_messages::MAX_SERIALIZED_SIZE],
     packet_to_send: Option<UdpMetadata>,
+    /// Sequence number of the last link event read from `net`
+    last_link_event: u32,
+    /// Generation of `net` as of our last read; its sequence numbers start
+    /// over when it restarts
+    net_generation: Generation,
 }
 
 impl NetHandler {
@@ -418,6 +434,39 @@ impl NetHandler {
             tx_buf,
             rx_buf,
             packet_to_send: None,
+            last_link_event: 0,
+            net_generation: NET.get_task_id().generation(),
+        }
+    }
+
+    /// Reads and logs the link events which `net` has recorded since we last
+    /// looked, returning `true` if any link came up.
+    fn read_link_events(&mut self) -> bool {
+        let generation = sys_refresh_task_id(NET.get_task_id()).generation();
+        if generation != self.net_generation {
+            self.net_generation = generation;
+            self.last_link_event = 0;
+        }
+
+        let mut link_up = false;
+        loop {
+            let events = self.net.get_link_events(self.last_link_event);
+            let events = &events.events[..usize::from(events.count)];
+            for e in events {
+                let missed = e.seq - self.last_link_event - 1;
+                if missed > 0 {
+                    ringbuf_entry!(Log::LinkEventsMissed { count: missed });
+                }
+                ringbuf_entry!(Log::LinkEvent {
+                    link: e.link,
+                    state: e.state,
+                });
+                link_up |= matches!(e.state, LinkState::Up(_));
+                self.last_link_event = e.seq;
+            }
+            if events.len() < LINK_EVENTS_PER_READ {
+                return link_up;
+            }
         }
     }
 
@@ -542,6 +542,10 @@ pub(crate) fn sprot_send_recv(
         frame[0].ok_or(Failure::Fault(Fault::BadParameter(0)))? as u8,
     )
     .ok_or(Failure::Fault(Fault::BadParameter(0)))?;
+    // The udprpc key is secret, and is only for `udprpc` to ask for
+    if matches!(msgtype, drv_sprot_api::MsgType::UdpRpcKeyReq) {
+        return Err(Failure::Fault(Fault::BadParameter(0)));
+    }
     let len: usize =
         frame[1].ok_or(Failure::Fault(Fault::BadParameter(1)))? as usize;
     let result =
@@ -582,6 +586,10 @@ pub(crate) fn sprot_send_recv_retries(
         frame[0].ok_or(Failure::Fault(Fault::BadParameter(0)))? as u8,
     )
     .ok_or(Failure::Fault(Fault::BadParameter(0)))?;
+    // The udprpc key is secret, and is only for `udprpc` to ask for
+    if matches!(msgtype, drv_sprot_api::MsgType::UdpRpcKeyReq) {
+        return Err(Failure::Fault(Fault::BadParameter(0)));
+    }
     let attempts =
         frame[1].ok_or(Failure::Fault(Fault::BadParameter(1)))? as u16;
     let len: usize =
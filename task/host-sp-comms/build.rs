@@ -2,6 +2,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use build_stm32pins::{Chip, PinConfig};
+use serde::Deserialize;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    #[serde(default)]
+    pins: Vec<PinConfig>,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
 
@@ -11,5 +21,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         idol::server::ServerStyle::InOrder,
     )?;
 
+    let task_config =
+        build_util::task_maybe_config::<TaskConfig>()?.unwrap_or_default();
+    build_stm32pins::codegen(Chip::from_features()?, task_config.pins)?;
+
     Ok(())
 }
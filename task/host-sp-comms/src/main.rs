@@ -37,6 +37,8 @@ task_slot!(HOST_FLASH, hf);
 task_slot!(NET, net);
 task_slot!(SYS, sys);
 
+include!(concat!(env!("OUT_DIR"), "/pin_config.rs"));
+
 // TODO: When rebooting the host, we need to wait for the relevant power rails
 // to decay. We ought to do this properly by monitoring the rails, but for now,
 // we'll simply wait a fixed period of time. This time is a WAG - we should
@@ -931,6 +933,9 @@ enum Action {
     ClearStatusBits(Status),
 }
 
+#[cfg(not(feature = "hardware_flow_control"))]
+compile_error!("hardware_flow_control should be enabled");
+
 #[cfg(any(feature = "stm32h743", feature = "stm32h753"))]
 fn configure_uart_device(sys: &sys_api::Sys) -> Usart {
     use drv_usart::device;
@@ -951,35 +956,26 @@ fn configure_uart_device(sys: &sys_api::Sys) -> Usart {
 
     cfg_if::cfg_if! {
         if #[cfg(feature = "uart7")] {
-            const PINS: &[(PinSet, Alternate)] = {
-                cfg_if::cfg_if! {
-                    if #[cfg(feature = "hardware_flow_control")] {
-                        &[(
-                            Port::E.pin(7).and_pin(8).and_pin(9).and_pin(10),
-                            Alternate::AF7
-                        )]
-                    } else {
-                        compile_error!("hardware_flow_control should be enabled");
-                    }
-                }
-            };
             let usart = unsafe { &*device::UART7::ptr() };
             let peripheral = Peripheral::Uart7;
-            let pins = PINS;
         } else {
             compile_error!("no usartX/uartX feature specified");
         }
     }
 
-    Usart::turn_on(
+    // The UART's pins (including RTS and CTS) are declared in `app.toml`, and
+    // configured by the generated `setup_pins` rather than by `turn_on`.
+    let usart = Usart::turn_on(
         sys,
         usart,
         peripheral,
-        pins,
+        &[],
         CLOCK_HZ,
         BAUD_RATE,
         hardware_flow_control,
-    )
+    );
+    setup_pins(sys).unwrap();
+    usart
 }
 
 cfg_if::cfg_if! {
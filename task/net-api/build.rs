@@ -2,6 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::io::Write;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     idol::client::build_client_stub("../../idl/net.idol", "client_stub.rs")?;
 
@@ -16,5 +18,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     build_net::generate_socket_enum(&net_config, &mut out)?;
+    writeln!(
+        out,
+        "pub const SOCKET_COUNT: usize = {};",
+        net_config.sockets.len()
+    )?;
     Ok(())
 }
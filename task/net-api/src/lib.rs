@@ -17,7 +17,8 @@ pub enum SendError {
     /// The selected socket is not owned by this task
     NotYours = 1,
 
-    /// The specified VID is not in the configured range
+    /// The specified VID is not in the configured range, or the socket is
+    /// not bound on that VLAN
     InvalidVLan = 2,
 
     /// The outgoing tx queue is full
@@ -38,6 +39,88 @@ pub enum RecvError {
     Other = 3,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum TcpError {
+    /// The selected socket is not owned by this task
+    NotYours = 1,
+
+    /// The selected socket is not a TCP socket
+    NotTcp,
+
+    /// The specified VID is not in the configured range, or the socket is
+    /// not bound on that VLAN
+    InvalidVLan,
+
+    /// The socket has no connection matching the given `TcpMetadata`
+    NotConnected,
+
+    /// There's nothing to accept or read, or no room to write; the owner
+    /// will be notified when that changes
+    WouldBlock,
+
+    /// The remote end has closed the connection
+    Closed,
+
+    /// The socket is already listening or connected
+    InvalidState,
+
+    /// The net task was built without TCP support
+    NotAvailable,
+
+    Other,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum AddressError {
+    /// The specified VID is not in the configured range
+    InvalidVLan = 1,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum StatsError {
+    /// The specified VID is not in the configured range
+    InvalidVLan = 1,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum CaptureError {
+    /// The net task was built without the `pcap` feature
+    NotAvailable = 1,
+
+    /// The filter's VID is not in the configured range
+    InvalidVLan = 2,
+
+    /// The buffer passed to `capture_read` can't hold the next block
+    BufferTooSmall = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum MulticastError {
+    /// The selected socket is not owned by this task
+    NotYours = 1,
+
+    /// The selected socket is not a UDP socket
+    NotUdp = 2,
+
+    /// The specified VID is not in the configured range, or the socket is
+    /// not bound on that VLAN
+    InvalidVLan = 3,
+
+    /// The address isn't a multicast group that can be joined
+    InvalidGroup = 4,
+
+    /// The interface's table of group memberships is full
+    NoSpace = 5,
+
+    /// The net task was built without the `multicast` feature
+    NotAvailable = 6,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
 #[repr(u32)]
 pub enum PhyError {
@@ -124,6 +207,56 @@ pub struct MacAddressBlock {
     pub stride: u8,
 }
 
+/// Static global IPv6 configuration for one interface, as stored in VPD (one
+/// record per VLAN, in order) or generated from `app.toml`.
+///
+/// An all-zero `addr` means that the interface has no static address, and an
+/// all-zero `gateway` means that it has no static default router.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, FromBytes, AsBytes, Default)]
+#[repr(C)]
+pub struct Ipv6StaticAddress {
+    pub addr: [u8; 16],
+    pub gateway: [u8; 16],
+    pub prefix_len: u8,
+}
+
+impl Ipv6StaticAddress {
+    /// Checks this configuration against the same rules that are applied to
+    /// `app.toml` at build time: the address (if any) must be a global
+    /// unicast address with a prefix length of 1-128, and the gateway (if
+    /// any) must not be a multicast address.
+    pub fn is_valid(&self) -> bool {
+        let a = &self.addr;
+        let multicast = a[0] == 0xff;
+        let link_local = a[0] == 0xfe && a[1] & 0xc0 == 0x80;
+        let addr_ok = *a == [0; 16]
+            || (!multicast
+                && !link_local
+                && (1..=128).contains(&self.prefix_len));
+        addr_ok && self.gateway[0] != 0xff
+    }
+}
+
+/// An IPv6 address along with its prefix length
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Ipv6Net {
+    pub addr: Ipv6Address,
+    pub prefix_len: u8,
+}
+
+/// Addresses assigned to one interface, as reported by `get_ipv6_addresses`
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Ipv6Addresses {
+    /// Link-local address, derived from the interface's MAC address
+    pub link_local: Ipv6Address,
+    /// Static address from VPD or `app.toml`
+    pub static_addr: Option<Ipv6Net>,
+    /// Address configured from router advertisements
+    pub slaac: Option<Ipv6Net>,
+    /// Default router for off-link destinations, if there is one
+    pub router: Option<Ipv6Address>,
+}
+
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 #[repr(C)]
 pub struct ManagementLinkStatus {
@@ -162,6 +295,143 @@ pub struct ManagementCounters {
     pub vsc85x2_mac_valid: bool,
 }
 
+/// Counters for one socket on one interface. These (like all of the counters
+/// in `NetStats`) wrap on overflow. For TCP sockets, each `tcp_read` and
+/// `tcp_write` call counts as a packet, and only those fields are used.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SocketStats {
+    /// Packets delivered by `recv_packet` (or successful `tcp_read` calls)
+    pub rx_packets: u32,
+    /// Packets discarded by `recv_packet` because they were larger than the
+    /// caller's buffer
+    pub rx_discarded: u32,
+    /// Packets which arrived for the socket's port but were never queued,
+    /// e.g. because the socket's rx queue was full (not counting those which
+    /// were filtered or rate limited). This is only brought up to date when
+    /// the queue is empty, since that's when we know that every arrival has
+    /// been accounted for.
+    pub rx_dropped: u32,
+    /// Packets dropped because their source isn't in the socket's `allow`
+    /// list
+    pub rx_filtered: u32,
+    /// Packets dropped by the socket's rate limits
+    pub rx_rate_limited: u32,
+    /// Packets queued by `send_packet` (or successful `tcp_write` calls)
+    pub tx_packets: u32,
+    /// `send_packet` calls which failed with `SendError::QueueFull` (or
+    /// `tcp_write` calls which would block)
+    pub tx_queue_full: u32,
+    /// `send_packet` calls which failed for any other reason
+    pub tx_errors: u32,
+}
+
+/// Counters for one interface (i.e. one VLAN)
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InterfaceStats {
+    pub rx_frames: u32,
+    pub rx_bytes: u32,
+    pub tx_frames: u32,
+    pub tx_bytes: u32,
+}
+
+/// Counters from the Ethernet MAC's DMA, which is shared by all interfaces
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MacStats {
+    pub rx_packets: u32,
+    /// Packets dropped because the hardware flagged an error
+    pub rx_errors: u32,
+    /// Packets dropped because they weren't on any of our VLANs
+    pub rx_vlan_dropped: u32,
+    /// Packets dropped by the DMA, e.g. because the rx ring was full
+    pub rx_missed: u32,
+    pub tx_packets: u32,
+    /// Attempts to send while the tx ring was full
+    pub tx_ring_full: u32,
+}
+
+/// Statistics for one interface, as reported by `get_stats`
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct NetStats {
+    pub interface: InterfaceStats,
+    /// Per-socket counters, indexed by `SocketName`, for both UDP and TCP
+    /// sockets. Sockets which aren't bound on this interface are all zeros.
+    pub sockets: [SocketStats; SOCKET_COUNT],
+    pub mac: MacStats,
+}
+
+/// Selects which frames are captured by `capture_start`. A frame must pass
+/// every filter that's present.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CaptureFilter {
+    /// Only capture frames on this VLAN (ignored without VLANs)
+    pub vid: Option<u16>,
+    /// Only capture UDP or TCP packets with this source or destination port
+    pub port: Option<u16>,
+}
+
+/// Result of `capture_read`
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct CaptureRead {
+    /// Number of bytes written, which is always a whole number of pcapng
+    /// blocks
+    pub len: u32,
+    /// Frames which matched the filter but were dropped because the capture
+    /// buffer was full, since the previous `capture_read`
+    pub dropped: u32,
+}
+
+/// Number of Ethernet links whose state is reported by `get_link_events`
+pub const LINK_COUNT: usize = 2;
+
+/// Number of events returned by each call to `get_link_events`
+pub const LINK_EVENTS_PER_READ: usize = 8;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LinkSpeed {
+    Mbps10,
+    Mbps100,
+    Mbps1000,
+}
+
+// This must be repr(C); otherwise Rust cleverly optimizes out the enum tag,
+// which breaks ssmarshal's assumptions about struct sizes.
+#[derive(
+    Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq,
+)]
+#[repr(C)]
+pub enum LinkState {
+    /// The link isn't monitored on this board, or hasn't been read yet
+    #[default]
+    Unknown,
+    Down,
+    Up(LinkSpeed),
+}
+
+/// A change in the state of a link
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LinkEvent {
+    /// Sequence number, which increases by one with each event (starting at
+    /// one), so that gaps in the history can be spotted
+    pub seq: u32,
+    /// Kernel timestamp at which the change was seen
+    pub time: u64,
+    /// Index of the link
+    pub link: u8,
+    /// New state of the link
+    pub state: LinkState,
+}
+
+/// Result of `get_link_events`
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LinkEvents {
+    /// Current state of each link
+    pub links: [LinkState; LINK_COUNT],
+    /// Events, oldest first; only the first `count` are valid. If it's
+    /// full, there may be more events to read.
+    pub events: [LinkEvent; LINK_EVENTS_PER_READ],
+    pub count: u8,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
 #[repr(u32)]
 pub enum MgmtError {
@@ -197,6 +467,28 @@ pub struct UdpMetadata {
     pub vid: u16,
 }
 
+/// Identifies a TCP connection: the remote endpoint (and VLAN) of a socket's
+/// current connection.  This is returned by `tcp_accept`, and passed to
+/// `tcp_connect`, `tcp_read`, `tcp_write`, and `tcp_close`.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TcpMetadata {
+    pub addr: Address,
+    pub port: u16,
+
+    #[cfg(feature = "vlan")]
+    pub vid: u16,
+}
+
+#[cfg(feature = "use-smoltcp")]
+impl From<TcpMetadata> for smoltcp::wire::IpEndpoint {
+    fn from(m: TcpMetadata) -> Self {
+        Self {
+            addr: m.addr.into(),
+            port: m.port,
+        }
+    }
+}
+
 #[cfg(feature = "use-smoltcp")]
 impl From<UdpMetadata> for smoltcp::wire::IpEndpoint {
     fn from(m: UdpMetadata) -> Self {
@@ -2,10 +2,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use build_net::{BufSize, NetConfig, SocketConfig};
+use build_net::{BufSize, NetConfig, SocketConfig, TokenBucketConfig};
+use build_stm32pins::{Chip, PinConfig};
 use proc_macro2::TokenStream;
+use serde::Deserialize;
 use std::io::Write;
 
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    #[serde(default)]
+    pins: Vec<PinConfig>,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     idol::server::build_server_support(
         "../../idl/net.idol",
@@ -15,9 +24,47 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let net_config = build_net::load_net_config()?;
 
+    // As with VLANs, TCP support must be enabled iff it's used, since it
+    // adds a fair amount of code to smoltcp.
+    let has_tcp = net_config.sockets.values().any(|s| s.kind == "tcp");
+    match (build_util::has_feature("tcp"), has_tcp) {
+        (true, false) => {
+            return Err("TCP feature is enabled, but no TCP sockets are \
+                        configured"
+                .into())
+        }
+        (false, true) => {
+            return Err("TCP sockets are configured, but the TCP feature \
+                        is disabled"
+                .into())
+        }
+        _ => (),
+    }
+
+    // Likewise for SLAAC, which needs raw sockets to see router
+    // advertisements.
+    let has_slaac = net_config.ipv6.as_ref().map_or(false, |c| c.slaac);
+    match (build_util::has_feature("slaac"), has_slaac) {
+        (true, false) => {
+            return Err("SLAAC feature is enabled, but slaac is not \
+                        configured"
+                .into())
+        }
+        (false, true) => {
+            return Err("SLAAC is configured, but the SLAAC feature is \
+                        disabled"
+                .into())
+        }
+        _ => (),
+    }
+
     generate_net_config(&net_config)?;
     build_util::expose_target_board();
 
+    let task_config =
+        build_util::task_maybe_config::<TaskConfig>()?.unwrap_or_default();
+    build_stm32pins::codegen(Chip::from_features()?, task_config.pins)?;
+
     Ok(())
 }
 
@@ -40,6 +87,15 @@ fn generate_net_config(
             pub const SOCKET_COUNT: usize = #socket_count;
         }
     )?;
+    if build_util::has_feature("tcp") {
+        writeln!(
+            out,
+            "{}",
+            quote::quote! {
+                use smoltcp::socket::{TcpSocket, TcpSocketBuffer};
+            }
+        )?;
+    }
 
     if build_util::has_feature("vlan") {
         build_net::generate_vlan_consts(config, &mut out)?;
@@ -52,14 +108,20 @@ fn generate_net_config(
             generate_socket_state(
                 name,
                 socket,
-                config.vlan.map(|v| v.count).unwrap_or(1)
+                socket.vlan_indices(config.vlan).len()
             )?
         )?;
     }
     writeln!(out, "{}", generate_state_struct(config))?;
     writeln!(out, "{}", generate_constructor(config)?)?;
     writeln!(out, "{}", generate_owner_info(config)?)?;
+    writeln!(out, "{}", generate_link_subscribers(config))?;
     writeln!(out, "{}", generate_port_table(config)?)?;
+    writeln!(out, "{}", generate_kind_table(config))?;
+    writeln!(out, "{}", generate_vlan_mask_table(config)?)?;
+    writeln!(out, "{}", generate_global_table(config))?;
+    writeln!(out, "{}", generate_filter_table(config))?;
+    writeln!(out, "{}", generate_ipv6_static(config))?;
 
     build_net::generate_socket_enum(config, &mut out)?;
 
@@ -87,6 +149,148 @@ fn generate_port_table(
     })
 }
 
+fn generate_kind_table(config: &NetConfig) -> TokenStream {
+    let kinds = config.sockets.values().map(|socket| match &*socket.kind {
+        "tcp" => quote::quote! { SocketKind::Tcp },
+        _ => quote::quote! { SocketKind::Udp },
+    });
+    let tcp = build_util::has_feature("tcp").then(|| quote::quote! { Tcp, });
+
+    let n = config.sockets.len();
+
+    quote::quote! {
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub(crate) enum SocketKind {
+            Udp,
+            #tcp
+        }
+
+        pub(crate) const SOCKET_KINDS: [SocketKind; #n] = [
+            #( #kinds ),*
+        ];
+    }
+}
+
+/// Generates a mask of the VLANs (by index) on which each socket is bound,
+/// so that incoming packets can be matched to the right socket when sockets
+/// on different VLANs share a port.
+fn generate_vlan_mask_table(
+    config: &NetConfig,
+) -> Result<TokenStream, Box<dyn std::error::Error>> {
+    let masks = config
+        .sockets
+        .iter()
+        .map(|(name, socket)| {
+            socket.vlan_indices(config.vlan).into_iter().try_fold(
+                0u32,
+                |mask, i| match 1u32.checked_shl(i as u32) {
+                    Some(bit) => Ok(mask | bit),
+                    None => Err(format!(
+                        "socket {name}: VLAN index {i} doesn't fit in a mask"
+                    )),
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let n = config.sockets.len();
+
+    Ok(quote::quote! {
+        pub(crate) const SOCKET_VLANS: [u32; #n] = [
+            #( #masks ),*
+        ];
+    })
+}
+
+fn generate_global_table(config: &NetConfig) -> TokenStream {
+    let global = config.sockets.values().map(|socket| socket.global);
+    let n = config.sockets.len();
+
+    quote::quote! {
+        pub(crate) const SOCKET_GLOBAL: [bool; #n] = [
+            #( #global ),*
+        ];
+    }
+}
+
+/// Generates each socket's source filter and rate limits, for `filter.rs`.
+fn generate_filter_table(config: &NetConfig) -> TokenStream {
+    let bucket = |b: Option<TokenBucketConfig>| match b {
+        Some(TokenBucketConfig { rate, burst }) => quote::quote! {
+            Some(crate::filter::TokenBucketConfig {
+                rate: #rate,
+                burst: #burst,
+            })
+        },
+        None => quote::quote! { None },
+    };
+    let filters = config.sockets.values().map(|socket| {
+        let (limit, per_source) = match &socket.rate_limit {
+            Some(r) => (bucket(r.socket), bucket(r.per_source)),
+            None => (bucket(None), bucket(None)),
+        };
+        let allow = socket.allow.iter().flatten().map(|p| {
+            let addr = p.address.octets();
+            let len = p.len;
+            quote::quote! {
+                crate::filter::Prefix { addr: [#( #addr ),*], len: #len }
+            }
+        });
+        quote::quote! {
+            crate::filter::FilterConfig {
+                socket: #limit,
+                per_source: #per_source,
+                allow: &[ #( #allow ),* ],
+            }
+        }
+    });
+    let per_source = config.sockets.values().any(|s| {
+        s.rate_limit
+            .as_ref()
+            .map_or(false, |r| r.per_source.is_some())
+    });
+    let n = config.sockets.len();
+
+    quote::quote! {
+        pub(crate) const SOCKET_FILTERS: [crate::filter::FilterConfig; #n] = [
+            #( #filters ),*
+        ];
+        pub(crate) const PER_SOURCE_LIMITS: bool = #per_source;
+    }
+}
+
+/// Generates the static IPv6 address for each interface, in the same form
+/// as it's stored in VPD (all zeros for no address).
+fn generate_ipv6_static(config: &NetConfig) -> TokenStream {
+    let n = config.vlan.map(|v| v.count).unwrap_or(1);
+    let addrs = match &config.ipv6 {
+        Some(ipv6) => ipv6.static_addresses(config.vlan),
+        None => vec![None; n],
+    };
+    let addrs = addrs.into_iter().map(|a| {
+        let (addr, gateway, prefix_len) = match a {
+            Some(a) => (
+                a.address.octets(),
+                a.gateway.map(|g| g.octets()).unwrap_or_default(),
+                a.prefix_len,
+            ),
+            None => Default::default(),
+        };
+        quote::quote! {
+            task_net_api::Ipv6StaticAddress {
+                addr: [#( #addr ),*],
+                gateway: [#( #gateway ),*],
+                prefix_len: #prefix_len,
+            }
+        }
+    });
+
+    quote::quote! {
+        pub(crate) const IPV6_STATIC: [task_net_api::Ipv6StaticAddress; #n] = [
+            #( #addrs ),*
+        ];
+    }
+}
+
 fn generate_owner_info(
     config: &NetConfig,
 ) -> Result<TokenStream, Box<dyn std::error::Error>> {
@@ -113,17 +317,44 @@ fn generate_owner_info(
     })
 }
 
+fn generate_link_subscribers(config: &NetConfig) -> TokenStream {
+    let consts = config.link_notify.iter().map(|t| {
+        let task: syn::Ident = syn::parse_str(&t.name).unwrap();
+        let note = t.notification;
+        quote::quote! {
+            (
+                userlib::TaskId::for_index_and_gen(
+                    hubris_num_tasks::Task::#task as usize,
+                    userlib::Generation::ZERO,
+                ),
+                #note,
+            )
+        }
+    });
+
+    let n = config.link_notify.len();
+
+    quote::quote! {
+        pub(crate) const LINK_SUBSCRIBERS: [(userlib::TaskId, u32); #n] = [
+            #( #consts ),*
+        ];
+    }
+}
+
 fn generate_socket_state(
     name: &str,
     config: &SocketConfig,
     vlan_count: usize,
 ) -> Result<TokenStream, Box<dyn std::error::Error>> {
-    if config.kind != "udp" {
-        return Err("unsupported socket kind".into());
-    }
+    let udp = match &*config.kind {
+        "udp" => true,
+        "tcp" => false,
+        _ => return Err("unsupported socket kind".into()),
+    };
 
-    let tx = generate_buffers(name, "TX", &config.tx, vlan_count);
-    let rx = generate_buffers(name, "RX", &config.rx, vlan_count);
+    let section = config.section.as_deref();
+    let tx = generate_buffers(name, "TX", &config.tx, section, udp, vlan_count);
+    let rx = generate_buffers(name, "RX", &config.rx, section, udp, vlan_count);
     Ok(quote::quote! {
         #tx
         #rx
@@ -134,6 +365,8 @@ fn generate_buffers(
     name: &str,
     dir: &str,
     config: &BufSize,
+    section: Option<&str>,
+    headers: bool,
     vlan_count: usize,
 ) -> TokenStream {
     let pktcnt = config.packets;
@@ -143,25 +376,44 @@ fn generate_buffers(
         syn::parse_str(&format!("SOCK_{}_HDR_{}", dir, upname)).unwrap();
     let bufname: syn::Ident =
         syn::parse_str(&format!("SOCK_{}_DAT_{}", dir, upname)).unwrap();
+    // Only the data buffers are placed in `section`: it's not initialized,
+    // which is fine for bytes but not for packet metadata.
+    let link_section = section.map(|s| {
+        let s = format!(".{}", s);
+        quote::quote! { #[link_section = #s] }
+    });
+    // UDP sockets also need packet metadata; TCP sockets are just bytes.
+    let hdrs = headers.then(|| {
+        quote::quote! {
+            static mut #hdrname: [[UdpPacketMetadata; #pktcnt]; #vlan_count] = [
+                [UdpPacketMetadata::EMPTY; #pktcnt]; #vlan_count
+            ];
+        }
+    });
     quote::quote! {
-        static mut #hdrname: [[UdpPacketMetadata; #pktcnt]; #vlan_count] = [
-            [UdpPacketMetadata::EMPTY; #pktcnt]; #vlan_count
-        ];
+        #hdrs
+        #link_section
         static mut #bufname: [[u8; #bytecnt]; #vlan_count] = [[0u8; #bytecnt]; #vlan_count];
     }
 }
 
 fn generate_state_struct(config: &NetConfig) -> TokenStream {
     let n = config.sockets.len();
+    let tcp = build_util::has_feature("tcp")
+        .then(|| quote::quote! { Tcp(TcpSocket<'a>), });
     quote::quote! {
-        pub(crate) struct Sockets<'a, const N: usize>(pub [[UdpSocket<'a>; #n]; N]);
+        pub(crate) enum NetSocket<'a> {
+            Udp(UdpSocket<'a>),
+            #tcp
+        }
+        pub(crate) struct Sockets<'a, const N: usize>(pub [[Option<NetSocket<'a>>; #n]; N]);
     }
 }
 
 fn generate_constructor(
     config: &NetConfig,
 ) -> Result<TokenStream, Box<dyn std::error::Error>> {
-    let name_to_sockets = |name: &String, i: usize| {
+    let name_to_sockets = |name: &String, socket: &SocketConfig, i: usize| {
         let upname = name.to_ascii_uppercase();
         let rxhdrs: syn::Ident =
             syn::parse_str(&format!("SOCK_RX_HDR_{}", upname)).unwrap();
@@ -172,8 +424,17 @@ fn generate_constructor(
         let txbytes: syn::Ident =
             syn::parse_str(&format!("SOCK_TX_DAT_{}", upname)).unwrap();
 
+        if socket.kind == "tcp" {
+            return quote::quote! {
+                Some(NetSocket::Tcp(TcpSocket::new(
+                    TcpSocketBuffer::new(unsafe { &mut #rxbytes[#i][..] }),
+                    TcpSocketBuffer::new(unsafe { &mut #txbytes[#i][..] }),
+                )))
+            };
+        }
+
         quote::quote! {
-            UdpSocket::new(
+            Some(NetSocket::Udp(UdpSocket::new(
                 UdpSocketBuffer::new(
                     unsafe { &mut #rxhdrs[#i][..] },
                     unsafe { &mut #rxbytes[#i][..] },
@@ -182,7 +443,7 @@ fn generate_constructor(
                     unsafe { &mut #txhdrs[#i][..] },
                     unsafe { &mut #txbytes[#i][..] },
                 ),
-            )
+            )))
         }
     };
     let vlan_count = config.vlan.map(|v| v.count).unwrap_or(1);
@@ -190,8 +451,20 @@ fn generate_constructor(
         .map(|i| {
             let s = config
                 .sockets
-                .keys()
-                .map(|n| name_to_sockets(n, i))
+                .iter()
+                .map(|(n, s)| {
+                    // Sockets which aren't bound on this VLAN are `None`, and
+                    // the rest are indexed by their position in the socket's
+                    // list of VLANs.
+                    match s
+                        .vlan_indices(config.vlan)
+                        .iter()
+                        .position(|&v| v == i)
+                    {
+                        Some(j) => name_to_sockets(n, s, j),
+                        None => quote::quote! { None },
+                    }
+                })
                 .collect::<Vec<_>>();
             quote::quote! {
                 [
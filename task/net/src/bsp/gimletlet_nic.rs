@@ -5,10 +5,10 @@
 #[cfg(not(feature = "ksz8463"))]
 compile_error!("this BSP requires the ksz8463 feature");
 
-use crate::{bsp_support, pins};
+use crate::bsp_support;
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
-use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use drv_stm32xx_sys_api::{Port, Sys};
 use ksz8463::{
     Error as RawKszError, Ksz8463, MIBCounter, MIBCounterValue,
     Register as KszRegister,
@@ -42,20 +42,6 @@ impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
     const WAKE_INTERVAL: Option<u64> = Some(5000);
 
-    fn configure_ethernet_pins(sys: &Sys) {
-        pins::RmiiPins {
-            refclk: Port::A.pin(1),
-            crs_dv: Port::A.pin(7),
-            tx_en: Port::B.pin(11),
-            txd0: Port::B.pin(12),
-            txd1: Port::B.pin(13),
-            rxd0: Port::C.pin(4),
-            rxd1: Port::C.pin(5),
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-    }
-
     fn new(_eth: &eth::Ethernet, sys: &Sys) -> Self {
         let ksz8463 = loop {
             // SPI device is based on ordering in app.toml
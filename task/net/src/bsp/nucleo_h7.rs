@@ -3,9 +3,8 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::miim_bridge::MiimBridge;
-use crate::pins;
 use drv_stm32h7_eth as eth;
-use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use drv_stm32xx_sys_api::Sys;
 use task_net_api::PhyError;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 use vsc85xx::PhyRw;
@@ -19,27 +18,6 @@ const PHYADDR: u8 = 0x0;
 pub struct BspImpl;
 
 impl crate::bsp_support::Bsp for BspImpl {
-    fn configure_ethernet_pins(sys: &Sys) {
-        pins::RmiiPins {
-            refclk: Port::A.pin(1),
-            crs_dv: Port::A.pin(7),
-            tx_en: Port::G.pin(11),
-            txd0: Port::G.pin(13),
-            txd1: Port::B.pin(13),
-            rxd0: Port::C.pin(4),
-            rxd1: Port::C.pin(5),
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-
-        pins::MdioPins {
-            mdio: Port::A.pin(2),
-            mdc: Port::C.pin(1),
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-    }
-
     fn new(eth: &eth::Ethernet, _sys: &Sys) -> Self {
         // Unlike most Microchip PHYs, the LAN8742A-CZ-TR does not use register
         // 31 to switch between register pages (since it only uses page 0).
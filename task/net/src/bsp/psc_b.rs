@@ -5,10 +5,10 @@
 #[cfg(not(all(feature = "ksz8463", feature = "mgmt")))]
 compile_error!("this BSP requires the ksz8463 and mgmt features");
 
-use crate::{bsp_support, mgmt, pins};
+use crate::{bsp_support, mgmt};
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
-use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use drv_stm32xx_sys_api::{Port, Sys};
 use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
@@ -25,29 +25,6 @@ impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
     const WAKE_INTERVAL: Option<u64> = Some(500);
 
-    /// Stateless function to configure ethernet pins before the Bsp struct
-    /// is actually constructed
-    fn configure_ethernet_pins(sys: &Sys) {
-        pins::RmiiPins {
-            refclk: Port::A.pin(1), // CLK_50MHZ_SP_RMII_REFCLK
-            crs_dv: Port::A.pin(7), // RMII_MGMT_SW_TO_SP_CRS_DV
-            tx_en: Port::G.pin(11), // RMII_SP_TO_MGMT_SW_TX_EN
-            txd0: Port::G.pin(13),  // RMII_SP_TO_MGMT_SW_TXD0
-            txd1: Port::G.pin(12),  // RMII_SP_TO_MGMT_SW_TXD1
-            rxd0: Port::C.pin(4),   // RMII_MGMT_SW_TO_SP_RXD0
-            rxd1: Port::C.pin(5),   // RMII_MGMT_SW_TO_SP_RXD1
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-
-        pins::MdioPins {
-            mdio: Port::A.pin(2), // SP_TO_MGMT_PHY_MDIO_SP_DOMAIN
-            mdc: Port::C.pin(1),  // SP_TO_MGMT_PHY_MDC_SP_DOMAIN
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-    }
-
     fn new(eth: &eth::Ethernet, sys: &Sys) -> Self {
         let bsp = mgmt::Config {
             // SP_TO_MGMT_PHY_A2_PWR_EN
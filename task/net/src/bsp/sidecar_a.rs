@@ -5,11 +5,11 @@
 #[cfg(not(all(feature = "ksz8463", feature = "mgmt")))]
 compile_error!("this BSP requires the ksz8463 and mgmt features");
 
-use crate::{bsp_support, mgmt, miim_bridge::MiimBridge, pins};
+use crate::{bsp_support, mgmt, miim_bridge::MiimBridge};
 use drv_sidecar_seq_api::Sequencer;
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
-use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use drv_stm32xx_sys_api::{Port, Sys};
 use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
@@ -27,29 +27,6 @@ impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
     const WAKE_INTERVAL: Option<u64> = Some(500);
 
-    /// Stateless function to configure ethernet pins before the Bsp struct
-    /// is actually constructed
-    fn configure_ethernet_pins(sys: &Sys) {
-        pins::RmiiPins {
-            refclk: Port::A.pin(1),
-            crs_dv: Port::A.pin(7),
-            tx_en: Port::G.pin(11),
-            txd0: Port::G.pin(13),
-            txd1: Port::G.pin(12),
-            rxd0: Port::C.pin(4),
-            rxd1: Port::C.pin(5),
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-
-        pins::MdioPins {
-            mdio: Port::A.pin(2),
-            mdc: Port::C.pin(1),
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-    }
-
     fn preinit() {
         // Wait for the sequencer to turn on the clock
         let seq = Sequencer::from(SEQ.get_task_id());
@@ -10,11 +10,11 @@
 #[cfg(not(all(feature = "ksz8463", feature = "mgmt")))]
 compile_error!("this BSP requires the ksz8463 and mgmt features");
 
-use crate::{bsp_support, mgmt, miim_bridge::MiimBridge, pins};
+use crate::{bsp_support, mgmt, miim_bridge::MiimBridge};
 use drv_sidecar_seq_api::Sequencer;
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
-use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use drv_stm32xx_sys_api::{Port, Sys};
 use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
@@ -32,29 +32,6 @@ impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
     const WAKE_INTERVAL: Option<u64> = Some(500);
 
-    /// Stateless function to configure ethernet pins before the Bsp struct
-    /// is actually constructed
-    fn configure_ethernet_pins(sys: &Sys) {
-        pins::RmiiPins {
-            refclk: Port::A.pin(1), // CLK_50M_SP_RMII_REFCLK
-            crs_dv: Port::A.pin(7), // RMII_SP_TO_EPE_RX_DV
-            tx_en: Port::G.pin(11), // RMII_SP_TO_EPE_TX_EN
-            txd0: Port::G.pin(13),  // RMII_SP_TO_EPE_TXD0
-            txd1: Port::G.pin(12),  // RMII_SP_TO_EPE_TXD1
-            rxd0: Port::C.pin(4),   // RMII_SP_TO_EPE_RDX0 (typo in schematic)
-            rxd1: Port::C.pin(5),   // RMII_SP_TO_EPE_RXD1
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-
-        pins::MdioPins {
-            mdio: Port::A.pin(2), // MIIM_SP_TO_PHY_MDIO_3V3
-            mdc: Port::C.pin(1),  // MIIM_SP_TO_PHY_MDC_3V3
-            af: Alternate::AF11,
-        }
-        .configure(sys);
-    }
-
     fn preinit() {
         // Wait for the sequencer to turn on the clock
         let seq = Sequencer::from(SEQ.get_task_id());
@@ -16,7 +16,7 @@
 
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::Sys;
-use task_net_api::PhyError;
+use task_net_api::{LinkState, PhyError, LINK_COUNT};
 use vsc7448_pac::types::PhyRegisterAddress;
 
 #[cfg(feature = "mgmt")]
@@ -37,9 +37,6 @@ pub trait Bsp: Sized {
     /// Opportunity to do any work before the Ethernet peripheral is turned on.
     /// By default this does nothing, override it if necessary.
     fn preinit() {}
-    /// Stateless function to configure ethernet pins before the Bsp struct
-    /// is actually constructed
-    fn configure_ethernet_pins(sys: &Sys);
 
     fn new(eth: &eth::Ethernet, sys: &Sys) -> Self;
 
@@ -82,4 +79,28 @@ pub trait Bsp: Sized {
         &self,
         eth: &crate::eth::Ethernet,
     ) -> Result<task_net_api::ManagementCounters, MgmtError>;
+
+    /// Reads the state of each Ethernet link. This is called periodically
+    /// from `wake` (if any task has subscribed to link events), so that
+    /// tasks can be notified when a link changes state; `None` means that
+    /// the states couldn't be read (or that this board's links aren't
+    /// monitored), and leaves the previous states in place.
+    ///
+    /// With the `mgmt` feature, the default derives each link's state from
+    /// `management_link_status`; otherwise, it returns `None`.
+    #[cfg(feature = "mgmt")]
+    fn link_states(
+        &self,
+        eth: &eth::Ethernet,
+    ) -> Option<[LinkState; LINK_COUNT]> {
+        crate::mgmt::link_states(self.management_link_status(eth))
+    }
+
+    #[cfg(not(feature = "mgmt"))]
+    fn link_states(
+        &self,
+        _eth: &eth::Ethernet,
+    ) -> Option<[LinkState; LINK_COUNT]> {
+        None
+    }
 }
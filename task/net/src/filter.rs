@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-socket source filtering and rate limiting.
+//!
+//! Sockets may be configured with a list of source prefixes to accept packets
+//! from, and with token buckets limiting the rate of incoming packets: one
+//! for the socket as a whole, and one for each source address. Each
+//! interface's device checks incoming UDP packets against these before
+//! passing them to smoltcp, so that a host flooding one socket can't fill
+//! its rx queue (or, with per-source limits, starve other hosts).
+//!
+//! Per-source buckets are kept in a small table for each socket; when it's
+//! full, a new source replaces the one that was seen least recently. This
+//! means that a flood from many (possibly spoofed) sources can get past the
+//! per-source limits, which is what the socket-wide limit is for.
+//!
+//! Each VLAN has its own device, and so its own buckets: a socket which is
+//! bound on several VLANs may receive up to its limit on each of them.
+//!
+//! The buckets themselves are in the `packet-filter` crate, where they're
+//! tested on the host.
+
+use core::cell::Cell;
+use packet_filter::{Bucket, SourceTable};
+use smoltcp::wire::Ipv6Address;
+
+use crate::generated::{self, SOCKET_COUNT};
+
+pub use packet_filter::{Prefix, TokenBucketConfig};
+
+/// Number of per-source buckets for each socket, if any socket has
+/// per-source limits
+const SOURCES: usize = if generated::PER_SOURCE_LIMITS { 8 } else { 0 };
+
+/// Filtering configuration for one socket, generated from `app.toml`
+pub struct FilterConfig {
+    pub socket: Option<TokenBucketConfig>,
+    pub per_source: Option<TokenBucketConfig>,
+    /// Source prefixes to accept packets from; if empty, any source is
+    /// accepted.
+    pub allow: &'static [Prefix],
+}
+
+/// Filtering state for every socket on one interface, which is kept by its
+/// device so that incoming frames can be checked before they're passed to
+/// smoltcp.
+pub struct SocketFilters {
+    buckets: [Cell<Option<Bucket>>; SOCKET_COUNT],
+    sources: [SourceTable<SOURCES>; SOCKET_COUNT],
+    /// Packets dropped because their source wasn't allowed
+    pub filtered: [Cell<u32>; SOCKET_COUNT],
+    /// Packets dropped by a rate limit
+    pub rate_limited: [Cell<u32>; SOCKET_COUNT],
+}
+
+impl Default for SocketFilters {
+    fn default() -> Self {
+        const NO_BUCKET: Cell<Option<Bucket>> = Cell::new(None);
+        const ZERO: Cell<u32> = Cell::new(0);
+        Self {
+            buckets: [NO_BUCKET; SOCKET_COUNT],
+            sources: core::array::from_fn(|_| SourceTable::default()),
+            filtered: [ZERO; SOCKET_COUNT],
+            rate_limited: [ZERO; SOCKET_COUNT],
+        }
+    }
+}
+
+impl SocketFilters {
+    /// Checks whether an incoming UDP packet from `src` for socket `i` (as
+    /// found by `server::udp_destination`) should be passed to smoltcp, at
+    /// time `now` (in milliseconds).
+    pub fn accepts(&self, i: usize, src: Ipv6Address, now: u64) -> bool {
+        let config = &generated::SOCKET_FILTERS[i];
+
+        if !config.allow.is_empty()
+            && !config.allow.iter().any(|p| p.contains(src.0))
+        {
+            bump(&self.filtered[i]);
+            return false;
+        }
+
+        // The per-source limit is checked first, so that packets from a
+        // source which is over its limit don't use up the socket's tokens.
+        let ok = config
+            .per_source
+            .map_or(true, |c| self.sources[i].take(src.0, c, now))
+            && config.socket.map_or(true, |c| {
+                let cell = &self.buckets[i];
+                let mut b = cell.get().unwrap_or(Bucket::full(c, now));
+                let ok = b.take(c, now);
+                cell.set(Some(b));
+                ok
+            });
+        if !ok {
+            bump(&self.rate_limited[i]);
+        }
+        ok
+    }
+}
+
+fn bump(c: &Cell<u32>) {
+    c.set(c.get().wrapping_add(1));
+}
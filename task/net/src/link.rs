@@ -0,0 +1,81 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Ethernet link state monitoring.
+//!
+//! The netstack asks the BSP for the state of each link, and records any
+//! change as a `LinkEvent` (notifying the tasks listed in `link-notify`). If
+//! any tasks are listed, it polls every `LINK_POLL_INTERVAL`; otherwise, it
+//! only looks when `get_link_events` is called. The most recent events are
+//! kept, so that tasks can find out what happened while they weren't
+//! looking; each one has a sequence number, so a task which falls behind can
+//! tell that it's missed some.
+
+use task_net_api::{LinkEvent, LinkEvents, LinkState, LINK_COUNT};
+
+/// Number of events kept in the history
+const HISTORY: usize = 16;
+
+pub struct LinkMonitor {
+    states: [LinkState; LINK_COUNT],
+    /// Recent events, with event `seq` at index `seq % HISTORY`
+    history: [LinkEvent; HISTORY],
+    /// Sequence number of the next event
+    next_seq: u32,
+}
+
+impl Default for LinkMonitor {
+    fn default() -> Self {
+        Self {
+            states: [LinkState::Unknown; LINK_COUNT],
+            history: [LinkEvent::default(); HISTORY],
+            next_seq: 1,
+        }
+    }
+}
+
+impl LinkMonitor {
+    /// Records the state of each link at time `now`, returning `true` if any
+    /// of them changed.
+    pub fn update(
+        &mut self,
+        states: [LinkState; LINK_COUNT],
+        now: u64,
+    ) -> bool {
+        let mut changed = false;
+        for (link, new) in states.into_iter().enumerate() {
+            if self.states[link] == new {
+                continue;
+            }
+            self.states[link] = new;
+            let seq = self.next_seq;
+            self.history[seq as usize % HISTORY] = LinkEvent {
+                seq,
+                time: now,
+                link: link as u8,
+                state: new,
+            };
+            self.next_seq += 1;
+            changed = true;
+        }
+        changed
+    }
+
+    /// Returns the current state of each link, and as many events with
+    /// sequence numbers greater than `after` as we still have and will fit.
+    pub fn events_after(&self, after: u32) -> LinkEvents {
+        let oldest = self.next_seq.saturating_sub(HISTORY as u32).max(1);
+        let first = after.saturating_add(1).max(oldest);
+
+        let mut out = LinkEvents {
+            links: self.states,
+            ..Default::default()
+        };
+        for (slot, seq) in out.events.iter_mut().zip(first..self.next_seq) {
+            *slot = self.history[seq as usize % HISTORY];
+            out.count += 1;
+        }
+        out
+    }
+}
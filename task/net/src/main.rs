@@ -5,10 +5,10 @@
 #![no_std]
 #![no_main]
 
-pub mod pins;
-
 mod bsp_support;
 mod buf;
+mod filter;
+mod link;
 mod miim_bridge;
 mod server;
 
@@ -37,11 +37,23 @@ mod server_impl;
 #[cfg(feature = "mgmt")]
 pub(crate) mod mgmt;
 
+#[cfg(feature = "slaac")]
+mod slaac;
+
+#[cfg(feature = "pcap")]
+mod pcap;
+
+#[cfg(feature = "multicast")]
+mod multicast;
+
 mod idl {
     use task_net_api::{
-        KszError, KszMacTableEntry, LargePayloadBehavior, MacAddress,
-        MacAddressBlock, ManagementCounters, ManagementLinkStatus, MgmtError,
-        PhyError, RecvError, SendError, SocketName, UdpMetadata,
+        AddressError, CaptureError, CaptureFilter, CaptureRead, Ipv6Address,
+        Ipv6Addresses, KszError, KszMacTableEntry, LargePayloadBehavior,
+        LinkEvents, MacAddress, MacAddressBlock, ManagementCounters,
+        ManagementLinkStatus, MgmtError, MulticastError, NetStats, PhyError,
+        RecvError, SendError, SocketName, StatsError, TcpError, TcpMetadata,
+        UdpMetadata,
     };
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -49,7 +61,7 @@ mod idl {
 use core::sync::atomic::{AtomicU32, Ordering};
 use enum_map::Enum;
 use multitimer::{Multitimer, Repeat};
-use task_net_api::MacAddressBlock;
+use task_net_api::{Ipv6StaticAddress, MacAddressBlock};
 use zerocopy::{AsBytes, U16};
 
 #[cfg(feature = "h743")]
@@ -66,9 +78,11 @@ use crate::bsp_support::Bsp;
 
 task_slot!(SYS, sys);
 
-#[cfg(feature = "vpd-mac")]
+#[cfg(any(feature = "vpd-mac", feature = "vpd-ipv6"))]
 task_slot!(I2C, i2c_driver);
 
+include!(concat!(env!("OUT_DIR"), "/pin_config.rs"));
+
 /////////////////////////////////////////////////////////////////////////////
 // Configuration things!
 //
@@ -118,6 +132,28 @@ fn mac_address_from_vpd() -> Option<MacAddressBlock> {
     drv_local_vpd::read_config(i2c_task, *b"MAC0").ok()
 }
 
+/// Reads static IPv6 addresses from VPD, which hold one record per interface
+/// (in VLAN order).  Interfaces whose record is missing or invalid use the
+/// corresponding entry in `config` instead.
+#[cfg(feature = "vpd-ipv6")]
+fn ipv6_static_from_vpd<const N: usize>(
+    config: [Ipv6StaticAddress; N],
+) -> [Ipv6StaticAddress; N] {
+    let i2c_task = I2C.get_task_id();
+    let Ok(mut vpd) = drv_local_vpd::read_config::<[Ipv6StaticAddress; N]>(
+        i2c_task,
+        *b"IPV6",
+    ) else {
+        return config;
+    };
+    for (v, c) in vpd.iter_mut().zip(config) {
+        if !v.is_valid() {
+            *v = c;
+        }
+    }
+    vpd
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 const TX_RING_SZ: usize = 4;
@@ -172,8 +208,8 @@ fn main() -> ! {
     sys.enter_reset(drv_stm32xx_sys_api::Peripheral::Tim16);
     sys.leave_reset(drv_stm32xx_sys_api::Peripheral::Tim16);
 
-    // Do preliminary pin configuration
-    BspImpl::configure_ethernet_pins(&sys);
+    // Configure the RMII and MDIO pins, which are declared in `app.toml`
+    setup_pins(&sys).unwrap();
 
     // Set up our ring buffers.
     let (tx_storage, tx_buffers) = buf::claim_tx_statics();
@@ -200,10 +236,17 @@ fn main() -> ! {
     #[cfg(not(feature = "vpd-mac"))]
     let mac_address = mac_address_from_uid();
 
+    // Static IPv6 addresses in VPD take precedence over those in our config.
+    #[cfg(feature = "vpd-ipv6")]
+    let ipv6_static = ipv6_static_from_vpd(generated::IPV6_STATIC);
+
+    #[cfg(not(feature = "vpd-ipv6"))]
+    let ipv6_static = generated::IPV6_STATIC;
+
     // Board-dependant initialization (e.g. bringing up the PHYs)
     let bsp = BspImpl::new(&eth, &sys);
 
-    let mut server = server_impl::new(&eth, mac_address, bsp);
+    let mut server = server_impl::new(&eth, mac_address, ipv6_static, bsp);
 
     // Turn on our IRQ.
     userlib::sys_irq_control(ETH_IRQ, true);
@@ -11,7 +11,8 @@ use ksz8463::{
 };
 use ringbuf::*;
 use task_net_api::{
-    ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
+    LinkSpeed, LinkState, ManagementCounters, ManagementLinkStatus, MgmtError,
+    PhyError, LINK_COUNT,
 };
 use userlib::hl::sleep_for;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
@@ -345,3 +346,27 @@ impl Bsp {
         Ok(out)
     }
 }
+
+/// Converts the management network's status into the state of each of its
+/// two links. A link is up if every layer of its port is up: the KSZ8463's
+/// 100BASE-FX side, and the VSC85x2's 100BASE-FX and SGMII sides. Since the
+/// media is 100BASE-FX, the speed never changes.
+///
+/// If the status couldn't be read, this returns `None`, so that a transient
+/// error on the management bus leaves the previous states in place rather
+/// than looking like every link went away.
+pub fn link_states(
+    status: Result<ManagementLinkStatus, MgmtError>,
+) -> Option<[LinkState; LINK_COUNT]> {
+    let s = status.ok()?;
+    Some(core::array::from_fn(|i| {
+        if s.ksz8463_100base_fx_link_up[i]
+            && s.vsc85x2_100base_fx_link_up[i]
+            && s.vsc85x2_sgmii_link_up[i]
+        {
+            LinkState::Up(LinkSpeed::Mbps100)
+        } else {
+            LinkState::Down
+        }
+    }))
+}
@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! IPv6 multicast group membership, with MLDv2 (RFC 3810).
+//!
+//! smoltcp delivers multicast packets to any UDP socket bound to their
+//! destination port, and doesn't implement MLD for IPv6. Instead, each
+//! interface's device keeps a table of which sockets have joined which
+//! groups, and drops incoming packets for groups that the destination socket
+//! hasn't joined. (The all-nodes and solicited-node groups are always let
+//! through, since neighbor discovery depends on them.)
+//!
+//! We send MLDv2 reports through a raw socket on each interface: one when an
+//! interface joins or leaves a group, and one in response to each query from
+//! a router. Reports aren't repeated, so a lost report is only corrected by
+//! the router's next query.
+
+use core::cell::Cell;
+use smoltcp::socket::{RawPacketMetadata, RawSocket, RawSocketBuffer};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, Icmpv6Packet, IpProtocol, IpVersion,
+    Ipv6Address, Ipv6Packet, Ipv6Repr, UdpPacket,
+};
+
+use crate::generated;
+
+/// Number of (group, socket) memberships on each interface
+pub const MEMBERSHIPS: usize = 8;
+
+/// Number of packets buffered in each direction between polls
+const MLD_PACKETS: usize = 2;
+
+/// Bytes of packets buffered in each direction between polls; this is enough
+/// for a query, or a report listing every group.
+const MLD_BYTES: usize = 256;
+
+/// Address to which MLDv2 reports are sent
+const ALL_MLDV2_ROUTERS: Ipv6Address =
+    Ipv6Address([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x16]);
+
+/// Hop-by-hop options header carrying a Router Alert option for MLD (RFC
+/// 2711), padded to 8 bytes, followed by ICMPv6
+const HOP_BY_HOP_ROUTER_ALERT: [u8; 8] = [58, 0, 5, 2, 0, 0, 1, 0];
+
+/// ICMPv6 message types
+const MLD_QUERY: u8 = 130;
+const MLDV2_REPORT: u8 = 143;
+
+/// Multicast address record types, from RFC 3810 section 5.2.12
+#[derive(Copy, Clone)]
+pub enum RecordType {
+    /// Current state, in response to a query
+    IsExclude = 2,
+    /// The interface has left the group
+    ToInclude = 3,
+    /// The interface has joined the group
+    ToExclude = 4,
+}
+
+/// Buffers for the raw socket used to send and receive MLD messages
+pub struct MldStorage {
+    rx_meta: [RawPacketMetadata; MLD_PACKETS],
+    rx: [u8; MLD_BYTES],
+    tx_meta: [RawPacketMetadata; MLD_PACKETS],
+    tx: [u8; MLD_BYTES],
+}
+
+impl Default for MldStorage {
+    fn default() -> Self {
+        Self {
+            rx_meta: [RawPacketMetadata::EMPTY; MLD_PACKETS],
+            rx: [0; MLD_BYTES],
+            tx_meta: [RawPacketMetadata::EMPTY; MLD_PACKETS],
+            tx: [0; MLD_BYTES],
+        }
+    }
+}
+
+/// Builds the raw socket used for MLD. MLD messages always start with a
+/// hop-by-hop options header, so that's the protocol it's bound to.
+pub(crate) fn socket(storage: &'static mut MldStorage) -> RawSocket<'static> {
+    RawSocket::new(
+        IpVersion::Ipv6,
+        IpProtocol::HopByHop,
+        RawSocketBuffer::new(&mut storage.rx_meta[..], &mut storage.rx[..]),
+        RawSocketBuffer::new(&mut storage.tx_meta[..], &mut storage.tx[..]),
+    )
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Membership {
+    pub group: Ipv6Address,
+    /// Index of the socket which joined the group
+    pub socket: usize,
+}
+
+/// Table of memberships on one interface, which is kept by its device so
+/// that incoming frames can be checked before they're passed to smoltcp.
+pub struct Memberships {
+    slots: [Cell<Option<Membership>>; MEMBERSHIPS],
+}
+
+impl Default for Memberships {
+    fn default() -> Self {
+        const NONE: Cell<Option<Membership>> = Cell::new(None);
+        Self {
+            slots: [NONE; MEMBERSHIPS],
+        }
+    }
+}
+
+impl Memberships {
+    fn iter(&self) -> impl Iterator<Item = Membership> + '_ {
+        self.slots.iter().filter_map(Cell::get)
+    }
+
+    pub fn contains(&self, m: Membership) -> bool {
+        self.iter().any(|n| n == m)
+    }
+
+    /// Checks whether any socket has joined `group`
+    pub fn has_group(&self, group: Ipv6Address) -> bool {
+        self.iter().any(|m| m.group == group)
+    }
+
+    /// Returns each group that's been joined, once.
+    pub fn groups(&self) -> impl Iterator<Item = Ipv6Address> + '_ {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let m = slot.get()?;
+            let seen = self.slots[..i]
+                .iter()
+                .any(|s| s.get().map_or(false, |n| n.group == m.group));
+            (!seen).then_some(m.group)
+        })
+    }
+
+    /// Adds `m`, which must not already be present. Returns `Err` if the
+    /// table is full.
+    pub fn insert(&self, m: Membership) -> Result<(), ()> {
+        let slot = self.slots.iter().find(|s| s.get().is_none()).ok_or(())?;
+        slot.set(Some(m));
+        Ok(())
+    }
+
+    /// Removes `m`, returning `true` if it was present.
+    pub fn remove(&self, m: Membership) -> bool {
+        match self.slots.iter().find(|s| s.get() == Some(m)) {
+            Some(slot) => {
+                slot.set(None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks whether an incoming frame should be passed to smoltcp. Frames
+    /// for multicast groups are only accepted if they're UDP packets for a
+    /// socket that's joined the group, or (for queries about the group)
+    /// anything else sent to a group that's been joined.
+    pub fn accepts(&self, frame: &[u8]) -> bool {
+        let Ok(eth) = EthernetFrame::new_checked(frame) else { return true };
+        if eth.ethertype() != EthernetProtocol::Ipv6 {
+            return true;
+        }
+        let Ok(ip) = Ipv6Packet::new_checked(eth.payload()) else {
+            return true;
+        };
+        let dst = ip.dst_addr();
+        if !dst.is_multicast()
+            || dst == Ipv6Address::LINK_LOCAL_ALL_NODES
+            || is_solicited_node(dst)
+        {
+            return true;
+        }
+
+        match ip.next_header() {
+            IpProtocol::Udp => match UdpPacket::new_checked(ip.payload()) {
+                Ok(udp) => self.iter().any(|m| {
+                    m.group == dst
+                        && generated::SOCKET_PORTS[m.socket] == udp.dst_port()
+                }),
+                Err(_) => false,
+            },
+            _ => self.has_group(dst),
+        }
+    }
+}
+
+/// Checks whether `group` is one that sockets may join: a multicast address
+/// with at least link-local scope, other than all-nodes (which every
+/// interface is always a member of).
+pub fn is_joinable(group: Ipv6Address) -> bool {
+    group.is_multicast()
+        && (group.0[1] & 0xf) >= 2
+        && group != Ipv6Address::LINK_LOCAL_ALL_NODES
+}
+
+fn is_solicited_node(addr: Ipv6Address) -> bool {
+    addr.0[..13] == [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff]
+}
+
+/// Returns the Ethernet address for frames sent to `group` (RFC 2464
+/// section 7).
+pub fn group_mac(group: Ipv6Address) -> [u8; 6] {
+    let g = group.0;
+    [0x33, 0x33, g[12], g[13], g[14], g[15]]
+}
+
+/// Returns the Ethernet address for the solicited-node group of `addr`, which
+/// neighbor solicitations for `addr` are sent to.
+pub fn solicited_node_mac(addr: Ipv6Address) -> [u8; 6] {
+    let a = addr.0;
+    [0x33, 0x33, 0xff, a[13], a[14], a[15]]
+}
+
+/// A query from a router, asking which groups we've joined
+pub(crate) enum Query {
+    General,
+    Group(Ipv6Address),
+}
+
+/// Checks that `packet` (a raw IPv6 packet starting with a hop-by-hop
+/// options header) is a valid MLD query, and returns what it asks about.
+pub(crate) fn parse_query(packet: &[u8]) -> Option<Query> {
+    let ip = Ipv6Packet::new_checked(packet).ok()?;
+
+    // RFC 3810 section 5.1.14: queries must come from a link-local address,
+    // and must not have been forwarded.
+    let src = ip.src_addr();
+    if ip.hop_limit() != 1 || !src.is_link_local() {
+        return None;
+    }
+
+    let options = ip.payload();
+    if *options.first()? != u8::from(IpProtocol::Icmpv6) {
+        return None;
+    }
+    let options_len = (usize::from(*options.get(1)?) + 1) * 8;
+    let icmp = Icmpv6Packet::new_checked(options.get(options_len..)?).ok()?;
+    if !icmp.verify_checksum(&src.into(), &ip.dst_addr().into()) {
+        return None;
+    }
+
+    // The group address follows the type, code, checksum, maximum response
+    // code, and a reserved field; this layout is shared by MLDv1 and MLDv2.
+    let body = icmp.into_inner();
+    if body.len() < 24 || body[0] != MLD_QUERY {
+        return None;
+    }
+    let group = Ipv6Address::from_bytes(&body[8..24]);
+    Some(if group.is_unspecified() {
+        Query::General
+    } else {
+        Query::Group(group)
+    })
+}
+
+/// Queues an MLDv2 report, with one record of type `record` for each of
+/// `groups`, sent from the link-local address `src`. If the socket's queue is
+/// full, the report is dropped.
+pub(crate) fn send_report(
+    socket: &mut RawSocket<'_>,
+    src: Ipv6Address,
+    record: RecordType,
+    groups: &[Ipv6Address],
+) {
+    const RECORD_LEN: usize = 20;
+    let icmp_len = 8 + RECORD_LEN * groups.len();
+    let repr = Ipv6Repr {
+        src_addr: src,
+        dst_addr: ALL_MLDV2_ROUTERS,
+        next_header: IpProtocol::HopByHop,
+        payload_len: HOP_BY_HOP_ROUTER_ALERT.len() + icmp_len,
+        hop_limit: 1,
+    };
+    let Ok(buf) = socket.send(repr.buffer_len() + repr.payload_len) else {
+        return;
+    };
+    let (header, payload) = buf.split_at_mut(repr.buffer_len());
+    repr.emit(&mut Ipv6Packet::new_unchecked(header));
+    let (options, icmp) = payload.split_at_mut(HOP_BY_HOP_ROUTER_ALERT.len());
+    options.copy_from_slice(&HOP_BY_HOP_ROUTER_ALERT);
+
+    // Type, code, checksum, reserved, and number of records
+    icmp[..8].copy_from_slice(&[MLDV2_REPORT, 0, 0, 0, 0, 0, 0, 0]);
+    icmp[6..8].copy_from_slice(&(groups.len() as u16).to_be_bytes());
+    for (r, group) in icmp[8..].chunks_exact_mut(RECORD_LEN).zip(groups) {
+        // Type, auxiliary data length, and number of sources (always zero:
+        // joining a group excludes no sources, and leaving includes none)
+        r[..4].copy_from_slice(&[record as u8, 0, 0, 0]);
+        r[4..].copy_from_slice(group.as_bytes());
+    }
+    Icmpv6Packet::new_unchecked(icmp)
+        .fill_checksum(&src.into(), &ALL_MLDV2_ROUTERS.into());
+}
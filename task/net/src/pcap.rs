@@ -0,0 +1,196 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Packet capture, for debugging without a mirrored switch port.
+//!
+//! Every interface's device hands the frames that it receives and sends to a
+//! single `Capture`, which keeps the ones that pass the current filter in a
+//! ring buffer. Frames are stored as little-endian pcapng Enhanced Packet
+//! Blocks, so host tooling can drain them with `capture_read` and write a
+//! pcapng file by adding a Section Header Block and one Interface Description
+//! Block per interface (in VLAN order, with the default microsecond timestamp
+//! resolution).
+//!
+//! When the buffer is full, new frames are dropped (and counted) rather than
+//! overwriting old ones, so that the buffer always holds whole blocks.
+
+use core::cell::{Cell, RefCell};
+use idol_runtime::{Leased, RequestError, W};
+use mutable_statics::mutable_statics;
+use task_net_api::{CaptureError, CaptureRead};
+use userlib::UnwrapLite;
+
+use crate::server::transport_ports;
+
+/// Size of the capture buffer in bytes, which must be a multiple of 4
+const CAPTURE_BYTES: usize = 16384;
+
+/// pcapng block type of an Enhanced Packet Block
+const EPB_TYPE: u32 = 6;
+
+/// pcapng option code for an Enhanced Packet Block's flags word
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// Size of an Enhanced Packet Block, aside from the frame itself: seven words
+/// of header, the flags option (two words), the end-of-options marker, and
+/// the trailing copy of the block length.
+const EPB_OVERHEAD: usize = 44;
+
+/// Direction of a captured frame, encoded as in the pcapng `epb_flags` option
+#[derive(Copy, Clone)]
+pub enum Direction {
+    Rx = 1,
+    Tx = 2,
+}
+
+/// A `CaptureFilter`, with its VID converted to an interface index
+#[derive(Copy, Clone)]
+pub struct Filter {
+    pub iface: Option<usize>,
+    pub port: Option<u16>,
+}
+
+pub struct Capture {
+    /// Current filter, or `None` if we're not capturing
+    filter: Cell<Option<Filter>>,
+    /// Frames dropped since the last `read`
+    dropped: Cell<u32>,
+    ring: RefCell<Ring>,
+}
+
+/// Grabs references to the capture buffer. Can only be called once!
+pub fn claim_capture_statics() -> &'static Capture {
+    let (buf, capture) = mutable_statics! {
+        static mut BUFFER: [u8; CAPTURE_BYTES] = [|| 0; _];
+        static mut CAPTURE: [Option<Capture>; 1] = [|| None; _];
+    };
+    capture[0].insert(Capture {
+        filter: Cell::new(None),
+        dropped: Cell::new(0),
+        ring: RefCell::new(Ring {
+            buf,
+            start: 0,
+            len: 0,
+        }),
+    })
+}
+
+impl Capture {
+    /// Starts capturing frames which pass `filter`, discarding anything
+    /// that's already been captured.
+    pub fn start(&self, filter: Filter) {
+        let mut ring = self.ring.borrow_mut();
+        ring.start = 0;
+        ring.len = 0;
+        self.dropped.set(0);
+        self.filter.set(Some(filter));
+    }
+
+    /// Stops capturing frames, keeping what's already been captured.
+    pub fn stop(&self) {
+        self.filter.set(None);
+    }
+
+    /// Captures `frame`, which was received or sent on interface `iface`, if
+    /// it passes the filter.
+    pub fn record(&self, iface: usize, dir: Direction, frame: &[u8]) {
+        let Some(filter) = self.filter.get() else { return };
+        if filter.iface.map_or(false, |i| i != iface) {
+            return;
+        }
+        if let Some(port) = filter.port {
+            match transport_ports(frame) {
+                Some((_, _, src, dst)) if src == port || dst == port => (),
+                _ => return,
+            }
+        }
+
+        let padded = (frame.len() + 3) & !3;
+        let total = EPB_OVERHEAD + padded;
+        let mut ring = self.ring.borrow_mut();
+        if CAPTURE_BYTES - ring.len < total {
+            self.dropped.set(self.dropped.get().wrapping_add(1));
+            return;
+        }
+
+        let micros = userlib::sys_get_timer().now * 1000;
+        let total = total as u32;
+        for word in [
+            EPB_TYPE,
+            total,
+            iface as u32,
+            (micros >> 32) as u32,
+            micros as u32,
+            frame.len() as u32, // captured length
+            frame.len() as u32, // original length
+        ] {
+            ring.push(&word.to_le_bytes());
+        }
+        ring.push(frame);
+        ring.push(&[0; 3][..padded - frame.len()]);
+        ring.push(&OPT_EPB_FLAGS.to_le_bytes());
+        ring.push(&4u16.to_le_bytes());
+        ring.push(&(dir as u32).to_le_bytes());
+        ring.push(&[0; 4]); // opt_endofopt
+        ring.push(&total.to_le_bytes());
+    }
+
+    /// Moves as many whole blocks as will fit from the ring into `buf`.
+    pub fn read(
+        &self,
+        buf: Leased<W, [u8]>,
+    ) -> Result<CaptureRead, RequestError<CaptureError>> {
+        let mut ring = self.ring.borrow_mut();
+        let mut n = 0;
+        while n < ring.len {
+            let block = ring.peek_u32(n + 4) as usize;
+            if n + block > buf.len() {
+                break;
+            }
+            n += block;
+        }
+        if n == 0 && ring.len != 0 {
+            return Err(CaptureError::BufferTooSmall.into());
+        }
+
+        // The blocks may wrap around the end of the ring
+        let first = n.min(CAPTURE_BYTES - ring.start);
+        buf.write_range(0..first, &ring.buf[ring.start..ring.start + first])
+            .map_err(|_| RequestError::went_away())?;
+        buf.write_range(first..n, &ring.buf[..n - first])
+            .map_err(|_| RequestError::went_away())?;
+        ring.start = (ring.start + n) % CAPTURE_BYTES;
+        ring.len -= n;
+
+        Ok(CaptureRead {
+            len: n as u32,
+            dropped: self.dropped.replace(0),
+        })
+    }
+}
+
+/// Circular buffer of pcapng blocks. Since every block is a multiple of 4
+/// bytes long, so is every offset into the ring.
+struct Ring {
+    buf: &'static mut [u8; CAPTURE_BYTES],
+    start: usize,
+    len: usize,
+}
+
+impl Ring {
+    /// Appends `data`, which the caller has checked will fit.
+    fn push(&mut self, data: &[u8]) {
+        let end = (self.start + self.len) % CAPTURE_BYTES;
+        let n = data.len().min(CAPTURE_BYTES - end);
+        self.buf[end..end + n].copy_from_slice(&data[..n]);
+        self.buf[..data.len() - n].copy_from_slice(&data[n..]);
+        self.len += data.len();
+    }
+
+    /// Reads the little-endian word at `offset` from the start of the ring.
+    fn peek_u32(&self, offset: usize) -> u32 {
+        let i = (self.start + offset) % CAPTURE_BYTES;
+        u32::from_le_bytes(self.buf[i..i + 4].try_into().unwrap_lite())
+    }
+}
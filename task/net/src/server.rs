@@ -3,7 +3,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::bsp_support;
-use crate::generated::{self, SOCKET_COUNT};
+use crate::filter;
+use crate::generated::{self, NetSocket, SocketKind, SOCKET_COUNT};
+use crate::link;
 use crate::{
     idl, link_local_iface_addr, MacAddressBlock, ETH_IRQ, NEIGHBORS,
     WAKE_IRQ_BIT,
@@ -15,16 +17,42 @@ use crate::generated::VLAN_RANGE;
 use drv_stm32h7_eth as eth;
 use idol_runtime::{ClientError, RequestError};
 use task_net_api::{
-    KszError, KszMacTableEntry, LargePayloadBehavior, MacAddress,
-    ManagementCounters, ManagementLinkStatus, MgmtError, PhyError, RecvError,
-    SendError, SocketName, UdpMetadata,
+    AddressError, CaptureError, CaptureFilter, CaptureRead, InterfaceStats,
+    Ipv6Addresses, Ipv6Net, Ipv6StaticAddress, KszError, KszMacTableEntry,
+    LargePayloadBehavior, LinkEvents, MacAddress, MacStats, ManagementCounters,
+    ManagementLinkStatus, MgmtError, MulticastError, NetStats, PhyError,
+    RecvError, SendError, SocketName, SocketStats, StatsError, TcpError,
+    TcpMetadata, UdpMetadata,
 };
 
+use core::cell::Cell;
 use core::iter::zip;
 use heapless::Vec;
-use smoltcp::iface::{Interface, Neighbor, SocketHandle, SocketStorage};
+use smoltcp::iface::{
+    Interface, Neighbor, Route, Routes, SocketHandle, SocketStorage,
+};
 use smoltcp::socket::UdpSocket;
-use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv6Cidr};
+use smoltcp::wire::{
+    EthernetAddress, EthernetFrame, EthernetProtocol, IpAddress, IpCidr,
+    IpProtocol, Ipv6Address, Ipv6Cidr, Ipv6Packet, TcpPacket, UdpPacket,
+};
+
+#[cfg(feature = "multicast")]
+use crate::multicast::{self, RecordType};
+#[cfg(feature = "pcap")]
+use crate::pcap;
+#[cfg(feature = "slaac")]
+use crate::slaac;
+#[cfg(any(feature = "slaac", feature = "multicast"))]
+use smoltcp::socket::RawSocket;
+#[cfg(feature = "multicast")]
+use smoltcp::wire::HardwareAddress;
+
+#[cfg(feature = "tcp")]
+use smoltcp::{
+    socket::{TcpSocket, TcpState},
+    wire::IpEndpoint,
+};
 use userlib::{sys_post, sys_refresh_task_id, UnwrapLite};
 use zerocopy::byteorder::U16;
 
@@ -114,6 +142,86 @@ where
         Ok(MacAddress(out.0))
     }
 
+    fn get_ipv6_addresses(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        vid: u16,
+    ) -> Result<Ipv6Addresses, RequestError<AddressError>> {
+        #[cfg(feature = "vlan")]
+        let vlan_index = {
+            if !VLAN_RANGE.contains(&vid) {
+                return Err(AddressError::InvalidVLan.into());
+            }
+            usize::from(vid - VLAN_RANGE.start)
+        };
+        #[cfg(not(feature = "vlan"))]
+        let vlan_index = {
+            // There's only the one interface
+            let _ = vid;
+            0
+        };
+
+        let vlan = &self.vlan_state[vlan_index];
+        let net = |c: Ipv6Cidr| Ipv6Net {
+            addr: c.address().into(),
+            prefix_len: c.prefix_len(),
+        };
+        Ok(Ipv6Addresses {
+            link_local: vlan
+                .address(ADDR_LINK_LOCAL)
+                .unwrap_lite()
+                .address()
+                .into(),
+            static_addr: vlan.address(ADDR_STATIC).map(net),
+            slaac: vlan.address(ADDR_SLAAC).map(net),
+            router: vlan.router.map(Into::into),
+        })
+    }
+
+    fn get_stats(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        vid: u16,
+    ) -> Result<NetStats, RequestError<StatsError>> {
+        #[cfg(feature = "vlan")]
+        let vlan_index = {
+            if !VLAN_RANGE.contains(&vid) {
+                return Err(StatsError::InvalidVLan.into());
+            }
+            usize::from(vid - VLAN_RANGE.start)
+        };
+        #[cfg(not(feature = "vlan"))]
+        let vlan_index = {
+            // There's only the one interface
+            let _ = vid;
+            0
+        };
+
+        let vlan = &mut self.vlan_state[vlan_index];
+        for i in 0..SOCKET_COUNT {
+            vlan.settle_rx_dropped(i);
+        }
+        let dev = vlan.iface.device().stats();
+        let eth = self.eth.stats();
+        Ok(NetStats {
+            interface: InterfaceStats {
+                rx_frames: dev.rx_frames.get(),
+                rx_bytes: dev.rx_bytes.get(),
+                tx_frames: dev.tx_frames.get(),
+                tx_bytes: dev.tx_bytes.get(),
+            },
+            sockets: vlan.socket_stats,
+            mac: MacStats {
+                rx_packets: eth.rx.packets,
+                rx_errors: eth.rx.errors,
+                rx_vlan_dropped: eth.rx.vlan_dropped,
+                rx_missed: eth.rx_missed,
+                tx_packets: eth.tx.packets,
+                tx_ring_full: eth.tx.full,
+            },
+        })
+    }
+
     fn get_spare_mac_addresses(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -121,6 +229,250 @@ where
         Ok(self.spare_macs)
     }
 
+    ////////////////////////////////////////////////////////////////////////////
+    // TCP functions
+    #[cfg(feature = "tcp")]
+    fn tcp_listen(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_listen(msg, socket)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_accept(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<TcpMetadata, RequestError<TcpError>> {
+        self.net_tcp_accept(msg, socket)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_connect(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        remote: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_connect(msg, socket, remote)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_read(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+        payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        self.net_tcp_read(msg, socket, connection, payload)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_write(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+        payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        self.net_tcp_write(msg, socket, connection, payload)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_close(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_close(msg, socket, connection)
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Stubs for TCP functions when it's not enabled
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_listen(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_accept(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+    ) -> Result<TcpMetadata, RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_connect(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _remote: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_read(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _connection: TcpMetadata,
+        _payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_write(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _connection: TcpMetadata,
+        _payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_close(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _connection: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(TcpError::NotAvailable.into())
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Packet capture functions
+    #[cfg(feature = "pcap")]
+    fn capture_start(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        filter: CaptureFilter,
+    ) -> Result<(), RequestError<CaptureError>> {
+        #[cfg(feature = "vlan")]
+        let iface = match filter.vid {
+            Some(vid) if !VLAN_RANGE.contains(&vid) => {
+                return Err(CaptureError::InvalidVLan.into());
+            }
+            Some(vid) => Some(usize::from(vid - VLAN_RANGE.start)),
+            None => None,
+        };
+        #[cfg(not(feature = "vlan"))]
+        let iface = None;
+
+        self.capture().start(pcap::Filter {
+            iface,
+            port: filter.port,
+        });
+        Ok(())
+    }
+
+    #[cfg(feature = "pcap")]
+    fn capture_stop(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<CaptureError>> {
+        self.capture().stop();
+        Ok(())
+    }
+
+    #[cfg(feature = "pcap")]
+    fn capture_read(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        buf: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<CaptureRead, RequestError<CaptureError>> {
+        self.capture().read(buf)
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Stubs for packet capture functions when it's not enabled
+    #[cfg(not(feature = "pcap"))]
+    fn capture_start(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _filter: CaptureFilter,
+    ) -> Result<(), RequestError<CaptureError>> {
+        Err(CaptureError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "pcap"))]
+    fn capture_stop(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<CaptureError>> {
+        Err(CaptureError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "pcap"))]
+    fn capture_read(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _buf: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<CaptureRead, RequestError<CaptureError>> {
+        Err(CaptureError::NotAvailable.into())
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Multicast functions
+    #[cfg(feature = "multicast")]
+    fn join_multicast_group(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        vid: u16,
+        group: task_net_api::Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        self.net_join_multicast_group(msg, socket, vid, group.into())
+    }
+
+    #[cfg(feature = "multicast")]
+    fn leave_multicast_group(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        vid: u16,
+        group: task_net_api::Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        self.net_leave_multicast_group(msg, socket, vid, group.into())
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Stubs for multicast functions when it's not enabled
+    #[cfg(not(feature = "multicast"))]
+    fn join_multicast_group(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _vid: u16,
+        _group: task_net_api::Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        Err(MulticastError::NotAvailable.into())
+    }
+
+    #[cfg(not(feature = "multicast"))]
+    fn leave_multicast_group(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _vid: u16,
+        _group: task_net_api::Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        Err(MulticastError::NotAvailable.into())
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // Stubs for KSZ8463 functions when it's not present
     #[cfg(not(feature = "ksz8463"))]
@@ -204,6 +556,19 @@ where
         Ok(out)
     }
 
+    fn get_link_events(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        after: u32,
+    ) -> Result<LinkEvents, RequestError<core::convert::Infallible>> {
+        // Unless someone has subscribed to link events, `wake` doesn't poll
+        // the links, so catch up now.
+        if generated::LINK_SUBSCRIBERS.is_empty() {
+            self.poll_links(userlib::sys_get_timer().now);
+        }
+        Ok(self.links.events_after(after))
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // Management network functions, if it's not present
     #[cfg(not(feature = "mgmt"))]
@@ -252,6 +617,121 @@ pub trait DeviceExt: for<'d> smoltcp::phy::Device<'d> {
         size: usize,
         addr: task_net_api::Address,
     ) -> UdpMetadata;
+
+    #[cfg(feature = "tcp")]
+    fn make_tcp_meta(
+        &self,
+        port: u16,
+        addr: task_net_api::Address,
+    ) -> TcpMetadata;
+
+    /// Checks whether this device is the one addressed by `meta`
+    #[cfg(feature = "tcp")]
+    fn matches_tcp_meta(&self, meta: &TcpMetadata) -> bool;
+
+    fn stats(&self) -> &DeviceStats;
+
+    /// Returns the source filters and rate limits for this device's sockets
+    fn filters(&self) -> &filter::SocketFilters;
+
+    /// Returns the packet capture buffer, which is shared by all devices
+    #[cfg(feature = "pcap")]
+    fn capture(&self) -> &pcap::Capture;
+
+    /// Returns the multicast groups joined on this device's interface
+    #[cfg(feature = "multicast")]
+    fn multicast(&self) -> &multicast::Memberships;
+}
+
+/// Counters kept by each interface's device as frames go past, which wrap on
+/// overflow.
+pub struct DeviceStats {
+    rx_frames: Cell<u32>,
+    rx_bytes: Cell<u32>,
+    tx_frames: Cell<u32>,
+    tx_bytes: Cell<u32>,
+    /// UDP packets which arrived for each UDP socket's port, whether or not
+    /// smoltcp managed to queue them
+    udp_arrivals: [Cell<u32>; SOCKET_COUNT],
+}
+
+fn bump(c: &Cell<u32>, n: usize) {
+    c.set(c.get().wrapping_add(n as u32));
+}
+
+impl Default for DeviceStats {
+    fn default() -> Self {
+        const ZERO: Cell<u32> = Cell::new(0);
+        Self {
+            rx_frames: ZERO,
+            rx_bytes: ZERO,
+            tx_frames: ZERO,
+            tx_bytes: ZERO,
+            udp_arrivals: [ZERO; SOCKET_COUNT],
+        }
+    }
+}
+
+impl DeviceStats {
+    /// Counts an incoming frame, before it's passed to smoltcp. `socket` is
+    /// the UDP socket it's for, as found by [`udp_destination`].
+    pub fn count_rx(&self, frame: &[u8], socket: Option<usize>) {
+        bump(&self.rx_frames, 1);
+        bump(&self.rx_bytes, frame.len());
+        if let Some(i) = socket {
+            bump(&self.udp_arrivals[i], 1);
+        }
+    }
+
+    /// Counts an outgoing frame of `len` bytes.
+    pub fn count_tx(&self, len: usize) {
+        bump(&self.tx_frames, 1);
+        bump(&self.tx_bytes, len);
+    }
+}
+
+/// Returns the protocol, source address, and source and destination ports of
+/// `frame`, if it's a UDP or TCP packet over IPv6. (Packets with extension
+/// headers aren't recognized, but we don't expect to see any.)
+pub(crate) fn transport_ports(
+    frame: &[u8],
+) -> Option<(IpProtocol, Ipv6Address, u16, u16)> {
+    let eth = EthernetFrame::new_checked(frame).ok()?;
+    if eth.ethertype() != EthernetProtocol::Ipv6 {
+        return None;
+    }
+    let ip = Ipv6Packet::new_checked(eth.payload()).ok()?;
+    let src = ip.src_addr();
+    match ip.next_header() {
+        IpProtocol::Udp => {
+            let udp = UdpPacket::new_checked(ip.payload()).ok()?;
+            Some((IpProtocol::Udp, src, udp.src_port(), udp.dst_port()))
+        }
+        IpProtocol::Tcp => {
+            let tcp = TcpPacket::new_checked(ip.payload()).ok()?;
+            Some((IpProtocol::Tcp, src, tcp.src_port(), tcp.dst_port()))
+        }
+        _ => None,
+    }
+}
+
+/// Finds the UDP socket that an incoming `frame` on the interface with index
+/// `vlan` (0 without VLANs) is for, returning its index and the packet's
+/// source address. Sockets on different VLANs may share a port, so only
+/// those bound on this interface are considered.
+pub(crate) fn udp_destination(
+    frame: &[u8],
+    vlan: usize,
+) -> Option<(usize, Ipv6Address)> {
+    let (IpProtocol::Udp, src, _, port) = transport_ports(frame)? else {
+        return None;
+    };
+    let socket = (0..SOCKET_COUNT).find(|&i| {
+        generated::SOCKET_KINDS[i] == SocketKind::Udp
+            && generated::SOCKET_PORTS[i] == port
+            && generated::SOCKET_VLANS[i] & (1 << vlan) != 0
+    })?;
+    Some((socket, src))
 }
 
 /// State for the running network server
@@ -267,34 +747,309 @@ where
 
     mac: EthernetAddress,
     spare_macs: MacAddressBlock,
+
+    /// State and recent history of the board's Ethernet links
+    links: link::LinkMonitor,
+    /// Kernel time at which `wake` should next poll the links
+    next_link_poll: u64,
 }
 
 struct VLanState<E>
 where
     E: DeviceExt,
 {
-    socket_handles: [SocketHandle; SOCKET_COUNT],
+    /// Handles for each socket, or `None` if the socket isn't bound on this
+    /// VLAN
+    socket_handles: [Option<SocketHandle>; SOCKET_COUNT],
     iface: Interface<'static, E>,
+
+    /// TCP socket states as of the last call to `wake_sockets`, so that we
+    /// can notify owners when their connections change state
+    #[cfg(feature = "tcp")]
+    tcp_states: [TcpState; SOCKET_COUNT],
+
+    /// Whether each TCP socket's current connection has been returned by
+    /// `tcp_accept` (or was opened by `tcp_connect`), so that it's only
+    /// handed to the owner once
+    #[cfg(feature = "tcp")]
+    tcp_accepted: [bool; SOCKET_COUNT],
+
+    /// Default router for off-link destinations, if we have one
+    router: Option<Ipv6Address>,
+
+    /// Counters for each socket on this interface
+    socket_stats: [SocketStats; SOCKET_COUNT],
+
+    #[cfg(feature = "slaac")]
+    slaac: SlaacState,
+
+    /// Raw socket which sends MLD reports and receives queries
+    #[cfg(feature = "multicast")]
+    mld: SocketHandle,
+
+    /// Set when our addresses change, so that the server reprograms the
+    /// MAC's address filter for their solicited-node groups
+    #[cfg(feature = "multicast")]
+    addresses_changed: bool,
+}
+
+/// Minimum interval between polls of the links' state by `wake`, in
+/// milliseconds. Each poll costs a handful of SPI and MIIM transactions, so
+/// this is slower than the BSP's usual `WAKE_INTERVAL`.
+const LINK_POLL_INTERVAL: u64 = 2000;
+
+/// Slots in each interface's address list. Empty slots hold `::/128`, which
+/// never matches a real address; see `no_address`.
+const ADDR_LINK_LOCAL: usize = 0;
+const ADDR_STATIC: usize = 1;
+const ADDR_SLAAC: usize = 2;
+const ADDR_COUNT: usize = 3;
+
+fn no_address() -> IpCidr {
+    Ipv6Cidr::new(Ipv6Address::UNSPECIFIED, 128).into()
+}
+
+#[cfg(feature = "slaac")]
+struct SlaacState {
+    /// Raw socket which receives router advertisements
+    handle: SocketHandle,
+    /// When the SLAAC address expires, or `None` if it doesn't (or there
+    /// isn't one)
+    addr_expires: Option<u64>,
+    /// When the default router expires, if it was learned from an
+    /// advertisement
+    router_expires: Option<u64>,
+    /// Whether the default router is from static config, in which case
+    /// advertisements don't change it
+    static_router: bool,
 }
 
 impl<E: DeviceExt> VLanState<E> {
+    /// Copies the device's filter counters for socket `index`, and brings
+    /// `rx_dropped` up to date if its rx queue is empty. At that point, every
+    /// packet which arrived for the socket was either delivered, discarded,
+    /// filtered, rate limited, or dropped, so we can work out the last one by
+    /// subtraction.
+    fn settle_rx_dropped(&mut self, index: usize) {
+        let filters = self.iface.device().filters();
+        let stats = &mut self.socket_stats[index];
+        stats.rx_filtered = filters.filtered[index].get();
+        stats.rx_rate_limited = filters.rate_limited[index].get();
+
+        match self.get_socket_mut(index) {
+            Some(socket) if !socket.can_recv() => (),
+            _ => return,
+        }
+        let arrivals = self.iface.device().stats().udp_arrivals[index].get();
+        let stats = &mut self.socket_stats[index];
+        stats.rx_dropped = arrivals
+            .wrapping_sub(stats.rx_packets)
+            .wrapping_sub(stats.rx_discarded)
+            .wrapping_sub(stats.rx_filtered)
+            .wrapping_sub(stats.rx_rate_limited);
+    }
+
+    /// Returns the address in `slot` (one of the `ADDR_*` constants), or
+    /// `None` if the slot is empty.
+    fn address(&self, slot: usize) -> Option<Ipv6Cidr> {
+        match self.iface.ip_addrs()[slot] {
+            IpCidr::Ipv6(c) if !c.address().is_unspecified() => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Sets (or clears) the address in `slot`, rebinding global sockets if
+    /// that changes the interface's global address, and flagging that the
+    /// MAC's address filter needs updating (see `update_address_filter`).
+    fn set_address(&mut self, slot: usize, addr: Option<Ipv6Cidr>) {
+        self.iface.update_ip_addrs(|addrs| {
+            addrs[slot] = addr.map(Into::into).unwrap_or_else(no_address);
+        });
+        self.bind_global_sockets();
+        #[cfg(feature = "multicast")]
+        self.addresses_changed = true;
+    }
+
+    /// Binds global sockets to this interface's global address, preferring a
+    /// static address over one from SLAAC. If the address has changed,
+    /// sockets are closed (dropping anything queued) and rebound; if there's
+    /// no global address, they're left closed.
+    fn bind_global_sockets(&mut self) {
+        let addr = self
+            .address(ADDR_STATIC)
+            .or_else(|| self.address(ADDR_SLAAC))
+            .map(|c| c.address());
+        for i in 0..SOCKET_COUNT {
+            if !generated::SOCKET_GLOBAL[i] {
+                continue;
+            }
+            let Some(socket) = self.get_socket_mut(i) else { continue };
+            let bound = socket.is_open().then(|| socket.endpoint().addr);
+            if bound == addr.map(IpAddress::from) {
+                continue;
+            }
+            socket.close();
+            if let Some(addr) = addr {
+                socket
+                    .bind((addr, generated::SOCKET_PORTS[i]))
+                    .unwrap_lite();
+            }
+        }
+    }
+
+    /// Points the default route at `router`, or removes it.
+    fn set_router(&mut self, router: Option<Ipv6Address>) {
+        let routes = self.iface.routes_mut();
+        match router {
+            Some(r) => {
+                routes.add_default_ipv6_route(r).unwrap_lite();
+            }
+            None => {
+                routes.remove_default_ipv6_route();
+            }
+        }
+        self.router = router;
+    }
+
     fn get_handle(&self, index: usize) -> Option<SocketHandle> {
-        self.socket_handles.get(index).cloned()
+        self.socket_handles.get(index).cloned().flatten()
     }
 
-    /// Gets the socket `index`. If `index` is out of range, returns
-    /// `None`.
-    ///
-    /// Sockets are currently assumed to be UDP.
+    /// Gets the UDP socket `index`. If `index` is out of range, the socket
+    /// isn't bound on this VLAN, or it isn't a UDP socket, returns `None`.
     pub(crate) fn get_socket_mut(
         &mut self,
         index: usize,
     ) -> Option<&mut UdpSocket<'static>> {
+        if generated::SOCKET_KINDS.get(index)? != &SocketKind::Udp {
+            return None;
+        }
         Some(
             self.iface
                 .get_socket::<UdpSocket<'_>>(self.get_handle(index)?),
         )
     }
+
+    /// Gets the TCP socket `index`, as in `get_socket_mut`.
+    #[cfg(feature = "tcp")]
+    pub(crate) fn get_tcp_socket_mut(
+        &mut self,
+        index: usize,
+    ) -> Option<&mut TcpSocket<'static>> {
+        if generated::SOCKET_KINDS.get(index)? != &SocketKind::Tcp {
+            return None;
+        }
+        Some(
+            self.iface
+                .get_socket::<TcpSocket<'_>>(self.get_handle(index)?),
+        )
+    }
+}
+
+#[cfg(feature = "slaac")]
+impl<E: DeviceExt> VLanState<E> {
+    /// Handles router advertisements received since the last poll, then
+    /// drops the SLAAC address and learned router if they've expired.
+    fn poll_slaac(&mut self, now: u64) {
+        let mut adverts: Vec<slaac::RouterAdvert, 2> = Vec::new();
+        let socket = self.iface.get_socket::<RawSocket<'_>>(self.slaac.handle);
+        while let Ok(packet) = socket.recv() {
+            if let Some(ra) = slaac::parse(packet) {
+                // If we somehow get more than we can handle at once, the
+                // router will send another one soon enough.
+                adverts.push(ra).ok();
+            }
+        }
+        for ra in adverts {
+            self.handle_router_advert(ra, now);
+        }
+
+        if self.slaac.addr_expires.map_or(false, |t| t <= now) {
+            self.slaac.addr_expires = None;
+            self.set_address(ADDR_SLAAC, None);
+        }
+        if self.slaac.router_expires.map_or(false, |t| t <= now) {
+            self.slaac.router_expires = None;
+            self.set_router(None);
+        }
+    }
+
+    fn handle_router_advert(&mut self, ra: slaac::RouterAdvert, now: u64) {
+        if let Some(prefix) = ra.prefix {
+            let link_local = self.address(ADDR_LINK_LOCAL).unwrap_lite();
+            let addr = prefix.address(link_local.address());
+            match self.address(ADDR_SLAAC) {
+                Some(current) if current == addr => {
+                    self.slaac.addr_expires = slaac::expiry(
+                        now,
+                        self.slaac.addr_expires,
+                        prefix.valid_lifetime,
+                    );
+                }
+                // We only configure one address per interface, so ignore
+                // other prefixes while we have one.
+                Some(_) => (),
+                None if prefix.valid_lifetime == Some(0) => (),
+                None => {
+                    self.slaac.addr_expires =
+                        prefix.valid_lifetime.map(|v| now + v);
+                    self.set_address(ADDR_SLAAC, Some(addr));
+                }
+            }
+        }
+
+        if self.slaac.static_router {
+            return;
+        }
+        if ra.router_lifetime > 0 {
+            // Like addresses, we stick with the first router we hear from.
+            if self.router.map_or(true, |r| r == ra.router) {
+                self.slaac.router_expires = Some(now + ra.router_lifetime);
+                self.set_router(Some(ra.router));
+            }
+        } else if self.router == Some(ra.router) {
+            self.slaac.router_expires = None;
+            self.set_router(None);
+        }
+    }
+}
+
+#[cfg(feature = "multicast")]
+impl<E: DeviceExt> VLanState<E> {
+    /// Answers MLD queries received since the last poll.
+    fn poll_mld(&mut self) {
+        let mut queries: Vec<multicast::Query, 2> = Vec::new();
+        let socket = self.iface.get_socket::<RawSocket<'_>>(self.mld);
+        while let Ok(packet) = socket.recv() {
+            if let Some(q) = multicast::parse_query(packet) {
+                // Routers repeat their queries, so we can drop extras.
+                queries.push(q).ok();
+            }
+        }
+        for q in queries {
+            let groups: Vec<Ipv6Address, { multicast::MEMBERSHIPS }> = self
+                .iface
+                .device()
+                .multicast()
+                .groups()
+                .filter(|g| match q {
+                    multicast::Query::General => true,
+                    multicast::Query::Group(a) => *g == a,
+                })
+                .collect();
+            self.send_mld_report(RecordType::IsExclude, &groups);
+        }
+    }
+
+    /// Sends an MLD report about `groups`, unless it's empty.
+    fn send_mld_report(&mut self, record: RecordType, groups: &[Ipv6Address]) {
+        if groups.is_empty() {
+            return;
+        }
+        let src = self.address(ADDR_LINK_LOCAL).unwrap_lite().address();
+        let socket = self.iface.get_socket::<RawSocket<'_>>(self.mld);
+        multicast::send_report(socket, src, record, groups);
+    }
 }
 
 impl<'a, B, E, const N: usize> GenServerImpl<'a, B, E, N>
@@ -306,6 +1061,7 @@ where
     pub(crate) fn new(
         eth: &'a eth::Ethernet,
         mac_address_block: MacAddressBlock,
+        ipv6_static: [Ipv6StaticAddress; N],
         bsp: B,
         storage: &'static mut [Storage; N],
         sockets: generated::Sockets<'static, N>,
@@ -324,7 +1080,7 @@ where
             let ipv6_addr = link_local_iface_addr(mac_addr);
 
             // Make some types explicit to try and make this clearer.
-            let sockets: [UdpSocket<'_>; SOCKET_COUNT] = sockets;
+            let sockets: [Option<NetSocket<'_>>; SOCKET_COUNT] = sockets;
 
             let neighbor_cache =
                 smoltcp::iface::NeighborCache::new(&mut storage.neighbors[..]);
@@ -334,29 +1090,83 @@ where
                 &mut storage.sockets[..],
             );
 
-            storage.net = Ipv6Cidr::new(ipv6_addr, 64).into();
+            // An all-zero address or gateway means there isn't one.
+            let static_cfg = &ipv6_static[i];
+            let static_addr = (static_cfg.addr != [0; 16]).then(|| {
+                Ipv6Cidr::new(
+                    Ipv6Address(static_cfg.addr),
+                    static_cfg.prefix_len,
+                )
+            });
+            let gateway = (static_cfg.gateway != [0; 16])
+                .then_some(Ipv6Address(static_cfg.gateway));
+
+            storage.net[ADDR_LINK_LOCAL] = Ipv6Cidr::new(ipv6_addr, 64).into();
+            storage.net[ADDR_STATIC] =
+                static_addr.map(Into::into).unwrap_or_else(no_address);
+            storage.net[ADDR_SLAAC] = no_address();
             let mut iface = builder
                 .hardware_addr(mac_addr.into())
                 .neighbor_cache(neighbor_cache)
-                .ip_addrs(core::slice::from_mut(&mut storage.net))
+                .ip_addrs(&mut storage.net[..])
+                .routes(Routes::new(&mut storage.routes[..]))
                 .finalize();
 
             // Associate sockets with this interface.
-            let socket_handles = sockets.map(|s| iface.add_socket(s));
-            // Bind sockets to their ports.
-            for (&h, port) in zip(&socket_handles, generated::SOCKET_PORTS) {
+            let socket_handles = sockets.map(|s| {
+                s.map(|s| match s {
+                    NetSocket::Udp(s) => iface.add_socket(s),
+                    #[cfg(feature = "tcp")]
+                    NetSocket::Tcp(s) => iface.add_socket(s),
+                })
+            });
+            // Bind UDP sockets to their ports on our link-local address.
+            // TCP sockets are bound when their owners ask to listen or
+            // connect, and global sockets are bound below.
+            let udp_ports = (0..SOCKET_COUNT).map(|i| {
+                (generated::SOCKET_KINDS[i] == SocketKind::Udp
+                    && !generated::SOCKET_GLOBAL[i])
+                    .then_some(generated::SOCKET_PORTS[i])
+            });
+            for (h, port) in zip(&socket_handles, udp_ports) {
+                let (Some(h), Some(port)) = (*h, port) else { continue };
                 iface
                     .get_socket::<UdpSocket<'_>>(h)
                     .bind((ipv6_addr, port))
                     .unwrap_lite();
             }
 
-            vlan_state
-                .push(VLanState {
-                    socket_handles,
-                    iface,
-                })
-                .unwrap_lite();
+            #[cfg(feature = "slaac")]
+            let slaac = SlaacState {
+                handle: iface.add_socket(slaac::socket(&mut storage.ra)),
+                addr_expires: None,
+                router_expires: None,
+                static_router: gateway.is_some(),
+            };
+            #[cfg(feature = "multicast")]
+            let mld = iface.add_socket(multicast::socket(&mut storage.mld));
+
+            let mut state = VLanState {
+                socket_handles,
+                iface,
+                #[cfg(feature = "tcp")]
+                tcp_states: [TcpState::Closed; SOCKET_COUNT],
+                #[cfg(feature = "tcp")]
+                tcp_accepted: [false; SOCKET_COUNT],
+                router: None,
+                socket_stats: [SocketStats::default(); SOCKET_COUNT],
+                #[cfg(feature = "slaac")]
+                slaac,
+                #[cfg(feature = "multicast")]
+                mld,
+                #[cfg(feature = "multicast")]
+                addresses_changed: false,
+            };
+            if gateway.is_some() {
+                state.set_router(gateway);
+            }
+            state.bind_global_sockets();
+            vlan_state.push(state).unwrap_lite();
 
             // Increment the MAC and IP addresses based on the stride in the
             // configuration block, so that each VLAN has a unique address.
@@ -385,7 +1195,7 @@ where
             mac[3..].copy_from_slice(&next_mac.to_be_bytes()[1..]);
         }
 
-        Self {
+        let server = Self {
             eth,
             client_waiting_to_send: [false; SOCKET_COUNT],
             vlan_state: vlan_state.into_array().unwrap_lite(),
@@ -396,7 +1206,12 @@ where
                 count: U16::new(mac_address_block.count.get() - N as u16),
                 stride: mac_address_block.stride,
             },
-        }
+            links: link::LinkMonitor::default(),
+            next_link_poll: 0,
+        };
+        #[cfg(feature = "multicast")]
+        server.update_address_filter();
+        server
     }
 
     pub(crate) fn poll(&mut self, t: u64) -> smoltcp::Result<crate::Activity> {
@@ -409,6 +1224,22 @@ where
             ip |= vlan.iface.poll(t)?;
             // Test and clear our receive activity flag.
             mac_rx |= vlan.iface.device().read_and_clear_activity_flag();
+
+            #[cfg(feature = "slaac")]
+            vlan.poll_slaac(t.total_millis() as u64);
+            #[cfg(feature = "multicast")]
+            vlan.poll_mld();
+        }
+
+        // SLAAC may have changed our addresses, and so the solicited-node
+        // groups that we need to receive.
+        #[cfg(feature = "multicast")]
+        if self
+            .vlan_state
+            .iter_mut()
+            .fold(false, |c, v| core::mem::take(&mut v.addresses_changed) | c)
+        {
+            self.update_address_filter();
         }
 
         Ok(crate::Activity { ip, mac_rx })
@@ -421,22 +1252,37 @@ where
     /// - any of its sockets (on any VLAN) have incoming packets waiting, or
     ///
     /// - it is waiting to send on some socket S, and _all_ of the copies of S
-    ///   across all VLANs can accept an outgoing packet. (The "all" is
-    ///   important here since we don't keep track of which one it's trying to
-    ///   send through.)
+    ///   across the VLANs where it's bound can accept an outgoing packet. (The
+    ///   "all" is important here since we don't keep track of which one it's
+    ///   trying to send through.)
+    ///
+    /// TCP sockets also wake their owners when a connection changes state
+    /// (e.g. it's established, or closed by the remote end).
     pub fn wake_sockets(&mut self) {
         for i in 0..SOCKET_COUNT {
+            #[cfg(feature = "tcp")]
+            if generated::SOCKET_KINDS[i] == SocketKind::Tcp {
+                if self.tcp_wake(i) {
+                    let (task_id, notification) = generated::SOCKET_OWNERS[i];
+                    let task_id = sys_refresh_task_id(task_id);
+                    sys_post(task_id, notification);
+                }
+                continue;
+            }
+
             // recv wake depends only on the state of the sockets.
             let recv_wake = self
                 .vlan_state
                 .iter_mut()
-                .any(|v| v.get_socket_mut(i).unwrap().can_recv());
+                .filter_map(|v| v.get_socket_mut(i))
+                .any(|s| s.can_recv());
             // send wake only happens if the wait flag is set.
             let send_wake = self.client_waiting_to_send[i]
                 && self
                     .vlan_state
                     .iter_mut()
-                    .all(|v| v.get_socket_mut(i).unwrap().can_send());
+                    .filter_map(|v| v.get_socket_mut(i))
+                    .all(|s| s.can_send());
 
             if recv_wake || send_wake {
                 let (task_id, notification) = generated::SOCKET_OWNERS[i];
@@ -446,8 +1292,33 @@ where
         }
     }
 
-    pub fn wake(&self) {
-        self.bsp.wake(self.eth)
+    /// Pokes the BSP, then (if any task has subscribed to link events, and
+    /// it's been `LINK_POLL_INTERVAL` since we last looked) polls the links.
+    pub fn wake(&mut self) {
+        self.bsp.wake(self.eth);
+
+        if generated::LINK_SUBSCRIBERS.is_empty() {
+            return;
+        }
+        let now = userlib::sys_get_timer().now;
+        if now >= self.next_link_poll {
+            self.next_link_poll = now + LINK_POLL_INTERVAL;
+            self.poll_links(now);
+        }
+    }
+
+    /// Checks whether any link has changed state, notifying the tasks which
+    /// asked to hear about it if so.
+    fn poll_links(&mut self, now: u64) {
+        let Some(states) = self.bsp.link_states(self.eth) else {
+            return;
+        };
+        if self.links.update(states, now) {
+            for (task_id, notification) in generated::LINK_SUBSCRIBERS {
+                let task_id = sys_refresh_task_id(task_id);
+                sys_post(task_id, notification);
+            }
+        }
     }
 
     fn eth_bsp(&mut self) -> (&eth::Ethernet, &mut B) {
@@ -458,6 +1329,12 @@ where
         &self.mac
     }
 
+    /// Returns the packet capture buffer (which every device shares)
+    #[cfg(feature = "pcap")]
+    fn capture(&self) -> &pcap::Capture {
+        self.vlan_state[0].iface.device().capture()
+    }
+
     /// Requests that a packet waiting in the rx queue of `socket` be delivered
     /// into loaned memory at `payload`.
     ///
@@ -478,47 +1355,59 @@ where
         {
             return Err(RecvError::NotYours.into());
         }
+        if generated::SOCKET_KINDS[socket_index] != SocketKind::Udp {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
 
         // Iterate over all of the per-VLAN sockets, returning the first
         // available packet with a bonus `vid` tag attached in the metadata.
         for vlan in &mut self.vlan_state {
-            let socket = vlan
-                .get_socket_mut(socket_index)
-                .ok_or(RequestError::Fail(ClientError::BadMessageContents))?;
-            loop {
+            // Skip VLANs where this socket isn't bound
+            let Some(socket) = vlan.get_socket_mut(socket_index) else {
+                continue;
+            };
+            let mut discarded = 0;
+            let result = loop {
                 match socket.recv() {
                     Ok((body, endp)) => {
                         if payload.len() < body.len() {
+                            // If we add a `::Fail` case, we will need to
+                            // allow for caller retries (possibly by peeking
+                            // on the socket instead of recving)
                             match large_payload_behavior {
-                                LargePayloadBehavior::Discard => continue,
-                                // If we add a `::Fail` case, we will need to
-                                // allow for caller retries (possibly by peeking
-                                // on the socket instead of recving)
+                                LargePayloadBehavior::Discard => {
+                                    discarded += 1;
+                                    continue;
+                                }
                             }
                         }
                         payload
                             .write_range(0..body.len(), body)
                             .map_err(|_| RequestError::went_away())?;
-
-                        // Release borrow on self/socket
-                        let body_len = body.len();
-
-                        return Ok(vlan.iface.device().make_meta(
-                            endp.port,
-                            body_len,
-                            endp.addr.try_into().map_err(|_| ()).unwrap(),
-                        ));
+                        break Some((body.len(), endp));
                     }
                     Err(smoltcp::Error::Exhausted) => {
                         // Move on to next vid
-                        break;
+                        break None;
                     }
                     Err(_) => {
                         // uhhhh TODO
                         // (move on to next vid in the meantime)
-                        break;
+                        break None;
                     }
                 }
+            };
+
+            // Release borrow on self/socket
+            let stats = &mut vlan.socket_stats[socket_index];
+            stats.rx_discarded = stats.rx_discarded.wrapping_add(discarded);
+            if let Some((body_len, endp)) = result {
+                stats.rx_packets = stats.rx_packets.wrapping_add(1);
+                return Ok(vlan.iface.device().make_meta(
+                    endp.port,
+                    body_len,
+                    endp.addr.try_into().map_err(|_| ()).unwrap(),
+                ));
             }
         }
         Err(RecvError::QueueEmpty.into())
@@ -539,6 +1428,9 @@ where
         {
             return Err(SendError::NotYours.into());
         }
+        if generated::SOCKET_KINDS[socket_index] != SocketKind::Udp {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
 
         #[cfg(feature = "vlan")]
         let vlan_index = {
@@ -551,11 +1443,12 @@ where
         #[cfg(not(feature = "vlan"))]
         let vlan_index = 0;
 
-        let socket =
-            self.vlan_state[vlan_index]
-                .get_socket_mut(socket_index)
-                .ok_or(RequestError::Fail(ClientError::BadMessageContents))?;
-        match socket.send(payload.len(), metadata.into()) {
+        // The socket may not be bound on every VLAN
+        let vlan = &mut self.vlan_state[vlan_index];
+        let socket = vlan
+            .get_socket_mut(socket_index)
+            .ok_or(SendError::InvalidVLan)?;
+        let result = match socket.send(payload.len(), metadata.into()) {
             Ok(buf) => {
                 payload
                     .read_range(0..payload.len(), buf)
@@ -565,13 +1458,416 @@ where
             }
             Err(smoltcp::Error::Exhausted) => {
                 self.client_waiting_to_send[socket_index] = true;
-                Err(SendError::QueueFull.into())
+                Err(SendError::QueueFull)
             }
             Err(_e) => {
                 // uhhhh TODO
-                Err(SendError::Other.into())
+                Err(SendError::Other)
+            }
+        };
+
+        let stats = &mut vlan.socket_stats[socket_index];
+        let counter = match result {
+            Ok(()) => &mut stats.tx_packets,
+            Err(SendError::QueueFull) => &mut stats.tx_queue_full,
+            Err(_) => &mut stats.tx_errors,
+        };
+        *counter = counter.wrapping_add(1);
+        result.map_err(RequestError::from)
+    }
+}
+
+/// TCP support, which is only built if the `tcp` feature is enabled.
+///
+/// Each TCP socket carries at most one connection per VLAN (smoltcp sockets
+/// don't have a separate accept queue), so an owner listens, waits for a
+/// notification, accepts the connection, and then listens again once it's
+/// closed.
+#[cfg(feature = "tcp")]
+impl<'a, B, E, const N: usize> GenServerImpl<'a, B, E, N>
+where
+    B: bsp_support::Bsp,
+    E: DeviceExt,
+{
+    /// Checks that `socket` is a TCP socket owned by the sender, returning
+    /// its index.
+    fn check_tcp_socket(
+        &self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<usize, TcpError> {
+        let socket_index = socket as usize;
+        if generated::SOCKET_OWNERS[socket_index].0.index()
+            != msg.sender.index()
+        {
+            return Err(TcpError::NotYours);
+        }
+        if generated::SOCKET_KINDS[socket_index] != SocketKind::Tcp {
+            return Err(TcpError::NotTcp);
+        }
+        Ok(socket_index)
+    }
+
+    /// Finds the VLAN addressed by `meta`
+    fn tcp_vlan(
+        &mut self,
+        meta: &TcpMetadata,
+    ) -> Result<&mut VLanState<E>, TcpError> {
+        self.vlan_state
+            .iter_mut()
+            .find(|v| v.iface.device().matches_tcp_meta(meta))
+            .ok_or(TcpError::InvalidVLan)
+    }
+
+    /// Finds the copy of socket `index` whose current connection is
+    /// described by `connection`.
+    fn tcp_connection(
+        &mut self,
+        index: usize,
+        connection: &TcpMetadata,
+    ) -> Result<&mut TcpSocket<'static>, TcpError> {
+        let socket = self
+            .tcp_vlan(connection)?
+            .get_tcp_socket_mut(index)
+            .ok_or(TcpError::InvalidVLan)?;
+        if !socket.is_active()
+            || socket.remote_endpoint() != (*connection).into()
+        {
+            return Err(TcpError::NotConnected);
+        }
+        Ok(socket)
+    }
+
+    /// Bumps the counter chosen by `f` in the stats for socket `index` on the
+    /// VLAN of `connection`.
+    fn tcp_count(
+        &mut self,
+        index: usize,
+        connection: &TcpMetadata,
+        f: impl FnOnce(&mut SocketStats) -> &mut u32,
+    ) {
+        if let Ok(vlan) = self.tcp_vlan(connection) {
+            let counter = f(&mut vlan.socket_stats[index]);
+            *counter = counter.wrapping_add(1);
+        }
+    }
+
+    /// Checks whether the owner of TCP socket `index` should be woken, and
+    /// records each copy's state for next time.
+    fn tcp_wake(&mut self, index: usize) -> bool {
+        let waiting = self.client_waiting_to_send[index];
+        let mut wake = false;
+        for vlan in &mut self.vlan_state {
+            let (state, ready) = match vlan.get_tcp_socket_mut(index) {
+                Some(s) => {
+                    (s.state(), s.can_recv() || (waiting && s.can_send()))
+                }
+                None => continue,
+            };
+            let prev = core::mem::replace(&mut vlan.tcp_states[index], state);
+            wake |= ready || prev != state;
+        }
+        wake
+    }
+
+    /// Starts listening on every copy of `socket` which isn't already in
+    /// use.
+    fn net_tcp_listen(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+        let port = generated::SOCKET_PORTS[socket_index];
+
+        let mut listening = false;
+        for vlan in &mut self.vlan_state {
+            let Some(socket) = vlan.get_tcp_socket_mut(socket_index) else {
+                continue;
+            };
+            if !socket.is_open() {
+                socket.listen(port).map_err(|_| TcpError::Other)?;
+                vlan.tcp_accepted[socket_index] = false;
+            }
+            listening |= socket.is_listening();
+        }
+
+        if listening {
+            Ok(())
+        } else {
+            Err(TcpError::InvalidState.into())
+        }
+    }
+
+    /// Returns the first established connection on any copy of `socket`
+    /// which hasn't already been accepted.
+    fn net_tcp_accept(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<TcpMetadata, RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+
+        for vlan in &mut self.vlan_state {
+            if vlan.tcp_accepted[socket_index] {
+                continue;
+            }
+            let endp = match vlan.get_tcp_socket_mut(socket_index) {
+                Some(s) => match s.state() {
+                    TcpState::Established | TcpState::CloseWait => {
+                        s.remote_endpoint()
+                    }
+                    _ => continue,
+                },
+                None => continue,
+            };
+            let addr = endp.addr.try_into().map_err(|_| TcpError::Other)?;
+            vlan.tcp_accepted[socket_index] = true;
+            return Ok(vlan.iface.device().make_tcp_meta(endp.port, addr));
+        }
+        Err(TcpError::WouldBlock.into())
+    }
+
+    /// Starts connecting `socket` to `remote`, on the VLAN given in `remote`.
+    fn net_tcp_connect(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        remote: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+        let port = generated::SOCKET_PORTS[socket_index];
+
+        let vlan = self.tcp_vlan(&remote)?;
+        let handle =
+            vlan.get_handle(socket_index).ok_or(TcpError::InvalidVLan)?;
+        let (socket, cx) =
+            vlan.iface.get_socket_and_context::<TcpSocket<'_>>(handle);
+        if socket.is_open() {
+            return Err(TcpError::InvalidState.into());
+        }
+        socket
+            .connect(cx, IpEndpoint::from(remote), port)
+            .map_err(|_| TcpError::Other)?;
+        vlan.tcp_accepted[socket_index] = true;
+        Ok(())
+    }
+
+    /// Copies as much received data as fits from `connection` into
+    /// `payload`.
+    fn net_tcp_read(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+        payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+        let socket = self.tcp_connection(socket_index, &connection)?;
+
+        let r = socket.recv(|buf| {
+            let n = buf.len().min(payload.len());
+            match payload.write_range(0..n, &buf[..n]) {
+                Ok(()) => (n, Ok(n)),
+                Err(()) => (0, Err(())),
+            }
+        });
+        let result = match r {
+            Ok(Ok(0)) if payload.len() > 0 => Err(TcpError::WouldBlock.into()),
+            Ok(Ok(n)) => Ok(n as u32),
+            Ok(Err(())) => Err(RequestError::went_away()),
+            Err(smoltcp::Error::Finished) => Err(TcpError::Closed.into()),
+            Err(_) => Err(TcpError::NotConnected.into()),
+        };
+        if result.is_ok() {
+            self.tcp_count(socket_index, &connection, |s| &mut s.rx_packets);
+        }
+        result
+    }
+
+    /// Queues as much of `payload` as fits into `connection`.
+    fn net_tcp_write(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+        payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+        let socket = self.tcp_connection(socket_index, &connection)?;
+
+        let r = socket.send(|buf| {
+            let n = buf.len().min(payload.len());
+            match payload.read_range(0..n, &mut buf[..n]) {
+                Ok(()) => (n, Ok(n)),
+                Err(()) => (0, Err(())),
+            }
+        });
+        let result = match r {
+            Ok(Ok(0)) if payload.len() > 0 => {
+                self.client_waiting_to_send[socket_index] = true;
+                Err(TcpError::WouldBlock)
+            }
+            Ok(Ok(n)) => {
+                self.client_waiting_to_send[socket_index] = false;
+                Ok(n as u32)
             }
+            Ok(Err(())) => return Err(RequestError::went_away()),
+            Err(_) => Err(TcpError::Closed),
+        };
+        self.tcp_count(socket_index, &connection, |s| match result {
+            Ok(_) => &mut s.tx_packets,
+            Err(TcpError::WouldBlock) => &mut s.tx_queue_full,
+            Err(_) => &mut s.tx_errors,
+        });
+        result.map_err(RequestError::from)
+    }
+
+    /// Closes `connection`, after sending any data that's still queued.
+    fn net_tcp_close(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        connection: TcpMetadata,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.check_tcp_socket(msg, socket)?;
+        self.tcp_connection(socket_index, &connection)?.close();
+        self.client_waiting_to_send[socket_index] = false;
+        Ok(())
+    }
+}
+
+/// Multicast support, which is only built if the `multicast` feature is
+/// enabled.
+///
+/// Group memberships are per socket and per VLAN; each interface reports the
+/// union of its sockets' groups to routers.
+#[cfg(feature = "multicast")]
+impl<'a, B, E, const N: usize> GenServerImpl<'a, B, E, N>
+where
+    B: bsp_support::Bsp,
+    E: DeviceExt,
+{
+    /// Checks the arguments to `join_multicast_group` and
+    /// `leave_multicast_group`, returning the VLAN index and membership.
+    fn check_membership(
+        &self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        vid: u16,
+        group: Ipv6Address,
+    ) -> Result<(usize, multicast::Membership), MulticastError> {
+        let socket_index = socket as usize;
+        if generated::SOCKET_OWNERS[socket_index].0.index()
+            != msg.sender.index()
+        {
+            return Err(MulticastError::NotYours);
+        }
+        if generated::SOCKET_KINDS[socket_index] != SocketKind::Udp {
+            return Err(MulticastError::NotUdp);
+        }
+        if !multicast::is_joinable(group) {
+            return Err(MulticastError::InvalidGroup);
+        }
+
+        #[cfg(feature = "vlan")]
+        let vlan_index = {
+            if !VLAN_RANGE.contains(&vid) {
+                return Err(MulticastError::InvalidVLan);
+            }
+            usize::from(vid - VLAN_RANGE.start)
+        };
+        #[cfg(not(feature = "vlan"))]
+        let vlan_index = {
+            // There's only the one interface
+            let _ = vid;
+            0
+        };
+
+        // The socket may not be bound on every VLAN
+        if self.vlan_state[vlan_index]
+            .get_handle(socket_index)
+            .is_none()
+        {
+            return Err(MulticastError::InvalidVLan);
         }
+        let m = multicast::Membership {
+            group,
+            socket: socket_index,
+        };
+        Ok((vlan_index, m))
+    }
+
+    /// Adds `socket` to `group`. If this is the first socket to join the
+    /// group on its interface, we report that to routers.
+    fn net_join_multicast_group(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        vid: u16,
+        group: Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        let (vlan_index, m) = self.check_membership(msg, socket, vid, group)?;
+        let vlan = &mut self.vlan_state[vlan_index];
+        let memberships = vlan.iface.device().multicast();
+        if memberships.contains(m) {
+            return Ok(());
+        }
+        let new_group = !memberships.has_group(group);
+        memberships
+            .insert(m)
+            .map_err(|()| MulticastError::NoSpace)?;
+
+        if new_group {
+            vlan.send_mld_report(RecordType::ToExclude, &[group]);
+            self.update_address_filter();
+        }
+        Ok(())
+    }
+
+    /// Removes `socket` from `group`, if it's a member. If this was the last
+    /// socket in the group on its interface, we report that to routers.
+    fn net_leave_multicast_group(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        vid: u16,
+        group: Ipv6Address,
+    ) -> Result<(), RequestError<MulticastError>> {
+        let (vlan_index, m) = self.check_membership(msg, socket, vid, group)?;
+        let vlan = &mut self.vlan_state[vlan_index];
+        let memberships = vlan.iface.device().multicast();
+        if memberships.remove(m) && !memberships.has_group(group) {
+            vlan.send_mld_report(RecordType::ToInclude, &[group]);
+            self.update_address_filter();
+        }
+        Ok(())
+    }
+
+    /// Programs the MAC's address filter with each interface's own address,
+    /// and every group that one of our interfaces belongs to: all-nodes, the
+    /// solicited-node groups of our addresses, and the groups that sockets
+    /// have joined. This takes the MAC out of promiscuous mode, so it's
+    /// called at startup, when sockets join or leave groups, and after `poll`
+    /// if any address has changed.
+    fn update_address_filter(&self) {
+        let vlan_macs = self.vlan_state.iter().flat_map(|v| {
+            let HardwareAddress::Ethernet(unicast) = v.iface.hardware_addr();
+            let solicited = [ADDR_LINK_LOCAL, ADDR_STATIC, ADDR_SLAAC]
+                .into_iter()
+                .filter_map(move |slot| v.address(slot))
+                .map(|a| multicast::solicited_node_mac(a.address()));
+            let joined = v
+                .iface
+                .device()
+                .multicast()
+                .groups()
+                .map(multicast::group_mac);
+            core::iter::once(unicast.0).chain(solicited).chain(joined)
+        });
+        let all_nodes = multicast::group_mac(Ipv6Address::LINK_LOCAL_ALL_NODES);
+        self.eth
+            .set_address_filter(core::iter::once(all_nodes).chain(vlan_macs));
     }
 }
 
@@ -596,11 +1892,22 @@ where
 }
 
 type NeighborStorage = Option<(IpAddress, Neighbor)>;
+type RouteStorage = Option<(IpCidr, Route)>;
+
+/// Sockets which the net task uses itself, in addition to the configured ones
+const EXTRA_SOCKETS: usize =
+    cfg!(feature = "slaac") as usize + cfg!(feature = "multicast") as usize;
 
 pub struct Storage {
     neighbors: [NeighborStorage; NEIGHBORS],
-    sockets: [SocketStorage<'static>; SOCKET_COUNT],
-    net: IpCidr,
+    sockets: [SocketStorage<'static>; SOCKET_COUNT + EXTRA_SOCKETS],
+    net: [IpCidr; ADDR_COUNT],
+    /// Just the default route
+    routes: [RouteStorage; 1],
+    #[cfg(feature = "slaac")]
+    ra: slaac::RaStorage,
+    #[cfg(feature = "multicast")]
+    mld: multicast::MldStorage,
 }
 
 impl Default for Storage {
@@ -608,7 +1915,12 @@ impl Default for Storage {
         Self {
             neighbors: Default::default(),
             sockets: Default::default(),
-            net: Ipv6Cidr::default().into(),
+            net: [no_address(); ADDR_COUNT],
+            routes: Default::default(),
+            #[cfg(feature = "slaac")]
+            ra: Default::default(),
+            #[cfg(feature = "multicast")]
+            mld: Default::default(),
         }
     }
 }
@@ -9,14 +9,21 @@
 use drv_stm32h7_eth as eth;
 
 use crate::bsp_support;
+use crate::filter;
 use crate::generated;
+#[cfg(feature = "multicast")]
+use crate::multicast;
+#[cfg(feature = "pcap")]
+use crate::pcap;
 use crate::{
-    server::{DeviceExt, GenServerImpl, Storage},
+    server::{udp_destination, DeviceExt, DeviceStats, GenServerImpl, Storage},
     MacAddressBlock,
 };
 use core::cell::Cell;
 use mutable_statics::mutable_statics;
-use task_net_api::UdpMetadata;
+#[cfg(feature = "tcp")]
+use task_net_api::TcpMetadata;
+use task_net_api::{Ipv6StaticAddress, UdpMetadata};
 
 /// Grabs references to the server storage arrays.  Can only be called once!
 fn claim_server_storage_statics() -> &'static mut [Storage; 1] {
@@ -32,18 +39,31 @@ pub type ServerImpl<'a, B> = GenServerImpl<'a, B, Smol<'a>, 1>;
 pub fn new<B>(
     eth: &eth::Ethernet,
     mac: MacAddressBlock,
+    ipv6_static: [Ipv6StaticAddress; 1],
     bsp: B,
 ) -> ServerImpl<'_, B>
 where
     B: bsp_support::Bsp,
 {
+    #[cfg(feature = "pcap")]
+    let capture = pcap::claim_capture_statics();
     ServerImpl::new(
         eth,
         mac,
+        ipv6_static,
         bsp,
         claim_server_storage_statics(),
         generated::construct_sockets(),
-        |_| Smol::from(eth),
+        |_| Smol {
+            eth,
+            mac_rx: Cell::new(false),
+            stats: DeviceStats::default(),
+            filters: Default::default(),
+            #[cfg(feature = "pcap")]
+            capture,
+            #[cfg(feature = "multicast")]
+            multicast: Default::default(),
+        },
     )
 }
 
@@ -56,14 +76,28 @@ where
 pub struct Smol<'d> {
     eth: &'d eth::Ethernet,
     mac_rx: Cell<bool>,
+    stats: DeviceStats,
+    filters: filter::SocketFilters,
+    #[cfg(feature = "pcap")]
+    capture: &'d pcap::Capture,
+    #[cfg(feature = "multicast")]
+    multicast: multicast::Memberships,
 }
 
-impl<'d> From<&'d eth::Ethernet> for Smol<'d> {
-    fn from(eth: &'d eth::Ethernet) -> Self {
-        Self {
-            eth,
-            mac_rx: Cell::new(false),
-        }
+impl Smol<'_> {
+    /// Records a frame that we've received (for UDP socket `socket`, if
+    /// any), before smoltcp sees it
+    fn saw_rx(&self, frame: &[u8], socket: Option<usize>) {
+        self.stats.count_rx(frame, socket);
+        #[cfg(feature = "pcap")]
+        self.capture.record(0, pcap::Direction::Rx, frame);
+    }
+
+    /// Records a frame that smoltcp has handed us to send
+    fn saw_tx(&self, frame: &[u8]) {
+        self.stats.count_tx(frame.len());
+        #[cfg(feature = "pcap")]
+        self.capture.record(0, pcap::Direction::Tx, frame);
     }
 }
 
@@ -71,13 +105,30 @@ pub struct OurRxToken<'d>(&'d Smol<'d>);
 impl<'d> smoltcp::phy::RxToken for OurRxToken<'d> {
     fn consume<R, F>(
         self,
-        _timestamp: smoltcp::time::Instant,
+        timestamp: smoltcp::time::Instant,
         f: F,
     ) -> smoltcp::Result<R>
     where
         F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
     {
-        self.0.eth.recv(f)
+        self.0.eth.recv(|frame| {
+            // Frames for groups we haven't joined are dropped before
+            // they're counted, since we'd never have seen them if the MAC
+            // were filtering.
+            #[cfg(feature = "multicast")]
+            if !self.0.multicast.accepts(frame) {
+                return Err(smoltcp::Error::Dropped);
+            }
+            let udp = udp_destination(frame, 0);
+            self.0.saw_rx(frame, udp.map(|(i, _)| i));
+            if let Some((i, src)) = udp {
+                let now = timestamp.total_millis() as u64;
+                if !self.0.filters.accepts(i, src, now) {
+                    return Err(smoltcp::Error::Dropped);
+                }
+            }
+            f(frame)
+        })
     }
 }
 
@@ -94,7 +145,13 @@ impl<'d> smoltcp::phy::TxToken for OurTxToken<'d> {
     {
         self.0
             .eth
-            .try_send(len, f)
+            .try_send(len, |frame| {
+                let r = f(&mut *frame);
+                if r.is_ok() {
+                    self.0.saw_tx(frame);
+                }
+                r
+            })
             .expect("TX token existed without descriptor available")
     }
 }
@@ -154,4 +211,37 @@ impl DeviceExt for Smol<'_> {
             addr,
         }
     }
+
+    #[cfg(feature = "tcp")]
+    fn make_tcp_meta(
+        &self,
+        port: u16,
+        addr: task_net_api::Address,
+    ) -> TcpMetadata {
+        TcpMetadata { port, addr }
+    }
+
+    #[cfg(feature = "tcp")]
+    fn matches_tcp_meta(&self, _meta: &TcpMetadata) -> bool {
+        // There's only one interface
+        true
+    }
+
+    fn stats(&self) -> &DeviceStats {
+        &self.stats
+    }
+
+    fn filters(&self) -> &filter::SocketFilters {
+        &self.filters
+    }
+
+    #[cfg(feature = "pcap")]
+    fn capture(&self) -> &pcap::Capture {
+        self.capture
+    }
+
+    #[cfg(feature = "multicast")]
+    fn multicast(&self) -> &multicast::Memberships {
+        &self.multicast
+    }
 }
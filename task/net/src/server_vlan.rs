@@ -11,12 +11,19 @@ use drv_stm32h7_eth as eth;
 
 use core::cell::Cell;
 use mutable_statics::mutable_statics;
-use task_net_api::UdpMetadata;
+#[cfg(feature = "tcp")]
+use task_net_api::TcpMetadata;
+use task_net_api::{Ipv6StaticAddress, UdpMetadata};
 
 use crate::bsp_support;
+use crate::filter;
 use crate::generated::{self, VLAN_COUNT, VLAN_RANGE};
+#[cfg(feature = "multicast")]
+use crate::multicast;
+#[cfg(feature = "pcap")]
+use crate::pcap;
 use crate::{
-    server::{DeviceExt, GenServerImpl, Storage},
+    server::{udp_destination, DeviceExt, DeviceStats, GenServerImpl, Storage},
     MacAddressBlock,
 };
 
@@ -33,6 +40,36 @@ pub struct VLanEthernet<'a> {
     pub eth: &'a eth::Ethernet,
     pub vid: u16,
     mac_rx: Cell<bool>,
+    stats: DeviceStats,
+    filters: filter::SocketFilters,
+    #[cfg(feature = "pcap")]
+    capture: &'a pcap::Capture,
+    #[cfg(feature = "multicast")]
+    multicast: multicast::Memberships,
+}
+
+impl VLanEthernet<'_> {
+    /// Records a frame that we've received (for UDP socket `socket`, if
+    /// any), before smoltcp sees it
+    fn saw_rx(&self, frame: &[u8], socket: Option<usize>) {
+        self.stats.count_rx(frame, socket);
+        #[cfg(feature = "pcap")]
+        self.capture
+            .record(self.index(), pcap::Direction::Rx, frame);
+    }
+
+    /// Records a frame that smoltcp has handed us to send
+    fn saw_tx(&self, frame: &[u8]) {
+        self.stats.count_tx(frame.len());
+        #[cfg(feature = "pcap")]
+        self.capture
+            .record(self.index(), pcap::Direction::Tx, frame);
+    }
+
+    /// Returns this VLAN's index in `VLAN_RANGE`
+    fn index(&self) -> usize {
+        usize::from(self.vid - VLAN_RANGE.start)
+    }
 }
 
 impl<'a, 'b> smoltcp::phy::Device<'a> for VLanEthernet<'b> {
@@ -42,17 +79,14 @@ impl<'a, 'b> smoltcp::phy::Device<'a> for VLanEthernet<'b> {
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
         if self.eth.vlan_can_recv(self.vid, VLAN_RANGE) && self.eth.can_send() {
             self.mac_rx.set(true);
-            Some((
-                VLanRxToken(self.eth, self.vid),
-                VLanTxToken(self.eth, self.vid),
-            ))
+            Some((VLanRxToken(self), VLanTxToken(self)))
         } else {
             None
         }
     }
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
         if self.eth.can_send() {
-            Some(VLanTxToken(self.eth, self.vid))
+            Some(VLanTxToken(self))
         } else {
             None
         }
@@ -80,25 +114,78 @@ impl DeviceExt for VLanEthernet<'_> {
             vid: self.vid,
         }
     }
+
+    #[cfg(feature = "tcp")]
+    fn make_tcp_meta(
+        &self,
+        port: u16,
+        addr: task_net_api::Address,
+    ) -> TcpMetadata {
+        TcpMetadata {
+            port,
+            addr,
+            vid: self.vid,
+        }
+    }
+
+    #[cfg(feature = "tcp")]
+    fn matches_tcp_meta(&self, meta: &TcpMetadata) -> bool {
+        meta.vid == self.vid
+    }
+
+    fn stats(&self) -> &DeviceStats {
+        &self.stats
+    }
+
+    fn filters(&self) -> &filter::SocketFilters {
+        &self.filters
+    }
+
+    #[cfg(feature = "pcap")]
+    fn capture(&self) -> &pcap::Capture {
+        self.capture
+    }
+
+    #[cfg(feature = "multicast")]
+    fn multicast(&self) -> &multicast::Memberships {
+        &self.multicast
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
-pub struct VLanRxToken<'a>(&'a eth::Ethernet, u16);
+pub struct VLanRxToken<'a>(&'a VLanEthernet<'a>);
 impl<'a> smoltcp::phy::RxToken for VLanRxToken<'a> {
     fn consume<R, F>(
         self,
-        _timestamp: smoltcp::time::Instant,
+        timestamp: smoltcp::time::Instant,
         f: F,
     ) -> smoltcp::Result<R>
     where
         F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
     {
-        self.0.vlan_recv(self.1, f)
+        self.0.eth.vlan_recv(self.0.vid, |frame| {
+            // Frames for groups we haven't joined are dropped before
+            // they're counted, since we'd never have seen them if the MAC
+            // were filtering.
+            #[cfg(feature = "multicast")]
+            if !self.0.multicast.accepts(frame) {
+                return Err(smoltcp::Error::Dropped);
+            }
+            let udp = udp_destination(frame, self.0.index());
+            self.0.saw_rx(frame, udp.map(|(i, _)| i));
+            if let Some((i, src)) = udp {
+                let now = timestamp.total_millis() as u64;
+                if !self.0.filters.accepts(i, src, now) {
+                    return Err(smoltcp::Error::Dropped);
+                }
+            }
+            f(frame)
+        })
     }
 }
 
-pub struct VLanTxToken<'a>(&'a eth::Ethernet, u16);
+pub struct VLanTxToken<'a>(&'a VLanEthernet<'a>);
 impl<'a> smoltcp::phy::TxToken for VLanTxToken<'a> {
     fn consume<R, F>(
         self,
@@ -110,7 +197,14 @@ impl<'a> smoltcp::phy::TxToken for VLanTxToken<'a> {
         F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
     {
         self.0
-            .vlan_try_send(len, self.1, f)
+            .eth
+            .vlan_try_send(len, self.0.vid, |frame| {
+                let r = f(&mut *frame);
+                if r.is_ok() {
+                    self.0.saw_tx(frame);
+                }
+                r
+            })
             .expect("TX token existed without descriptor available")
     }
 }
@@ -122,14 +216,18 @@ pub type ServerImpl<'a, B> = GenServerImpl<'a, B, VLanEthernet<'a>, VLAN_COUNT>;
 pub fn new<B>(
     eth: &eth::Ethernet,
     mac: MacAddressBlock,
+    ipv6_static: [Ipv6StaticAddress; VLAN_COUNT],
     bsp: B,
 ) -> ServerImpl<'_, B>
 where
     B: bsp_support::Bsp,
 {
+    #[cfg(feature = "pcap")]
+    let capture = pcap::claim_capture_statics();
     ServerImpl::new(
         eth,
         mac,
+        ipv6_static,
         bsp,
         claim_server_storage_statics(),
         generated::construct_sockets(),
@@ -137,6 +235,12 @@ where
             eth,
             vid: generated::VLAN_RANGE.start + i as u16,
             mac_rx: Cell::new(false),
+            stats: DeviceStats::default(),
+            filters: Default::default(),
+            #[cfg(feature = "pcap")]
+            capture,
+            #[cfg(feature = "multicast")]
+            multicast: Default::default(),
         },
     )
 }
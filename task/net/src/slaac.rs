@@ -0,0 +1,166 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Stateless address autoconfiguration (RFC 4862).
+//!
+//! smoltcp handles neighbor discovery itself, but ignores router
+//! advertisements. We get a copy of every incoming ICMPv6 packet through a raw
+//! socket on each interface, and pick the router advertisements out of that;
+//! the server then uses them to configure one global address (from the first
+//! suitable prefix) and a default router per interface.
+//!
+//! We don't send router solicitations, so an interface picks up its address
+//! when the router next sends an unsolicited advertisement.
+
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::{RawPacketMetadata, RawSocket, RawSocketBuffer};
+use smoltcp::time::Duration;
+use smoltcp::wire::{
+    Icmpv6Packet, Icmpv6Repr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr,
+    Ipv6Packet, NdiscPrefixInfoFlags, NdiscRepr,
+};
+
+/// Number of ICMPv6 packets buffered between polls
+const RA_PACKETS: usize = 2;
+
+/// Bytes of ICMPv6 packets buffered between polls; this is enough for a
+/// typical router advertisement, and larger packets are dropped.
+const RA_BYTES: usize = 512;
+
+/// Lifetime (in milliseconds) below which we won't shorten an address's
+/// remaining lifetime, per RFC 4862 section 5.5.3 (e)
+const TWO_HOURS: u64 = 2 * 60 * 60 * 1000;
+
+/// Buffers for the raw socket used to receive router advertisements
+pub struct RaStorage {
+    rx_meta: [RawPacketMetadata; RA_PACKETS],
+    rx: [u8; RA_BYTES],
+}
+
+impl Default for RaStorage {
+    fn default() -> Self {
+        Self {
+            rx_meta: [RawPacketMetadata::EMPTY; RA_PACKETS],
+            rx: [0; RA_BYTES],
+        }
+    }
+}
+
+/// Builds the raw socket used to receive router advertisements. It never
+/// sends, so it has no transmit buffer.
+pub(crate) fn socket(storage: &'static mut RaStorage) -> RawSocket<'static> {
+    RawSocket::new(
+        IpVersion::Ipv6,
+        IpProtocol::Icmpv6,
+        RawSocketBuffer::new(&mut storage.rx_meta[..], &mut storage.rx[..]),
+        RawSocketBuffer::new(&mut [][..], &mut [][..]),
+    )
+}
+
+/// The parts of a router advertisement that we use
+pub(crate) struct RouterAdvert {
+    pub router: Ipv6Address,
+    /// How long the sender may be used as a default router, in milliseconds;
+    /// zero means that it's not a default router.
+    pub router_lifetime: u64,
+    /// Prefix for address configuration, if the advertisement has a usable
+    /// one
+    pub prefix: Option<Prefix>,
+}
+
+pub(crate) struct Prefix {
+    pub prefix: Ipv6Address,
+    /// Valid lifetime in milliseconds, or `None` if it's infinite
+    pub valid_lifetime: Option<u64>,
+}
+
+impl Prefix {
+    /// Builds our address in this prefix, using the interface identifier
+    /// from our link-local address.
+    pub fn address(&self, link_local: Ipv6Address) -> Ipv6Cidr {
+        let mut addr = self.prefix.0;
+        addr[8..].copy_from_slice(&link_local.0[8..]);
+        Ipv6Cidr::new(Ipv6Address(addr), 64)
+    }
+}
+
+/// Converts an advertised lifetime into milliseconds, or `None` if it's the
+/// special "infinity" value.
+fn lifetime(d: Duration) -> Option<u64> {
+    (d != Duration::from_secs(0xffff_ffff)).then_some(d.total_millis())
+}
+
+/// Checks that `packet` (a raw IPv6 packet) is a valid router advertisement,
+/// and extracts the parts we care about.
+pub(crate) fn parse(packet: &[u8]) -> Option<RouterAdvert> {
+    let ip = Ipv6Packet::new_checked(packet).ok()?;
+
+    // RFC 4861 section 6.1.2: advertisements must come from a link-local
+    // address, and must not have been forwarded.
+    let router = ip.src_addr();
+    if ip.hop_limit() != 255 || !router.is_link_local() {
+        return None;
+    }
+
+    let icmp = Icmpv6Packet::new_checked(ip.payload()).ok()?;
+    let repr = Icmpv6Repr::parse(
+        &router.into(),
+        &ip.dst_addr().into(),
+        &icmp,
+        &ChecksumCapabilities::default(),
+    )
+    .ok()?;
+    let Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+        router_lifetime,
+        prefix_info,
+        ..
+    }) = repr else {
+        return None;
+    };
+
+    // RFC 4862 section 5.5.3: only use prefixes marked for autonomous
+    // configuration, which aren't link-local, and whose lifetimes make sense.
+    // We only generate 64-bit interface identifiers, so the prefix must also
+    // be a /64.
+    let prefix = prefix_info.filter(|p| {
+        p.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+            && !p.prefix.is_link_local()
+            && p.prefix_len == 64
+            && p.preferred_lifetime <= p.valid_lifetime
+    });
+
+    Some(RouterAdvert {
+        router,
+        router_lifetime: router_lifetime.total_millis(),
+        prefix: prefix.map(|p| Prefix {
+            prefix: p.prefix,
+            valid_lifetime: lifetime(p.valid_lifetime),
+        }),
+    })
+}
+
+/// Works out when an address should expire after an advertisement for its
+/// prefix, given its current expiry time (`None` for never). This follows
+/// RFC 4862 section 5.5.3 (e), which stops unauthenticated advertisements
+/// from cutting an address's remaining lifetime below two hours.
+pub(crate) fn expiry(
+    now: u64,
+    expires: Option<u64>,
+    valid_lifetime: Option<u64>,
+) -> Option<u64> {
+    let remaining = expires.map(|e| e.saturating_sub(now));
+    let accept = match (valid_lifetime, remaining) {
+        (None, _) => true,
+        (Some(v), _) if v > TWO_HOURS => true,
+        (Some(v), Some(r)) => v > r,
+        (Some(_), None) => false,
+    };
+    if accept {
+        valid_lifetime.map(|v| now + v)
+    } else if remaining.map_or(false, |r| r <= TWO_HOURS) {
+        expires
+    } else {
+        Some(now + TWO_HOURS)
+    }
+}
@@ -28,6 +28,8 @@ struct Sensor {
     device: String,
     description: String,
     sensors: BTreeMap<String, usize>,
+    #[serde(default)]
+    thresholds: BTreeMap<String, build_i2c::SensorThresholds>,
 }
 
 fn main() -> Result<()> {
@@ -37,10 +39,14 @@ fn main() -> Result<()> {
     build_i2c::codegen(build_i2c::Disposition::Sensors)?;
 
     let config: GlobalConfig = build_util::config()?;
+    let (descriptors, kinds) = descriptors(&config)?;
 
     let (count, text) = if let Some(config_sensor) = &config.sensor {
-        let sensor_count: usize =
-            config_sensor.devices.iter().map(|d| d.sensors.len()).sum();
+        let sensor_count: usize = config_sensor
+            .devices
+            .iter()
+            .map(|d| d.sensors.values().sum::<usize>())
+            .sum();
 
         let mut by_device: BTreeMap<String, Vec<usize>> = BTreeMap::new();
         let mut names = BTreeSet::new();
@@ -126,8 +132,90 @@ fn main() -> Result<()> {
 
     // Here's what we actually care about:
     pub const NUM_SENSORS: usize = NUM_I2C_SENSORS + NUM_OTHER_SENSORS;
+
+    #[allow(dead_code)]
+    pub static SENSOR_DESCRIPTORS: [crate::SensorDescriptor; NUM_SENSORS] = [
+{descriptors}    ];
+
+    // The kinds from `SENSOR_DESCRIPTORS`, usable in const contexts
+    #[allow(dead_code)]
+    pub const SENSOR_KINDS: [crate::SensorKind; NUM_SENSORS] = [
+{kinds}    ];
 }}"#
     )
     .unwrap();
     Ok(())
 }
+
+/// Generates the bodies of the `SENSOR_DESCRIPTORS` and `SENSOR_KINDS`
+/// tables, in `SensorId` order: I2C sensors first, followed by the other
+/// sensors from `config.sensor`.
+fn descriptors(config: &GlobalConfig) -> Result<(String, String)> {
+    let mut out = String::new();
+    let mut kinds = String::new();
+
+    let mut emit = |name: Option<&str>,
+                    kind: build_i2c::Sensor,
+                    device: &str,
+                    refdes: Option<&str>,
+                    t: &build_i2c::SensorThresholds| {
+        let kind = format!("crate::SensorKind::{kind:?}");
+        writeln!(&mut kinds, "        {kind},").unwrap();
+        writeln!(
+            &mut out,
+            "        crate::SensorDescriptor {{
+            name: {name:?},
+            kind: {kind},
+            unit: {kind}.unit(),
+            device: {device:?},
+            refdes: {refdes:?},
+            warning: {:?},
+            critical: {:?},
+            hysteresis: {:?},
+        }},",
+            t.warning, t.critical, t.hysteresis,
+        )
+        .unwrap();
+    };
+
+    for s in build_i2c::sensor_descriptions()? {
+        emit(
+            s.name.as_deref(),
+            s.kind,
+            &s.device,
+            s.refdes.as_deref(),
+            &s.thresholds,
+        );
+    }
+
+    if let Some(config_sensor) = &config.sensor {
+        for d in &config_sensor.devices {
+            for (sensor_type, t) in &d.thresholds {
+                if !d.sensors.contains_key(sensor_type) {
+                    bail!(
+                        "{} has {} thresholds but no such sensors",
+                        d.name,
+                        sensor_type
+                    );
+                }
+                t.check(&format!("{} {}", d.name, sensor_type))?;
+            }
+
+            for (sensor_type, &sensor_count) in d.sensors.iter() {
+                let kind = sensor_type.parse()?;
+                let t = d.thresholds.get(sensor_type).copied();
+                for _ in 0..sensor_count {
+                    emit(
+                        Some(&d.name),
+                        kind,
+                        &d.device,
+                        None,
+                        &t.unwrap_or_default(),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok((out, kinds))
+}
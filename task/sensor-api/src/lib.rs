@@ -8,9 +8,12 @@
 
 use derive_idol_err::IdolError;
 use drv_i2c_api::ResponseCode;
+use serde::{Deserialize, Serialize};
 use userlib::*;
 
-#[derive(zerocopy::AsBytes, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(
+    zerocopy::AsBytes, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize,
+)]
 #[repr(C)]
 pub struct SensorId(pub usize);
 
@@ -67,6 +70,7 @@ pub enum SensorError {
     DeviceUnavailable = 5,
     DeviceTimeout = 6,
     DeviceOff = 7,
+    NoHistory = 8,
 }
 
 impl From<NoData> for SensorError {
@@ -81,5 +85,191 @@ impl From<NoData> for SensorError {
     }
 }
 
+/// The kind of quantity that a sensor measures
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, FromPrimitive, Serialize, Deserialize,
+)]
+#[repr(u8)]
+pub enum SensorKind {
+    Temperature = 0,
+    Power = 1,
+    Current = 2,
+    Voltage = 3,
+    InputCurrent = 4,
+    InputVoltage = 5,
+    Speed = 6,
+}
+
+/// The unit in which a sensor's readings (and thresholds) are expressed
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, FromPrimitive, Serialize, Deserialize,
+)]
+#[repr(u8)]
+pub enum Unit {
+    Celsius = 0,
+    Watts = 1,
+    Amps = 2,
+    Volts = 3,
+    Rpm = 4,
+}
+
+impl SensorKind {
+    pub const fn unit(self) -> Unit {
+        match self {
+            SensorKind::Temperature => Unit::Celsius,
+            SensorKind::Power => Unit::Watts,
+            SensorKind::Current | SensorKind::InputCurrent => Unit::Amps,
+            SensorKind::Voltage | SensorKind::InputVoltage => Unit::Volts,
+            SensorKind::Speed => Unit::Rpm,
+        }
+    }
+}
+
+/// Static description of a sensor, generated from the app.toml
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorDescriptor {
+    /// Sensor name (e.g. rail or location), if any
+    pub name: Option<&'static str>,
+    pub kind: SensorKind,
+    pub unit: Unit,
+    /// Part name of the owning device
+    pub device: &'static str,
+    pub refdes: Option<&'static str>,
+    pub warning: Option<f32>,
+    pub critical: Option<f32>,
+    /// Distance below a threshold that a reading must fall before the
+    /// corresponding alarm is cleared
+    pub hysteresis: f32,
+}
+
+/// Alarm state of a sensor, as evaluated against its thresholds
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    FromPrimitive,
+    Serialize,
+    Deserialize,
+)]
+#[repr(u8)]
+pub enum AlarmState {
+    Normal = 0,
+    Warning = 1,
+    Critical = 2,
+}
+
+/// Per-sensor bookkeeping returned by `Sensor::stats`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SensorStats {
+    /// Timestamp (in kernel ticks) of the last reading or error, or `None`
+    /// if nothing has been posted for this sensor
+    pub last_update: Option<u64>,
+    /// Minimum and maximum readings since boot
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    /// Number of times that a `NoData` has been posted for this sensor
+    pub errors: u32,
+    pub alarm: AlarmState,
+}
+
+/// A single sensor's entry in the packed array written by
+/// `Sensor::get_range`.  Use [`SensorReading::reading`] to decode it.
+#[derive(
+    Copy, Clone, Debug, Default, zerocopy::AsBytes, zerocopy::FromBytes,
+)]
+#[repr(C)]
+pub struct SensorReading {
+    /// Timestamp (in kernel ticks) of the last reading or error
+    pub timestamp: u64,
+    /// Value of the sensor task's generation counter when this sensor was
+    /// last updated
+    pub generation: u64,
+    pub value: f32,
+    /// One of the `SensorReading::STATUS_*` values
+    pub status: u8,
+    /// `NoData` discriminant, if `status` is `STATUS_NODATA`
+    pub nodata: u8,
+    _pad: [u8; 2],
+}
+
+impl SensorReading {
+    pub const STATUS_ABSENT: u8 = 0;
+    pub const STATUS_VALUE: u8 = 1;
+    pub const STATUS_NODATA: u8 = 2;
+
+    pub fn new(reading: Reading, timestamp: u64, generation: u64) -> Self {
+        let (status, value, nodata) = match reading {
+            Reading::Absent => (Self::STATUS_ABSENT, 0.0, 0),
+            Reading::Value(v) => (Self::STATUS_VALUE, v, 0),
+            Reading::NoData(n) => (Self::STATUS_NODATA, 0.0, n as u8),
+        };
+        Self {
+            timestamp,
+            generation,
+            value,
+            status,
+            nodata,
+            _pad: [0; 2],
+        }
+    }
+
+    /// Decodes this entry, returning `None` if it is malformed
+    pub fn reading(&self) -> Option<Reading> {
+        match self.status {
+            Self::STATUS_ABSENT => Some(Reading::Absent),
+            Self::STATUS_VALUE => Some(Reading::Value(self.value)),
+            Self::STATUS_NODATA => {
+                NoData::from_u8(self.nodata).map(Reading::NoData)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returned by `Sensor::get_range`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeInfo {
+    /// Number of `SensorReading` entries written to the lease
+    pub count: u32,
+    /// Current value of the sensor task's generation counter.  Entries whose
+    /// `generation` is greater than that from a previous call have changed
+    /// since that call.  (The counter is 64 bits wide so that it never wraps.)
+    pub generation: u64,
+}
+
+/// Returned by `Sensor::history`, which writes up to `count` samples (as
+/// little-endian `f32`, oldest first) into the caller's lease.  Samples taken
+/// while the sensor had no reading are NaN.
+///
+/// History is read through hiffy or udprpc; it isn't forwarded to MGS by
+/// `control-plane-agent`, since the MGS protocol has no message for it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryInfo {
+    pub count: u32,
+    /// Interval between samples, in milliseconds
+    pub interval_ms: u32,
+    /// Timestamp (in kernel ticks) of the newest sample
+    pub newest: u64,
+}
+
+/// Sensor description returned by `Sensor::describe`.  The strings from the
+/// [`SensorDescriptor`] are written back-to-back into the caller's lease,
+/// with their lengths recorded here; a length of zero indicates that the
+/// string is absent.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SensorInfo {
+    pub kind: SensorKind,
+    pub unit: Unit,
+    pub warning: Option<f32>,
+    pub critical: Option<f32>,
+    pub name_len: u8,
+    pub device_len: u8,
+    pub refdes_len: u8,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
 include!(concat!(env!("OUT_DIR"), "/sensor_config.rs"));
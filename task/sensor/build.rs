@@ -2,12 +2,90 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::io::Write;
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TaskConfig {
+    /// Tasks to notify when a sensor enters or leaves an alarm state
+    #[serde(default)]
+    alarm_subscribers: Vec<Subscriber>,
+
+    /// Per-sensor history recording, if any
+    history: Option<History>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct History {
+    /// Number of samples retained per sensor
+    depth: usize,
+
+    /// Sampling interval, in milliseconds
+    interval_ms: u64,
+
+    /// Kinds of sensors to record
+    kinds: Vec<build_i2c::Sensor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Subscriber {
+    name: String,
+    notification: u32,
+}
+
+fn main() -> Result<()> {
     build_util::expose_target_board();
     idol::server::build_server_support(
         "../../idl/sensor.idol",
         "server_stub.rs",
         idol::server::ServerStyle::InOrder,
+    )
+    .map_err(|e| anyhow!("idol error: {e}"))?;
+
+    let config: TaskConfig =
+        build_util::task_maybe_config()?.unwrap_or_default();
+    let task_ids = build_util::task_ids();
+
+    let out_dir = build_util::out_dir();
+    let mut file = std::fs::File::create(out_dir.join("task_config.rs"))?;
+    writeln!(
+        file,
+        "pub(crate) const ALARM_SUBSCRIBERS: [(userlib::TaskId, u32); {}] = [",
+        config.alarm_subscribers.len()
     )?;
+    for s in &config.alarm_subscribers {
+        let index = task_ids
+            .get(&s.name)
+            .ok_or_else(|| anyhow!("unknown alarm subscriber `{}`", s.name))?;
+        writeln!(
+            file,
+            "    (userlib::TaskId::for_index_and_gen({index}, \
+                userlib::Generation::ZERO), {:#x}),",
+            s.notification
+        )?;
+    }
+    writeln!(file, "];")?;
+
+    let (depth, interval, kinds) = match &config.history {
+        Some(h) => {
+            if h.depth == 0 || h.interval_ms == 0 {
+                bail!("history depth and interval-ms must be non-zero");
+            }
+            (h.depth, h.interval_ms, h.kinds.as_slice())
+        }
+        None => (0, 1000, [].as_slice()),
+    };
+    writeln!(file, "pub(crate) const HISTORY_DEPTH: usize = {depth};")?;
+    writeln!(file, "pub(crate) const HISTORY_INTERVAL: u64 = {interval};")?;
+    writeln!(file, "pub(crate) const HISTORY_KINDS: &[SensorKind] = &[")?;
+    for k in kinds {
+        writeln!(file, "    SensorKind::{k:?},")?;
+    }
+    writeln!(file, "];")?;
+
     Ok(())
 }
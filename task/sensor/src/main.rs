@@ -3,23 +3,163 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Sensor management
+//!
+//! Sensor history is recorded for the kinds of sensors listed in the task
+//! config, e.g.:
+//!
+//! ```toml
+//! [tasks.sensor.config]
+//! history = { depth = 60, interval-ms = 1000, kinds = ["temperature"] }
+//! ```
+//!
+//! This costs `depth * 4` bytes of RAM per recorded sensor.
 
 #![no_std]
 #![no_main]
 
-use idol_runtime::{NotificationHandler, RequestError};
-use task_sensor_api::{NoData, Reading, SensorError, SensorId};
+use idol_runtime::{ClientError, Leased, NotificationHandler, RequestError, W};
+use mutable_statics::mutable_statics;
+use task_sensor_api::{
+    AlarmState, HistoryInfo, NoData, RangeInfo, Reading, SensorDescriptor,
+    SensorError, SensorId, SensorInfo, SensorKind, SensorReading, SensorStats,
+};
 use userlib::*;
+use zerocopy::AsBytes;
 
-use task_sensor_api::config::NUM_SENSORS;
+use task_sensor_api::config::{NUM_SENSORS, SENSOR_DESCRIPTORS, SENSOR_KINDS};
+
+include!(concat!(env!("OUT_DIR"), "/task_config.rs"));
+
+const NO_HISTORY: u16 = u16::MAX;
+
+const fn records_history(kind: SensorKind) -> bool {
+    let mut i = 0;
+    while i < HISTORY_KINDS.len() {
+        if HISTORY_KINDS[i] as u8 == kind as u8 {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Index of each sensor's history ring, or `NO_HISTORY`
+const HISTORY_SLOTS: [u16; NUM_SENSORS] = {
+    let mut slots = [NO_HISTORY; NUM_SENSORS];
+    let mut n = 0;
+    let mut i = 0;
+    while i < NUM_SENSORS {
+        if records_history(SENSOR_KINDS[i]) {
+            slots[i] = n;
+            n += 1;
+        }
+        i += 1;
+    }
+    slots
+};
+
+const NUM_HISTORY: usize = {
+    let mut n = 0;
+    let mut i = 0;
+    while i < NUM_SENSORS {
+        if HISTORY_SLOTS[i] != NO_HISTORY {
+            n += 1;
+        }
+        i += 1;
+    }
+    n
+};
+
+/// Downsampled history of the sensors whose kinds are listed in the task
+/// config.  All rings are sampled together, so they share a write position.
+struct History {
+    samples: &'static mut [[f32; HISTORY_DEPTH]; NUM_HISTORY],
+    next: usize,
+    count: usize,
+    newest: u64,
+}
+
+impl History {
+    fn record(&mut self, data: &[SensorState; NUM_SENSORS], now: u64) {
+        if NUM_HISTORY == 0 {
+            return;
+        }
+
+        for (s, &slot) in data.iter().zip(HISTORY_SLOTS.iter()) {
+            if slot != NO_HISTORY {
+                self.samples[slot as usize][self.next] = match s.reading {
+                    Reading::Value(v) => v,
+                    _ => f32::NAN,
+                };
+            }
+        }
+
+        self.next += 1;
+        if self.next == HISTORY_DEPTH {
+            self.next = 0;
+        }
+        self.count = (self.count + 1).min(HISTORY_DEPTH);
+        self.newest = now;
+    }
+}
+
+#[derive(Copy, Clone)]
+struct SensorState {
+    reading: Reading,
+    last_update: Option<u64>,
+    min: f32,
+    max: f32,
+    errors: u32,
+    alarm: AlarmState,
+    generation: u64,
+}
+
+impl SensorState {
+    const fn new() -> Self {
+        Self {
+            reading: Reading::Absent,
+            last_update: None,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            errors: 0,
+            alarm: AlarmState::Normal,
+            generation: 0,
+        }
+    }
+
+    fn stats(&self) -> SensorStats {
+        let seen = self.min <= self.max;
+        SensorStats {
+            last_update: self.last_update,
+            min: if seen { Some(self.min) } else { None },
+            max: if seen { Some(self.max) } else { None },
+            errors: self.errors,
+            alarm: self.alarm,
+        }
+    }
+}
 
 struct ServerImpl {
-    data: [Reading; NUM_SENSORS],
+    data: &'static mut [SensorState; NUM_SENSORS],
     deadline: u64,
+    /// Incremented on every update to any sensor
+    generation: u64,
+    history: History,
+}
+
+impl ServerImpl {
+    fn update(&mut self, index: usize, reading: Reading) -> &mut SensorState {
+        self.generation += 1;
+        let s = &mut self.data[index];
+        s.reading = reading;
+        s.last_update = Some(sys_get_timer().now);
+        s.generation = self.generation;
+        s
+    }
 }
 
 const TIMER_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: u64 = HISTORY_INTERVAL;
 
 impl idl::InOrderSensorImpl for ServerImpl {
     fn get(
@@ -30,7 +170,7 @@ impl idl::InOrderSensorImpl for ServerImpl {
         let index = id.0;
 
         if index < NUM_SENSORS {
-            match self.data[index] {
+            match self.data[index].reading {
                 Reading::Absent => Err(SensorError::NoReading.into()),
                 Reading::NoData(nodata) => {
                     let err: SensorError = nodata.into();
@@ -52,7 +192,16 @@ impl idl::InOrderSensorImpl for ServerImpl {
         let index = id.0;
 
         if index < NUM_SENSORS {
-            self.data[index] = Reading::Value(value);
+            let s = self.update(index, Reading::Value(value));
+            s.min = s.min.min(value);
+            s.max = s.max.max(value);
+
+            let alarm =
+                evaluate_alarm(s.alarm, value, &SENSOR_DESCRIPTORS[index]);
+            if alarm != s.alarm {
+                s.alarm = alarm;
+                notify_subscribers();
+            }
             Ok(())
         } else {
             Err(SensorError::InvalidSensor.into())
@@ -68,12 +217,171 @@ impl idl::InOrderSensorImpl for ServerImpl {
         let index = id.0;
 
         if index < NUM_SENSORS {
-            self.data[index] = Reading::NoData(nodata);
+            let s = self.update(index, Reading::NoData(nodata));
+            s.errors = s.errors.wrapping_add(1);
             Ok(())
         } else {
             Err(SensorError::InvalidSensor.into())
         }
     }
+
+    fn get_range(
+        &mut self,
+        _: &RecvMessage,
+        start: SensorId,
+        readings: Leased<W, [u8]>,
+    ) -> Result<RangeInfo, RequestError<SensorError>> {
+        const SIZE: usize = core::mem::size_of::<SensorReading>();
+
+        let sensors =
+            self.data.get(start.0..).ok_or(SensorError::InvalidSensor)?;
+
+        let mut count = 0;
+        for (s, offset) in sensors.iter().zip((0..readings.len()).step_by(SIZE))
+        {
+            if offset + SIZE > readings.len() {
+                break;
+            }
+            let entry = SensorReading::new(
+                s.reading,
+                s.last_update.unwrap_or(0),
+                s.generation,
+            );
+            readings
+                .write_range(offset..offset + SIZE, entry.as_bytes())
+                .map_err(|_| RequestError::went_away())?;
+            count += 1;
+        }
+
+        Ok(RangeInfo {
+            count,
+            generation: self.generation,
+        })
+    }
+
+    fn history(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        samples: Leased<W, [u8]>,
+    ) -> Result<HistoryInfo, RequestError<SensorError>> {
+        const SIZE: usize = core::mem::size_of::<f32>();
+
+        let slot =
+            *HISTORY_SLOTS.get(id.0).ok_or(SensorError::InvalidSensor)?;
+        if slot == NO_HISTORY {
+            return Err(SensorError::NoHistory.into());
+        }
+
+        // Return the newest samples that fit in the lease, oldest first
+        let h = &self.history;
+        let ring = &h.samples[slot as usize];
+        let count = h.count.min(samples.len() / SIZE);
+        let start = if h.next >= count {
+            h.next - count
+        } else {
+            h.next + HISTORY_DEPTH - count
+        };
+
+        for i in 0..count {
+            let j = start + i;
+            let v = ring[if j >= HISTORY_DEPTH {
+                j - HISTORY_DEPTH
+            } else {
+                j
+            }];
+            samples
+                .write_range(i * SIZE..(i + 1) * SIZE, &v.to_le_bytes())
+                .map_err(|_| RequestError::went_away())?;
+        }
+
+        Ok(HistoryInfo {
+            count: count as u32,
+            interval_ms: HISTORY_INTERVAL as u32,
+            newest: h.newest,
+        })
+    }
+
+    fn stats(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<SensorStats, RequestError<SensorError>> {
+        let s = self.data.get(id.0).ok_or(SensorError::InvalidSensor)?;
+        Ok(s.stats())
+    }
+
+    fn describe(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        strings: Leased<W, [u8]>,
+    ) -> Result<SensorInfo, RequestError<SensorError>> {
+        let d = SENSOR_DESCRIPTORS
+            .get(id.0)
+            .ok_or(SensorError::InvalidSensor)?;
+
+        let mut offset = 0;
+        let mut write =
+            |s: Option<&str>| -> Result<u8, RequestError<SensorError>> {
+                let s = s.unwrap_or("").as_bytes();
+                let len = u8::try_from(s.len())
+                    .map_err(|_| RequestError::Fail(ClientError::BadLease))?;
+                if offset + s.len() > strings.len() {
+                    return Err(RequestError::Fail(ClientError::BadLease));
+                }
+                strings
+                    .write_range(offset..offset + s.len(), s)
+                    .map_err(|_| RequestError::went_away())?;
+                offset += s.len();
+                Ok(len)
+            };
+
+        let name_len = write(d.name)?;
+        let device_len = write(Some(d.device))?;
+        let refdes_len = write(d.refdes)?;
+
+        Ok(SensorInfo {
+            kind: d.kind,
+            unit: d.unit,
+            warning: d.warning,
+            critical: d.critical,
+            name_len,
+            device_len,
+            refdes_len,
+        })
+    }
+}
+
+/// Returns the alarm state of a sensor given a new reading and its previous
+/// state.  An alarm is raised when the reading reaches a threshold, but is
+/// only cleared once the reading falls below the threshold by more than the
+/// sensor's hysteresis.
+fn evaluate_alarm(
+    prev: AlarmState,
+    value: f32,
+    d: &SensorDescriptor,
+) -> AlarmState {
+    let exceeds = |limit: Option<f32>, level: AlarmState| match limit {
+        Some(limit) => {
+            value >= limit || (prev >= level && value > limit - d.hysteresis)
+        }
+        None => false,
+    };
+
+    if exceeds(d.critical, AlarmState::Critical) {
+        AlarmState::Critical
+    } else if exceeds(d.warning, AlarmState::Warning) {
+        AlarmState::Warning
+    } else {
+        AlarmState::Normal
+    }
+}
+
+fn notify_subscribers() {
+    for &(task_id, notification) in &ALARM_SUBSCRIBERS {
+        sys_post(sys_refresh_task_id(task_id), notification);
+    }
 }
 
 impl NotificationHandler for ServerImpl {
@@ -82,6 +390,7 @@ impl NotificationHandler for ServerImpl {
     }
 
     fn handle_notification(&mut self, _bits: u32) {
+        self.history.record(self.data, sys_get_timer().now);
         self.deadline += TIMER_INTERVAL;
         sys_set_timer(Some(self.deadline), TIMER_MASK);
     }
@@ -96,9 +405,23 @@ fn main() -> ! {
     //
     sys_set_timer(Some(deadline), TIMER_MASK);
 
+    let (data, samples) = mutable_statics! {
+        static mut SENSOR_STATE: [SensorState; NUM_SENSORS] =
+            [SensorState::new; _];
+        static mut HISTORY: [[f32; HISTORY_DEPTH]; NUM_HISTORY] =
+            [|| [f32::NAN; HISTORY_DEPTH]; _];
+    };
+
     let mut server = ServerImpl {
-        data: [Reading::Absent; NUM_SENSORS],
+        data,
         deadline,
+        generation: 0,
+        history: History {
+            samples,
+            next: 0,
+            count: 0,
+            newest: 0,
+        },
     };
 
     let mut buffer = [0; idl::INCOMING_SIZE];
@@ -109,7 +432,10 @@ fn main() -> ! {
 }
 
 mod idl {
-    use super::{NoData, SensorError, SensorId};
+    use super::{
+        HistoryInfo, NoData, RangeInfo, SensorError, SensorId, SensorInfo,
+        SensorStats,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -2,7 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() {
+use build_stm32pins::{Chip, PinConfig};
+use serde::Deserialize;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    #[serde(default)]
+    pins: Vec<PinConfig>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
 
     let disposition = build_i2c::Disposition::Target;
@@ -11,4 +21,10 @@ fn main() {
         println!("code generation failed: {}", e);
         std::process::exit(1);
     }
+
+    let task_config =
+        build_util::task_maybe_config::<TaskConfig>()?.unwrap_or_default();
+    build_stm32pins::codegen(Chip::from_features()?, task_config.pins)?;
+
+    Ok(())
 }
@@ -25,8 +25,8 @@ use core::cell::Cell;
 use core::cell::RefCell;
 use drv_gimlet_state::PowerState;
 use drv_i2c_api::{Controller, I2cDevice, Mux, Segment};
-use drv_stm32xx_i2c::{I2cControl, I2cPin};
-use drv_stm32xx_sys_api::{OutputType, Pull, Speed, Sys};
+use drv_stm32xx_i2c::I2cControl;
+use drv_stm32xx_sys_api::Sys;
 use ringbuf::{ringbuf, ringbuf_entry};
 use task_jefe_api::Jefe;
 use userlib::{
@@ -41,21 +41,7 @@ const JEFE_STATE_NOTIFICATION_BIT: u8 = 8;
 
 mod ltc4306;
 
-fn configure_pins(pins: &[I2cPin]) {
-    let sys = SYS.get_task_id();
-    let sys = Sys::from(sys);
-
-    for pin in pins {
-        sys.gpio_configure_alternate(
-            pin.gpio_pins,
-            OutputType::OpenDrain,
-            Speed::High,
-            Pull::None,
-            pin.function,
-        )
-        .unwrap();
-    }
-}
+include!(concat!(env!("OUT_DIR"), "/pin_config.rs"));
 
 //
 // This is an excellent candidate to put into a non-DTCM memory region
@@ -202,7 +188,6 @@ fn read_spd_data(
 #[export_name = "main"]
 fn main() -> ! {
     let controller = &i2c_config::controllers()[0];
-    let pins = i2c_config::pins();
     use i2c_config::ports::*;
 
     cfg_if::cfg_if! {
@@ -280,8 +265,8 @@ fn main() -> ! {
 
     controller.enable(&sys);
 
-    // Configure our pins
-    configure_pins(&pins);
+    // Configure our pins, which are declared in `app.toml`
+    setup_pins(&sys).unwrap();
 
     ringbuf_entry!(Trace::Ready);
 
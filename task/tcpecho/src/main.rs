@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Echoes data back over TCP, one connection at a time.
+
+#![no_std]
+#![no_main]
+
+use task_net_api::*;
+use userlib::*;
+
+task_slot!(NET, net);
+
+const SOCKET: SocketName = SocketName::tcpecho;
+
+#[export_name = "main"]
+fn main() -> ! {
+    let net = Net::from(NET.get_task_id());
+    let mut connection = None;
+
+    loop {
+        if connection.is_none() {
+            // Listening fails while the previous connection is still
+            // closing; we'll be notified when its state changes.
+            match net.tcp_listen(SOCKET) {
+                Ok(()) | Err(TcpError::InvalidState) => (),
+                Err(_) => panic!(),
+            }
+            match net.tcp_accept(SOCKET) {
+                Ok(c) => connection = Some(c),
+                Err(TcpError::WouldBlock) => (),
+                Err(_) => panic!(),
+            }
+        }
+
+        if let Some(c) = connection {
+            if !echo(&net, c) {
+                connection = None;
+                continue;
+            }
+        }
+
+        // Wait for a connection, data to read, or room to write.
+        sys_recv_closed(&mut [], 1, TaskId::KERNEL).unwrap();
+    }
+}
+
+/// Echoes everything that's been received on `c`, returning `false` once the
+/// connection has been closed.
+fn echo(net: &Net, c: TcpMetadata) -> bool {
+    // Tiiiiiny payload buffer
+    let mut buf = [0u8; 64];
+    loop {
+        let n = match net.tcp_read(SOCKET, c, &mut buf) {
+            Ok(n) => n as usize,
+            Err(TcpError::WouldBlock) => return true,
+            Err(TcpError::Closed) => {
+                // The remote end is done sending, so we're done too.
+                let _ = net.tcp_close(SOCKET, c);
+                return false;
+            }
+            Err(TcpError::NotConnected) => return false,
+            Err(_) => panic!(),
+        };
+
+        let mut sent = 0;
+        while sent < n {
+            match net.tcp_write(SOCKET, c, &buf[sent..n]) {
+                Ok(m) => sent += m as usize,
+                Err(TcpError::WouldBlock) => {
+                    // Our outgoing buffer is full; wait for space.
+                    sys_recv_closed(&mut [], 1, TaskId::KERNEL).unwrap();
+                }
+                Err(TcpError::Closed | TcpError::NotConnected) => return false,
+                Err(_) => panic!(),
+            }
+        }
+        TCP_ECHO_COUNT
+            .fetch_add(n as u32, core::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+static TCP_ECHO_COUNT: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(0);
@@ -65,6 +65,7 @@ enum Trace {
     PowerModeChanged(PowerBitmask),
     PowerDownFailed(SeqError),
     ControlError(ThermalError),
+    SensorAlarm,
 }
 ringbuf!(Trace, 32, Trace::None);
 
@@ -79,6 +80,12 @@ struct ServerImpl<'a> {
 const TIMER_MASK: u32 = 1 << 0;
 const TIMER_INTERVAL: u64 = 1000;
 
+/// Posted by the `sensor` task when a sensor enters or leaves an alarm state.
+/// This is only logged: the control loop already sees every reading that we
+/// post, and must only run on `TIMER_MASK`, since the PID step assumes a fixed
+/// interval between runs.
+const ALARM_MASK: u32 = 1 << 1;
+
 impl<'a> ServerImpl<'a> {
     /// Configures the control loop to run in manual mode, loading the given
     /// PWM value immediately to all fans.
@@ -268,11 +275,14 @@ impl<'a> idl::InOrderThermalImpl for ServerImpl<'a> {
 
 impl<'a> NotificationHandler for ServerImpl<'a> {
     fn current_notification_mask(&self) -> u32 {
-        TIMER_MASK
+        TIMER_MASK | ALARM_MASK
     }
 
-    fn handle_notification(&mut self, _bits: u32) {
+    fn handle_notification(&mut self, bits: u32) {
         let now = sys_get_timer().now;
+        if bits & ALARM_MASK != 0 {
+            ringbuf_entry!(Trace::SensorAlarm);
+        }
         if now >= self.deadline {
             match self.mode {
                 ThermalMode::Auto => {
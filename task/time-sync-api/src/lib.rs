@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the time sync task, which maps kernel timestamps to UTC.
+
+#![no_std]
+
+use derive_idol_err::IdolError;
+use serde::{Deserialize, Serialize};
+use userlib::*;
+
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+pub enum TimeSyncError {
+    /// We haven't heard from the time server since boot
+    NotSynced = 1,
+
+    /// The timestamp maps to a time before the Unix epoch, or too far in the
+    /// future to represent
+    OutOfRange = 2,
+}
+
+/// Result of `get_status`
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Kernel timestamp of the most recent successful sync, or `None` if
+    /// we've never synced
+    pub last_sync: Option<u64>,
+    /// Milliseconds to add to a kernel timestamp to get milliseconds since
+    /// the Unix epoch, as of the most recent sync
+    pub offset: i64,
+    /// Round-trip delay of the most recent successful request, in
+    /// milliseconds
+    pub round_trip: u32,
+    /// Stratum reported by the server in its most recent reply
+    pub stratum: u8,
+    /// Number of successful syncs since boot
+    pub syncs: u32,
+    /// Number of requests which timed out or got an unusable reply
+    pub failures: u32,
+}
+
+impl TimeSync {
+    /// Returns the current time, in milliseconds since the Unix epoch.
+    pub fn now(&self) -> Result<u64, TimeSyncError> {
+        self.ticks_to_utc(sys_get_timer().now)
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -0,0 +1,105 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::io::Write;
+use std::net::Ipv6Addr;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TaskConfig {
+    /// Address of the SNTP server
+    #[serde(default = "all_ntp_servers")]
+    server: Ipv6Addr,
+
+    /// Time between successful syncs, in milliseconds
+    #[serde(default = "default_poll_interval")]
+    poll_interval_ms: u64,
+
+    /// VLAN on which to reach the server, if any
+    vid: Option<u16>,
+}
+
+impl Default for TaskConfig {
+    fn default() -> Self {
+        Self {
+            server: all_ntp_servers(),
+            poll_interval_ms: default_poll_interval(),
+            vid: None,
+        }
+    }
+}
+
+/// Link-local multicast group of NTP servers (RFC 4330 anycast mode)
+fn all_ntp_servers() -> Ipv6Addr {
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x101)
+}
+
+fn default_poll_interval() -> u64 {
+    64_000
+}
+
+fn main() -> Result<()> {
+    idol::server::build_server_support(
+        "../../idl/time-sync.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )
+    .map_err(|e| anyhow!("idol error: {e}"))?;
+
+    let config: TaskConfig =
+        build_util::task_maybe_config()?.unwrap_or_default();
+    if config.poll_interval_ms == 0 {
+        bail!("poll-interval-ms must be non-zero");
+    }
+
+    // The VID must be one on which our socket is bound, or the net task
+    // would reject our sends at runtime. Without one, we use the first.
+    let net = build_net::load_net_config().map_err(|e| anyhow!("{e}"))?;
+    let socket = net
+        .sockets
+        .get("time_sync")
+        .ok_or_else(|| anyhow!("no `time_sync` socket in config.net"))?;
+    let vid = match net.vlan {
+        None if config.vid.is_some() => {
+            bail!("vid is set, but the net task isn't using VLANs")
+        }
+        None => None,
+        Some(v) => {
+            let vids: Vec<usize> = socket
+                .vlan_indices(Some(v))
+                .into_iter()
+                .map(|i| v.start + i)
+                .collect();
+            match config.vid {
+                Some(vid) if !vids.contains(&usize::from(vid)) => {
+                    bail!("the time_sync socket isn't bound on vid {vid:#x}")
+                }
+                Some(vid) => Some(vid),
+                None => Some(vids[0] as u16),
+            }
+        }
+    };
+
+    let out_dir = build_util::out_dir();
+    let mut file = std::fs::File::create(out_dir.join("task_config.rs"))?;
+    writeln!(
+        file,
+        "pub(crate) const SERVER: Ipv6Address = Ipv6Address({:?});",
+        config.server.octets()
+    )?;
+    writeln!(
+        file,
+        "pub(crate) const POLL_INTERVAL: u64 = {};",
+        config.poll_interval_ms
+    )?;
+    writeln!(
+        file,
+        "#[allow(dead_code)]\npub(crate) const VID: Option<u16> = {:?};",
+        vid
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,290 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Wall-clock time service.
+//!
+//! This task periodically asks an SNTP server (RFC 4330) for the time, and
+//! uses the reply to work out the offset between kernel timestamps and
+//! milliseconds since the Unix epoch. Other tasks can then convert kernel
+//! timestamps to UTC with `ticks_to_utc`.
+//!
+//! By default, requests are sent to the link-local all-NTP-servers group, and
+//! the first valid reply is used (anycast mode); a specific server can be set
+//! with `server` in the task config. Once synced, the offset is kept even if
+//! later requests fail, so timestamps continue to be available (albeit
+//! drifting with the local clock) if the server goes away.
+
+#![no_std]
+#![no_main]
+
+use idol_runtime::{NotificationHandler, RequestError};
+use ringbuf::*;
+use task_net_api::*;
+use task_time_sync_api::{SyncStatus, TimeSyncError};
+use userlib::*;
+
+task_slot!(NET, net);
+
+include!(concat!(env!("OUT_DIR"), "/task_config.rs"));
+
+// Must match app.toml!
+const NET_MASK: u32 = 1 << 0;
+
+// Must not conflict with the net notification above!
+const TIMER_MASK: u32 = 1 << 1;
+
+const SOCKET: SocketName = SocketName::time_sync;
+
+/// Port on which SNTP servers listen
+const NTP_PORT: u16 = 123;
+
+/// Length of an SNTP message, without extension fields or authentication
+const NTP_LEN: usize = 48;
+
+/// Time to wait for a reply before giving up on a request, in milliseconds
+const TIMEOUT: u64 = 1000;
+
+/// Time to wait after a failed request before trying again, in milliseconds
+const RETRY_INTERVAL: u64 = 4000;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Request(u64),
+    Synced {
+        offset: i64,
+        round_trip: u32,
+        stratum: u8,
+    },
+    Rejected,
+    Timeout,
+    SendError(SendError),
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+struct ServerImpl {
+    net: Net,
+    /// Kernel timestamp at which the outstanding request was sent, if any
+    pending: Option<u64>,
+    /// Time of the next request or, if one is outstanding, of its timeout
+    deadline: u64,
+    status: SyncStatus,
+}
+
+impl ServerImpl {
+    /// Sends a request to the server, scheduling its timeout or (if it
+    /// couldn't be sent) a retry.
+    fn send_request(&mut self, now: u64) {
+        // The transmit timestamp is echoed back to us in the reply, so we use
+        // the send time to match replies to requests; the server doesn't
+        // otherwise look at it.
+        let mut out = [0u8; NTP_LEN];
+        out[0] = (4 << 3) | 3; // no leap indicator, version 4, client mode
+        out[40..48].copy_from_slice(&now.to_be_bytes());
+
+        let meta = UdpMetadata {
+            addr: Address::Ipv6(SERVER),
+            port: NTP_PORT,
+            size: out.len() as u32,
+            #[cfg(feature = "vlan")]
+            vid: VID.unwrap_or(VLAN_RANGE.start),
+        };
+        match self.net.send_packet(SOCKET, meta, &out) {
+            Ok(()) => {
+                ringbuf_entry!(Trace::Request(now));
+                self.pending = Some(now);
+                self.deadline = now + TIMEOUT;
+            }
+            Err(e @ SendError::QueueFull) => {
+                ringbuf_entry!(Trace::SendError(e));
+                self.fail(now);
+            }
+            Err(SendError::NotYours) => panic!(),
+            Err(SendError::InvalidVLan) => panic!(),
+            Err(SendError::Other) => panic!(),
+        }
+    }
+
+    /// Records a failed request, and schedules a retry.
+    fn fail(&mut self, now: u64) {
+        self.pending = None;
+        self.status.failures = self.status.failures.wrapping_add(1);
+        self.deadline = now + RETRY_INTERVAL;
+    }
+
+    /// Drains our socket, syncing to the first valid reply to the
+    /// outstanding request.
+    fn recv_replies(&mut self) {
+        loop {
+            // Leave room for extension fields and authentication, which we
+            // ignore.
+            let mut buf = [0u8; 128];
+            match self.net.recv_packet(
+                SOCKET,
+                LargePayloadBehavior::Discard,
+                &mut buf,
+            ) {
+                Ok(meta) => {
+                    let now = sys_get_timer().now;
+                    let reply = &buf[..meta.size as usize];
+                    if meta.port != NTP_PORT || !self.handle_reply(reply, now) {
+                        ringbuf_entry!(Trace::Rejected);
+                    }
+                }
+                Err(RecvError::QueueEmpty) => break,
+                Err(RecvError::NotYours) => panic!(),
+                Err(RecvError::Other) => panic!(),
+            }
+        }
+    }
+
+    /// Checks a reply (as described in RFC 4330 section 5) and syncs to it,
+    /// returning `false` if it was rejected.
+    fn handle_reply(&mut self, reply: &[u8], now: u64) -> bool {
+        let Some(sent) = self.pending else { return false };
+        if reply.len() < NTP_LEN {
+            return false;
+        }
+
+        let leap = reply[0] >> 6;
+        let mode = reply[0] & 0x7;
+        let stratum = reply[1];
+        let originate = &reply[24..32];
+        let receive = ntp_timestamp(&reply[32..40]);
+        let transmit = ntp_timestamp(&reply[40..48]);
+
+        // Leap indicator 3 means the server's clock isn't synchronized, and
+        // stratum 0 is a "kiss-o'-death" telling us to back off.
+        if leap == 3
+            || mode != 4
+            || !(1..=15).contains(&stratum)
+            || originate != sent.to_be_bytes()
+            || transmit == 0
+        {
+            return false;
+        }
+
+        // Kernel timestamps are in milliseconds, so we work in milliseconds
+        // throughout: T1 and T4 are kernel timestamps, and T2 and T3 are
+        // milliseconds since the Unix epoch.
+        let t1 = sent as i64;
+        let t2 = ntp_to_unix_ms(receive) as i64;
+        let t3 = ntp_to_unix_ms(transmit) as i64;
+        let t4 = now as i64;
+        let offset = ((t2 - t1) + (t3 - t4)) / 2;
+        let round_trip = ((t4 - t1) - (t3 - t2)).max(0) as u32;
+
+        ringbuf_entry!(Trace::Synced {
+            offset,
+            round_trip,
+            stratum
+        });
+        self.status = SyncStatus {
+            last_sync: Some(now),
+            offset,
+            round_trip,
+            stratum,
+            syncs: self.status.syncs.wrapping_add(1),
+            failures: self.status.failures,
+        };
+        self.pending = None;
+        self.deadline = now + POLL_INTERVAL;
+        true
+    }
+}
+
+/// Reads a big-endian 64-bit NTP timestamp: seconds since 1900 in the upper
+/// 32 bits, and the fraction of a second in the lower 32 bits.
+fn ntp_timestamp(b: &[u8]) -> u64 {
+    let mut out = [0; 8];
+    out.copy_from_slice(b);
+    u64::from_be_bytes(out)
+}
+
+/// Converts an NTP timestamp to milliseconds since the Unix epoch. Following
+/// RFC 4330 section 3, timestamps with the top bit clear are taken to be in
+/// the era starting in 2036.
+fn ntp_to_unix_ms(t: u64) -> u64 {
+    let mut secs = t >> 32;
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+    let millis = ((t & 0xffff_ffff) * 1000) >> 32;
+    secs.saturating_sub(NTP_UNIX_OFFSET) * 1000 + millis
+}
+
+impl idl::InOrderTimeSyncImpl for ServerImpl {
+    fn ticks_to_utc(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        ticks: u64,
+    ) -> Result<u64, RequestError<TimeSyncError>> {
+        if self.status.last_sync.is_none() {
+            return Err(TimeSyncError::NotSynced.into());
+        }
+        ticks
+            .checked_add_signed(self.status.offset)
+            .ok_or_else(|| TimeSyncError::OutOfRange.into())
+    }
+
+    fn get_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<SyncStatus, RequestError<core::convert::Infallible>> {
+        Ok(self.status)
+    }
+}
+
+impl NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        NET_MASK | TIMER_MASK
+    }
+
+    fn handle_notification(&mut self, bits: u32) {
+        if bits & NET_MASK != 0 {
+            self.recv_replies();
+        }
+
+        let now = sys_get_timer().now;
+        if now >= self.deadline {
+            if self.pending.is_some() {
+                ringbuf_entry!(Trace::Timeout);
+                self.fail(now);
+            } else {
+                self.send_request(now);
+            }
+        }
+        sys_set_timer(Some(self.deadline), TIMER_MASK);
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let deadline = sys_get_timer().now;
+
+    // This will put our timer in the past, and should immediately kick us.
+    sys_set_timer(Some(deadline), TIMER_MASK);
+
+    let mut server = ServerImpl {
+        net: Net::from(NET.get_task_id()),
+        pending: None,
+        deadline,
+        status: SyncStatus::default(),
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+    loop {
+        idol_runtime::dispatch_n(&mut buffer, &mut server);
+    }
+}
+
+mod idl {
+    use super::{SyncStatus, TimeSyncError};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
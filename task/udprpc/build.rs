@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::path::Path;
+
+fn main() -> Result<()> {
+    let config: build_rpc::RpcConfig =
+        build_util::task_maybe_config()?.unwrap_or_default();
+
+    let out_dir = build_util::out_dir();
+    let mut file = std::fs::File::create(out_dir.join("rpc_config.rs"))?;
+
+    let Some(allow) = config.allowlist()? else {
+        writeln!(
+            file,
+            "pub(crate) const ALLOWED_OPS: Option<&[AllowedOp]> = None;"
+        )?;
+        writeln!(file, "pub(crate) const MAX_LEASES: usize = 0;")?;
+        return Ok(());
+    };
+
+    let idl_dir = Path::new("../../idl");
+    for a in allow {
+        let path = idl_dir.join(format!("{}.idol", a.interface));
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    let interfaces = build_rpc::resolve(allow, idl_dir)?;
+
+    let task_ids = build_util::task_ids();
+    let mut max_leases = 0;
+    writeln!(
+        file,
+        "pub(crate) const ALLOWED_OPS: Option<&[AllowedOp]> = Some(&["
+    )?;
+    for iface in &interfaces {
+        let task = task_ids.get(&iface.task).ok_or_else(|| {
+            anyhow!("unknown task {} in udprpc allowlist", iface.task)
+        })?;
+        for op in &iface.ops {
+            writeln!(file, "    // {}::{}", iface.name, op.name)?;
+            writeln!(
+                file,
+                "    AllowedOp {{ task: {task}, op: {}, leases: &[",
+                op.code
+            )?;
+            for lease in op.leases.values() {
+                let max_len = lease
+                    .max_len
+                    .map_or(u16::MAX, |n| n.try_into().unwrap_or(u16::MAX));
+                writeln!(
+                    file,
+                    "        LeaseConfig {{ read: {}, write: {}, \
+                     max_len: {max_len} }},",
+                    lease.read, lease.write,
+                )?;
+            }
+            writeln!(file, "    ] }},")?;
+            max_leases = max_leases.max(op.leases.len());
+        }
+    }
+    writeln!(file, "]);")?;
+    writeln!(file, "pub(crate) const MAX_LEASES: usize = {max_leases};")?;
+
+    Ok(())
+}
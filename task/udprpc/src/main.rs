@@ -9,10 +9,58 @@ use task_net_api::*;
 use userlib::*;
 use zerocopy::{AsBytes, FromBytes, LittleEndian, U16, U64};
 
+#[cfg(all(
+    feature = "auth",
+    not(any(feature = "vpd-key", feature = "dice-key"))
+))]
+compile_error!("the auth feature needs either vpd-key or dice-key");
+
+#[cfg(all(feature = "vpd-key", feature = "dice-key"))]
+compile_error!("vpd-key and dice-key are mutually exclusive");
+
 task_slot!(NET, net);
 
+#[cfg(feature = "vpd-key")]
+task_slot!(I2C, i2c_driver);
+
+#[cfg(feature = "dice-key")]
+task_slot!(SPROT, sprot);
+
+#[cfg(feature = "auth")]
+task_slot!(RNG, rng_driver);
+
+include!(concat!(env!("OUT_DIR"), "/rpc_config.rs"));
+
+/// An op which may be called over the network
+struct AllowedOp {
+    /// Index of the task serving the op
+    task: u16,
+    op: u16,
+    leases: &'static [LeaseConfig],
+}
+
+struct LeaseConfig {
+    read: bool,
+    write: bool,
+    max_len: u16,
+}
+
+/// Looks up the leases taken by an op, returning `None` if the op may not
+/// be called. With `allow-any`, there's no allowlist, and any op may be
+/// called without leases.
+fn allowed_leases(task: TaskId, op: u16) -> Option<&'static [LeaseConfig]> {
+    let Some(allowed) = ALLOWED_OPS else {
+        return Some(&[]);
+    };
+    allowed
+        .iter()
+        .find(|a| usize::from(a.task) == task.index() && a.op == op)
+        .map(|a| a.leases)
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
+#[cfg_attr(not(feature = "auth"), allow(dead_code))]
 enum RpcReply {
     Ok,
     /// The RPC packet was too short to include the complete header
@@ -27,6 +75,61 @@ enum RpcReply {
     NReplyMismatch,
     /// The output would overflow `tx_data_buf`
     NReplyOverflow,
+    /// The packet's authentication tag is wrong (only with `auth`)
+    BadMac,
+    /// The packet's epoch is not the current one, or its counter is not
+    /// greater than that of the last packet we accepted (only with `auth`)
+    BadNonce,
+    /// We have no key with which to authenticate packets (only with `auth`)
+    NoKey,
+    /// The op is not in the allowlist
+    NotAllowed,
+    /// A lease is longer than the op's `max_len` for it
+    BadLease,
+}
+
+#[cfg(feature = "auth")]
+impl From<udprpc_auth::Reject> for RpcReply {
+    fn from(r: udprpc_auth::Reject) -> Self {
+        use udprpc_auth::Reject;
+        match r {
+            Reject::TooShort => RpcReply::TooShort,
+            Reject::BadMac => RpcReply::BadMac,
+            Reject::BadNonce => RpcReply::BadNonce,
+            Reject::NoKey => RpcReply::NoKey,
+        }
+    }
+}
+
+/// Minimum and maximum intervals between attempts to read the key, in
+/// milliseconds. The key may not be available when we start (e.g. if the RoT
+/// isn't up yet), so we try again when a request arrives without one,
+/// backing off each time it fails.
+#[cfg(feature = "auth")]
+const KEY_RETRY_MIN: u64 = 100;
+#[cfg(feature = "auth")]
+const KEY_RETRY_MAX: u64 = 10_000;
+
+/// Reads the per-device key from the `RPCK` tag of the local VPD.
+#[cfg(feature = "vpd-key")]
+fn read_key() -> Option<[u8; 32]> {
+    drv_local_vpd::read_config(I2C.get_task_id(), *b"RPCK").ok()
+}
+
+/// Asks the RoT for the per-device key, which stage0 derives from the DICE
+/// CDI.
+#[cfg(feature = "dice-key")]
+fn read_key() -> Option<[u8; 32]> {
+    use drv_sprot_api::{MsgType, SpRot};
+
+    let mut key = [0u8; 32];
+    let sprot = SpRot::from(SPROT.get_task_id());
+    let r = sprot.send_recv(MsgType::UdpRpcKeyReq, &[], &mut key).ok()?;
+    if usize::from(r.length) == key.len() {
+        Some(key)
+    } else {
+        None
+    }
 }
 
 /// Header for an RPC request
@@ -54,14 +157,19 @@ fn main() -> ! {
     // behavior, but prevents basic user error.
     let image_id = kipc::read_image_id();
 
-    // The output format is dependent on status code.  The first byte is always
-    // a member of `RpcReply` as a `u8`.
-    // - `NBytesMismatch`, `NReplyMismatch`, `NReplyOverflow` return nothing
-    //   else (so the reply is 1 byte)
-    // - `BadImageId` is followed by the *actual* 64-bit image id as a
-    //   little-endian value
-    // - `Ok` is followed by the return code as a 32-bit, little-endian value,
-    //   then by `nreply` bytes of reply.
+    // With authentication, the epoch must be unpredictable, so that requests
+    // from a previous boot can't be replayed.
+    #[cfg(feature = "auth")]
+    let mut auth = {
+        let mut epoch = [0u8; 8];
+        drv_rng_api::Rng::from(RNG.get_task_id())
+            .fill(&mut epoch)
+            .unwrap_lite();
+        udprpc_auth::Auth::new(read_key(), u64::from_le_bytes(epoch))
+    };
+    #[cfg(feature = "auth")]
+    let (mut next_key_try, mut key_retry) = (0, KEY_RETRY_MIN);
+
     loop {
         let mut rx_data_buf = [0u8; 1024];
         let mut tx_data_buf = [0u8; 1024];
@@ -71,78 +179,35 @@ fn main() -> ! {
             &mut rx_data_buf,
         ) {
             Ok(mut meta) => {
-                const HEADER_SIZE: usize = core::mem::size_of::<RpcHeader>();
-                const REPLY_PREFIX_SIZE: usize = 5;
-
-                // We deliberately assign to `r` here then manipulate it;
-                // otherwise, the compiler won't include `RpcReply` in DWARF
-                // data.
-                let (r, nreply) = if (meta.size as usize) < HEADER_SIZE {
-                    (RpcReply::TooShort, 0)
-                } else {
-                    // We can always read the header, since it's raw data
-                    let header =
-                        RpcHeader::read_from(&rx_data_buf[..HEADER_SIZE])
-                            .unwrap();
-
-                    let nbytes = header.nbytes.get() as usize;
-                    let nreply = header.nreply.get() as usize;
-
-                    let r = if image_id != header.image_id.get() {
-                        tx_data_buf[1..9].copy_from_slice(image_id.as_bytes());
-                        RpcReply::BadImageId
-                    } else if meta.size as usize != HEADER_SIZE + nbytes {
-                        RpcReply::NBytesMismatch
-                    } else if nreply + REPLY_PREFIX_SIZE > tx_data_buf.len() {
-                        RpcReply::NReplyOverflow
-                    } else {
-                        // This is the happy path: unpack the data and execute
-                        // the sys_send which actually calls the target.
-                        let rx_data = &rx_data_buf[HEADER_SIZE..][..nbytes];
-
-                        // The returned data is stored after the reply prefix,
-                        // which consists of a one-byte `RpcReply` then a
-                        // u32 return code from the `sys_send` call.
-                        let tx_data =
-                            &mut tx_data_buf[REPLY_PREFIX_SIZE..][..nreply];
-                        let (rc, len) = sys_send(
-                            TaskId(header.task.get()),
-                            header.op.get(),
-                            rx_data,
-                            tx_data,
-                            &[],
-                        );
-                        if rc == 0 && len != nreply {
-                            RpcReply::NReplyMismatch
-                        } else {
-                            // Store the return code
-                            tx_data_buf[1..5]
-                                .copy_from_slice(&rc.to_be_bytes());
-                            RpcReply::Ok
+                let rx_data = &mut rx_data_buf[..meta.size as usize];
+
+                #[cfg(not(feature = "auth"))]
+                let n = dispatch(rx_data, &mut tx_data_buf, image_id);
+
+                #[cfg(feature = "auth")]
+                if !auth.has_key() {
+                    let now = sys_get_timer().now;
+                    if now >= next_key_try {
+                        match read_key() {
+                            Some(key) => auth.set_key(key),
+                            None => {
+                                next_key_try = now + key_retry;
+                                key_retry = (key_retry * 2).min(KEY_RETRY_MAX);
+                            }
                         }
-                    };
-                    (r, nreply)
-                };
-
-                // Store the `RpcReply` return code and return size
-                tx_data_buf[0] = r as u8;
-                meta.size = match r {
-                    RpcReply::TooShort
-                    | RpcReply::NBytesMismatch
-                    | RpcReply::NReplyOverflow
-                    | RpcReply::NReplyMismatch => 1,
-                    RpcReply::BadImageId => {
-                        (1 + core::mem::size_of_val(&image_id)) as u32
                     }
-                    RpcReply::Ok => (nreply + REPLY_PREFIX_SIZE) as u32,
-                };
-
-                net.send_packet(
-                    SOCKET,
-                    meta,
-                    &tx_data_buf[0..(meta.size as usize)],
-                )
-                .unwrap();
+                }
+
+                #[cfg(feature = "auth")]
+                let n = auth.handle(
+                    rx_data,
+                    &mut tx_data_buf,
+                    |rx, tx| dispatch(rx, tx, image_id),
+                    |r| RpcReply::from(r) as u8,
+                );
+
+                meta.size = n as u32;
+                net.send_packet(SOCKET, meta, &tx_data_buf[..n]).unwrap();
             }
             Err(RecvError::QueueEmpty) => {
                 // Our incoming queue is empty. Wait for more packets.
@@ -153,3 +218,155 @@ fn main() -> ! {
         // Try again.
     }
 }
+
+const HEADER_SIZE: usize = core::mem::size_of::<RpcHeader>();
+const REPLY_PREFIX_SIZE: usize = 5;
+
+/// Executes the request in `rx_data_buf`, writing the reply to `tx_data_buf`
+/// and returning its length.
+///
+/// The request is an `RpcHeader`, then the length of each of the op's
+/// leases (if any) as a 16-bit little-endian value, then `nbytes` of
+/// arguments, then the contents of each lease which the server reads.
+///
+/// The output format is dependent on status code.  The first byte is always
+/// a member of `RpcReply` as a `u8`.
+/// - `NBytesMismatch`, `NReplyMismatch`, `NReplyOverflow`, `NotAllowed`,
+///   and `BadLease` return nothing else (so the reply is 1 byte)
+/// - `BadImageId` is followed by the *actual* 64-bit image id as a
+///   little-endian value
+/// - `Ok` is followed by the return code as a 32-bit, little-endian value,
+///   then by `nreply` bytes of reply, then by the contents of each lease
+///   which the server writes.
+fn dispatch(
+    rx_data_buf: &[u8],
+    tx_data_buf: &mut [u8],
+    image_id: u64,
+) -> usize {
+    // We deliberately assign to `r` here then manipulate it; otherwise, the
+    // compiler won't include `RpcReply` in DWARF data.
+    let (r, n) = if rx_data_buf.len() < HEADER_SIZE {
+        (RpcReply::TooShort, 0)
+    } else {
+        // We can always read the header, since it's raw data
+        let header = RpcHeader::read_from(&rx_data_buf[..HEADER_SIZE]).unwrap();
+        let task = TaskId(header.task.get());
+
+        if image_id != header.image_id.get() {
+            tx_data_buf[1..9].copy_from_slice(image_id.as_bytes());
+            (RpcReply::BadImageId, 0)
+        } else if let Some(leases) = allowed_leases(task, header.op.get()) {
+            let body = &rx_data_buf[HEADER_SIZE..];
+            match call(&header, leases, body, tx_data_buf) {
+                Ok((rc, n)) => {
+                    // Store the return code
+                    tx_data_buf[1..5].copy_from_slice(&rc.to_be_bytes());
+                    (RpcReply::Ok, n)
+                }
+                Err(r) => (r, 0),
+            }
+        } else {
+            (RpcReply::NotAllowed, 0)
+        }
+    };
+
+    // Store the `RpcReply` return code and return size
+    tx_data_buf[0] = r as u8;
+    match r {
+        RpcReply::TooShort
+        | RpcReply::NBytesMismatch
+        | RpcReply::NReplyOverflow
+        | RpcReply::NReplyMismatch
+        | RpcReply::BadMac
+        | RpcReply::BadNonce
+        | RpcReply::NoKey
+        | RpcReply::NotAllowed
+        | RpcReply::BadLease => 1,
+        RpcReply::BadImageId => 1 + core::mem::size_of_val(&image_id),
+        RpcReply::Ok => n + REPLY_PREFIX_SIZE,
+    }
+}
+
+/// Unpacks the arguments and leases of a request from `body` (which follows
+/// the header), and executes the `sys_send` which actually calls the target.
+/// Returns the return code and the number of bytes of reply and lease
+/// contents, which are written to `tx_data_buf` after the reply prefix.
+fn call(
+    header: &RpcHeader,
+    leases: &[LeaseConfig],
+    body: &[u8],
+    tx_data_buf: &mut [u8],
+) -> Result<(u32, usize), RpcReply> {
+    let nbytes = header.nbytes.get() as usize;
+    let nreply = header.nreply.get() as usize;
+
+    if body.len() < leases.len() * 2 {
+        return Err(RpcReply::NBytesMismatch);
+    }
+    let (lengths, body) = body.split_at(leases.len() * 2);
+    let lengths = lengths
+        .chunks_exact(2)
+        .map(|b| usize::from(u16::from_le_bytes([b[0], b[1]])));
+
+    let mut nread = 0;
+    let mut nwrite = 0;
+    for (lease, len) in leases.iter().zip(lengths.clone()) {
+        if len > usize::from(lease.max_len) {
+            return Err(RpcReply::BadLease);
+        }
+        if lease.read {
+            nread += len;
+        }
+        if lease.write {
+            nwrite += len;
+        }
+    }
+    if body.len() != nbytes + nread {
+        return Err(RpcReply::NBytesMismatch);
+    }
+    if REPLY_PREFIX_SIZE + nreply + nwrite > tx_data_buf.len() {
+        return Err(RpcReply::NReplyOverflow);
+    }
+
+    // The returned data is stored after the reply prefix, which consists of a
+    // one-byte `RpcReply` then a u32 return code from the `sys_send` call.
+    // Leases which the server writes are laid out directly after it, so that
+    // they're already in place for the reply.
+    let (rx_data, mut read_data) = body.split_at(nbytes);
+    let tx_data = &mut tx_data_buf[REPLY_PREFIX_SIZE..][..nreply + nwrite];
+    let (tx_data, mut write_space) = tx_data.split_at_mut(nreply);
+
+    let mut lease_buf = [(); MAX_LEASES].map(|_| Lease::read_only(&[]));
+    for ((slot, lease), len) in lease_buf.iter_mut().zip(leases).zip(lengths) {
+        *slot = if lease.write {
+            let (buf, rest) =
+                core::mem::take(&mut write_space).split_at_mut(len);
+            write_space = rest;
+            if lease.read {
+                let (data, rest) = read_data.split_at(len);
+                read_data = rest;
+                buf.copy_from_slice(data);
+                Lease::read_write(buf)
+            } else {
+                Lease::write_only(buf)
+            }
+        } else {
+            let (data, rest) = read_data.split_at(len);
+            read_data = rest;
+            Lease::read_only(data)
+        };
+    }
+
+    let (rc, len) = sys_send(
+        TaskId(header.task.get()),
+        header.op.get(),
+        rx_data,
+        tx_data,
+        &lease_buf[..leases.len()],
+    );
+    if rc == 0 && len != nreply {
+        Err(RpcReply::NReplyMismatch)
+    } else {
+        Ok((rc, nreply + nwrite))
+    }
+}
@@ -61,9 +61,10 @@
 //!
 //! # Output
 //!
-//! Output is produced on ITM stimulus port 8. Output is in a line-oriented
-//! human-readable format modeled after report formats like TAP, but avoiding
-//! some issues.
+//! Output is produced on ITM stimulus port 8, or through semihosting on ARMv6-M
+//! and when built with the `semihosting` feature (e.g. for QEMU). Output is in
+//! a line-oriented human-readable format modeled after report formats like
+//! TAP, but avoiding some issues.
 //!
 //! A test report consists of the following lines:
 //!
@@ -93,7 +94,7 @@ use zerocopy::AsBytes;
 use armv6m_atomic_hack::*;
 
 cfg_if::cfg_if! {
-    if #[cfg(armv6m)] {
+    if #[cfg(any(armv6m, feature = "semihosting"))] {
         /// Helper macro for producing output by semihosting :-(
         macro_rules! test_output {
             ($s:expr) => {